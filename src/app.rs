@@ -2,39 +2,106 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
-use std::{collections::VecDeque, io::Stdout, path::PathBuf, sync::mpsc, thread, time::Duration};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::Stdout,
+    path::PathBuf,
+    sync::{Arc, mpsc},
+    thread,
+    time::Duration,
+};
 
 use color_eyre::eyre::Result;
 use crossterm::event::{self, Event, KeyCode, KeyModifiers};
-use musicbrainz_rs::entity::release::Release;
 use ratatui::{Terminal, prelude::CrosstermBackend};
+use tokio::{
+    runtime::{Handle, Runtime},
+    sync::Semaphore,
+};
 
 use crate::{
+    config::Config,
+    discogs::client::Client as DiscogsClient,
+    duplicates::{self, DuplicateReport},
+    genres::GenreTree,
+    matching::{self, MatchScore, Weights},
     models::{AlbumCluster, AudioFile},
     musicbrainz::{
         client::Client as MbClient,
-        search::{SearchMessage, search_for_cluster},
+        coverart::{
+            CachedImage, Client as CoverArtClient, CoverArtMessage, Image, download_cover_art,
+            list_cover_art,
+        },
+        source::SOURCE_NAME as MUSICBRAINZ,
+        sync::{ReleaseDiff, SyncMessage, sync_cluster},
+    },
+    paths::escape_path,
+    scanner::{self, ScanOptions, ScanProgress},
+    search::{
+        SearchCursor, SearchMessage, load_more, lookup_reference, lookup_release_details,
+        search_for_cluster,
     },
-    scanner::{self, ScanProgress},
+    source::{AlbumCandidate, MetadataSource},
     ui,
 };
 
 const TICK_RATE: Duration = Duration::from_millis(100);
 
+/// Number of top search results whose full releases are fetched in the
+/// background, before they are highlighted.
+const PREFETCH_CANDIDATES: usize = 3;
+
+/// Number of clusters searched at the same time.  They share the source's
+/// rate limit, so this mostly hides the latency of each request.
+const SEARCH_WORKERS: usize = 4;
+
 struct PendingCluster {
     cluster: AlbumCluster,
-    results: Vec<Release>,
+    results: Vec<AlbumCandidate>,
+    cursors: Vec<SearchCursor>,
 }
 
 pub struct App {
     pending_clusters: VecDeque<PendingCluster>,
+    scan_path: PathBuf,
+    scan_options: ScanOptions,
+    runtime: Runtime,
+    /// Where releases are searched for and looked up, in the order their
+    /// candidates are listed.  Shared by every search and lookup task, so
+    /// the tasks are throttled together.
+    sources: Vec<Arc<dyn MetadataSource>>,
+    /// Shares its rate limiter with the MusicBrainz source.
+    mb_client: MbClient,
+    /// Sorts the genres proposed for a release out of its tags.
+    genres: GenreTree,
+    genre_count: usize,
+    /// How much each field counts when scoring candidates.
+    weights: Weights,
+    /// Shares its rate limiter with the MusicBrainz source.  `None` when
+    /// working offline.
+    cover_art: Option<CoverArtClient>,
     state: AppState,
     should_quit: bool,
+    /// The scanned clusters and the one selected, set aside while
+    /// auto-tagging so that repairs and the selection survive going back to
+    /// the list.
+    cluster_list: Option<(Vec<AlbumCluster>, usize)>,
+    search_queue_tx: Option<mpsc::Sender<AlbumCluster>>,
     scan_rx: Option<mpsc::Receiver<ScanMessage>>,
     scan_progress_rx: Option<mpsc::Receiver<ScanProgress>>,
+    search_tx: Option<mpsc::Sender<SearchMessage>>,
     search_rx: Option<mpsc::Receiver<SearchMessage>>,
+    /// The cluster whose retry is shown as the auto-tagging status, so the
+    /// status can be cleared once its search moves on.
+    retrying: Option<AlbumCluster>,
+    sync_tx: mpsc::Sender<SyncMessage>,
+    sync_rx: mpsc::Receiver<SyncMessage>,
+    art_tx: mpsc::Sender<CoverArtMessage>,
+    art_rx: mpsc::Receiver<CoverArtMessage>,
 }
 
+// There is only ever one state, so its size doesn't matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum AppState {
     Scanning {
@@ -45,45 +112,291 @@ pub enum AppState {
     },
     AutoTagging {
         cluster: AlbumCluster,
-        results: Vec<Release>,
+        results: Vec<AlbumCandidate>,
+        /// How well each of the results matches the cluster, in the same
+        /// order.
+        scores: Vec<MatchScore>,
+        /// Index into the rows from [`candidate_rows`].
         selected_idx: usize,
+        /// Whether candidates are collapsed by release group.
+        grouped: bool,
+        /// Release groups whose editions are listed, by ID.
+        expanded: HashSet<String>,
+        /// Queries with further pages of results, most precise first.
+        cursors: Vec<SearchCursor>,
+        /// Whether the next page of results has been requested.
+        loading_more: bool,
+        /// Full releases for the search results, by ID.
+        details: HashMap<String, ReleaseLookup>,
+        /// Text typed so far when entering a release ID or URL.
+        prompt: Option<String>,
+        /// Outcome of the last lookup, shown in place of the help line.
+        status: Option<String>,
+        /// Cover art being chosen from, shown in place of the release.
+        art: Option<ArtPicker>,
+        /// The image picked as the front cover.
+        front_cover: Option<FrontCover>,
     },
     ClusterList {
         clusters: Vec<AlbumCluster>,
         selected_idx: usize,
     },
+    Duplicates {
+        clusters: Vec<AlbumCluster>,
+        report: DuplicateReport,
+        selected_idx: usize,
+        /// The cluster selected in the list, to go back to.
+        list_idx: usize,
+    },
+    /// Clusters tagged with a release MBID, compared with the release as it
+    /// is on MusicBrainz now.
+    Syncing {
+        clusters: Vec<AlbumCluster>,
+        entries: Vec<SyncEntry>,
+        selected_idx: usize,
+        /// The cluster selected in the list, to go back to.
+        list_idx: usize,
+    },
     Error {
         message: String,
     },
 }
 
+/// Progress of looking up the full release for a search result.
+#[derive(Debug)]
+pub enum ReleaseLookup {
+    Pending,
+    Loaded {
+        release: Box<AlbumCandidate>,
+        /// Genres proposed for the album, best first.
+        genres: Vec<String>,
+    },
+    Failed(String),
+}
+
+/// The Cover Art Archive images of a release, to pick a front cover from.
+#[derive(Debug)]
+pub struct ArtPicker {
+    pub release_id: String,
+    /// The release, as "Artist - Title".
+    pub release: String,
+    pub listing: ArtListing,
+    pub selected_idx: usize,
+    /// The last retry of a request that keeps failing, shown in place of
+    /// the help line.
+    pub status: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ArtListing {
+    Pending,
+    Loaded(Vec<ArtEntry>),
+    Failed(String),
+}
+
+#[derive(Debug)]
+pub struct ArtEntry {
+    pub image: Image,
+    pub download: ArtDownload,
+}
+
+#[derive(Debug)]
+pub enum ArtDownload {
+    /// Waiting for the image to be highlighted.
+    NotStarted,
+    Pending,
+    Done(CachedImage),
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct FrontCover {
+    pub release_id: String,
+    pub image_id: String,
+    pub image: CachedImage,
+}
+
+/// A cluster being refreshed from MusicBrainz.
+#[derive(Debug)]
+pub struct SyncEntry {
+    /// Index into the scanned clusters.
+    pub cluster: usize,
+    pub status: SyncStatus,
+}
+
+#[derive(Debug)]
+pub enum SyncStatus {
+    Pending,
+    Done(ReleaseDiff),
+    Failed(String),
+}
+
+/// A line in the list of candidates.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CandidateRow {
+    /// A release group with several candidate editions, given as indices into
+    /// the candidates from the best score to the worst.
+    Group {
+        id: String,
+        editions: Vec<usize>,
+        expanded: bool,
+    },
+    /// A single candidate, either on its own or as an edition listed under
+    /// its release group.
+    Release { idx: usize, nested: bool },
+}
+
+impl CandidateRow {
+    /// The candidate whose details are shown for the row; a group stands for
+    /// its best-ranked edition.
+    pub fn candidate(&self) -> usize {
+        match self {
+            CandidateRow::Group { editions, .. } => editions[0],
+            CandidateRow::Release { idx, .. } => *idx,
+        }
+    }
+}
+
+/// List candidates from the best score to the worst, collapsed into one row
+/// per release group ranked by its best edition, listing the editions of
+/// expanded groups.
+pub fn candidate_rows(
+    results: &[AlbumCandidate],
+    scores: &[MatchScore],
+    grouped: bool,
+    expanded: &HashSet<String>,
+) -> Vec<CandidateRow> {
+    let order = matching::ranked(scores, results.len());
+    if !grouped {
+        return order
+            .into_iter()
+            .map(|idx| CandidateRow::Release { idx, nested: false })
+            .collect();
+    }
+
+    let mut groups: Vec<(&str, Vec<usize>)> = Vec::new();
+    for idx in order {
+        let id = group_id(&results[idx]);
+        match groups.iter_mut().find(|(it, _)| *it == id) {
+            Some((_, editions)) => editions.push(idx),
+            None => groups.push((id, vec![idx])),
+        }
+    }
+
+    let mut rows = Vec::new();
+    for (id, editions) in groups {
+        if editions.len() == 1 {
+            rows.push(CandidateRow::Release {
+                idx: editions[0],
+                nested: false,
+            });
+            continue;
+        }
+        let is_expanded = expanded.contains(id);
+        rows.push(CandidateRow::Group {
+            id: id.to_string(),
+            editions: editions.clone(),
+            expanded: is_expanded,
+        });
+        if is_expanded {
+            rows.extend(
+                editions
+                    .into_iter()
+                    .map(|idx| CandidateRow::Release { idx, nested: true }),
+            );
+        }
+    }
+    rows
+}
+
+/// The row showing a candidate, which is its release group's row when the
+/// group is collapsed.
+fn row_of(rows: &[CandidateRow], idx: usize) -> Option<usize> {
+    rows.iter()
+        .position(|it| matches!(it, CandidateRow::Release { idx: row, .. } if *row == idx))
+        .or_else(|| {
+            rows.iter().position(
+                |it| matches!(it, CandidateRow::Group { editions, .. } if editions.contains(&idx)),
+            )
+        })
+}
+
+/// The release group of a candidate, or the release itself when the search
+/// result didn't say.
+fn group_id(candidate: &AlbumCandidate) -> &str {
+    candidate.group.as_ref().map_or(&candidate.id, |it| &it.id)
+}
+
+/// The source a candidate or query came from.
+fn source_named(
+    sources: &[Arc<dyn MetadataSource>],
+    name: &str,
+) -> Option<Arc<dyn MetadataSource>> {
+    sources.iter().find(|it| it.name() == name).cloned()
+}
+
 enum ScanMessage {
     Complete(Vec<AudioFile>),
     Error(String),
 }
 
-struct AutoTagging {
-    cluster: AlbumCluster,
-    results: Option<Vec<Release>>,
-    selected_idx: usize,
-}
-
 impl App {
     /// Constructs a new instance of [`App`].
-    pub fn new(path: PathBuf) -> Self {
-        Self {
+    pub fn new(config: Config) -> Result<Self> {
+        let mb_client = MbClient::new(&config.musicbrainz)?;
+        let mut sources: Vec<Arc<dyn MetadataSource>> = vec![Arc::new(mb_client.clone())];
+        if let Some(discogs) = &config.discogs {
+            sources.push(Arc::new(DiscogsClient::new(discogs)?));
+        }
+
+        let mut genres = match &config.genre_tree {
+            Some(path) => GenreTree::load(path)?,
+            None => GenreTree::default(),
+        };
+        if let Some(path) = &config.genre_whitelist {
+            genres.load_whitelist(path)?;
+        }
+
+        let cover_art = match &config.cover_art {
+            Some(cover_art) => Some(CoverArtClient::new(cover_art, &mb_client)?),
+            None => None,
+        };
+
+        let (sync_tx, sync_rx) = mpsc::channel();
+        let (art_tx, art_rx) = mpsc::channel();
+
+        Ok(Self {
             pending_clusters: VecDeque::new(),
+            scan_path: config.scan_path.clone(),
+            scan_options: ScanOptions {
+                follow_symlinks: config.follow_symlinks,
+            },
+            runtime: Runtime::new()?,
+            sources,
+            mb_client,
+            genres,
+            genre_count: config.genre_count,
+            weights: config.weights,
+            cover_art,
             state: AppState::Scanning {
-                path,
+                path: config.scan_path,
                 files_found: Vec::new(),
                 current_file: None,
                 is_complete: false,
             },
             should_quit: false,
+            cluster_list: None,
+            search_queue_tx: None,
             scan_rx: None,
             scan_progress_rx: None,
+            search_tx: None,
             search_rx: None,
-        }
+            retrying: None,
+            sync_tx,
+            sync_rx,
+            art_tx,
+            art_rx,
+        })
     }
 
     /// Run the main application loop.
@@ -109,30 +422,46 @@ impl App {
     fn start_scan(&mut self) {
         if let AppState::Scanning { path, .. } = &self.state {
             let scan_path = path.clone();
+            let scan_options = self.scan_options.clone();
+            let sources = self.sources.clone();
+            let runtime = self.runtime.handle().clone();
             let (cluster_tx, cluster_rx) = mpsc::sync_channel(5);
+            // Searches asked for by hand skip the scanner's bounded queue, so
+            // that it closes once the scan is done.
+            let (queue_tx, queue_rx) = mpsc::channel();
+            self.search_queue_tx = Some(queue_tx);
             let (progress_tx, progress_rx) = mpsc::channel();
             let (search_tx, search_rx) = mpsc::channel();
+            let (scan_tx, scan_rx) = mpsc::channel();
 
             thread::spawn(move || {
-                let _ = scanner::scan_directory(&scan_path, cluster_tx, Some(progress_tx));
+                let result = scanner::scan_directory(
+                    &scan_path,
+                    &scan_options,
+                    cluster_tx,
+                    Some(progress_tx),
+                );
+                let message = match result {
+                    Ok(files) => ScanMessage::Complete(files),
+                    Err(e) => ScanMessage::Error(format!("Scan failed: {}", e)),
+                };
+                let _ = scan_tx.send(message);
             });
 
-            thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-
-                rt.block_on(async {
-                    let mut client = MbClient::new();
-
-                    while let Ok(cluster) = cluster_rx.recv() {
-                        let result =
-                            search_for_cluster(&mut client, search_tx.clone(), cluster).await;
-                        if let Err(e) = result {
-                            eprintln!("Search error: {}", e);
-                        }
-                    }
-                })
-            });
+            self.search_tx = Some(search_tx.clone());
+            // Searches run concurrently, but only a few at a time so that the
+            // bounded queue still holds back the scanner.
+            let workers = Arc::new(Semaphore::new(SEARCH_WORKERS));
+            spawn_searches(
+                cluster_rx,
+                Arc::clone(&workers),
+                sources.clone(),
+                runtime.clone(),
+                search_tx.clone(),
+            );
+            spawn_searches(queue_rx, workers, sources, runtime, search_tx);
 
+            self.scan_rx = Some(scan_rx);
             self.scan_progress_rx = Some(progress_rx);
             self.search_rx = Some(search_rx);
         }
@@ -144,7 +473,8 @@ impl App {
                 if let AppState::Scanning { current_file, .. } = &mut self.state {
                     *current_file = Some(format!(
                         "Scanning: {} ({} clusters found)",
-                        progress.current_dir, progress.clusters_found
+                        escape_path(&progress.current_dir),
+                        progress.clusters_found
                     ));
                 }
             }
@@ -160,15 +490,34 @@ impl App {
 
         for message in messages {
             match message {
-                SearchMessage::Searching(_cluster, status) => {
+                SearchMessage::Searching(cluster, status) => {
                     if let AppState::Scanning { current_file, .. } = &mut self.state {
                         *current_file = Some(format!("🔍 {}", status));
                     }
+                    self.clear_retry_status(&cluster);
                 }
-                SearchMessage::Results(cluster, releases) => {
+                SearchMessage::Retrying(cluster, status) => match &mut self.state {
+                    AppState::Scanning { current_file, .. } => {
+                        *current_file = Some(format!("🔍 {}", status));
+                    }
+                    // Searches for the clusters still to come stall too, so
+                    // these are worth showing whichever cluster they're for.
+                    AppState::AutoTagging {
+                        status: current,
+                        prompt: None,
+                        ..
+                    } => {
+                        *current = Some(format!("🔍 {}", status));
+                        self.retrying = Some(cluster);
+                    }
+                    _ => {}
+                },
+                SearchMessage::Results(cluster, candidates, cursors) => {
+                    self.clear_retry_status(&cluster);
                     self.pending_clusters.push_back(PendingCluster {
                         cluster,
-                        results: releases,
+                        results: candidates,
+                        cursors,
                     });
 
                     if !matches!(self.state, AppState::AutoTagging { .. }) {
@@ -176,23 +525,78 @@ impl App {
                     }
                 }
                 SearchMessage::NoResults(cluster) => {
-                    // todo!("Prompt user for manual search or skip etc.");
+                    self.clear_retry_status(&cluster);
                     if let AppState::Scanning { current_file, .. } = &mut self.state {
                         *current_file = Some(format!(
                             "∅ No matches for {} - {}",
                             cluster.album_artist, cluster.album
                         ));
                     }
+                    // Still offer the cluster so a release can be entered by
+                    // ID or URL.
+                    self.pending_clusters.push_back(PendingCluster {
+                        cluster,
+                        results: Vec::new(),
+                        cursors: Vec::new(),
+                    });
+                    if !matches!(self.state, AppState::AutoTagging { .. }) {
+                        self.show_next_cluster();
+                    }
                 }
-                SearchMessage::Error(_cluster, msg) => {
-                    if let AppState::Scanning { current_file, .. } = &mut self.state {
-                        *current_file = Some(format!("⚠ Error: {}", msg));
+                SearchMessage::Error(cluster, msg) => {
+                    self.clear_retry_status(&cluster);
+                    match &mut self.state {
+                        AppState::Scanning { current_file, .. } => {
+                            *current_file = Some(format!("⚠ Error: {}", msg));
+                        }
+                        AppState::AutoTagging {
+                            cluster: current,
+                            status,
+                            loading_more,
+                            ..
+                        } if is_same_cluster(current, &cluster) => {
+                            *status = Some(format!("⚠ {}", msg));
+                            *loading_more = false;
+                        }
+                        // todo!("Show error to user");
+                        _ => {}
                     }
-                    // todo!("Show error to user");
+                }
+                SearchMessage::Candidates(cluster, candidates) => {
+                    self.add_candidates(&cluster, candidates);
+                }
+                SearchMessage::ReleaseDetails(release) => {
+                    let id = release.id.clone();
+                    let genres = self.genres.propose(&release.genres, self.genre_count);
+                    self.set_release_lookup(id, ReleaseLookup::Loaded { release, genres });
+                }
+                SearchMessage::ReleaseDetailsError(id, msg) => {
+                    self.set_release_lookup(id, ReleaseLookup::Failed(msg));
+                }
+                SearchMessage::MoreResults(cluster, candidates, next) => {
+                    self.clear_retry_status(&cluster);
+                    self.append_candidates(&cluster, candidates, next);
                 }
             }
         }
 
+        while let Ok(message) = self.sync_rx.try_recv() {
+            let AppState::Syncing { entries, .. } = &mut self.state else {
+                continue;
+            };
+            let (idx, status) = match message {
+                SyncMessage::Diff(idx, diff) => (idx, SyncStatus::Done(diff)),
+                SyncMessage::Error(idx, msg) => (idx, SyncStatus::Failed(msg)),
+            };
+            if let Some(entry) = entries.iter_mut().find(|it| it.cluster == idx) {
+                entry.status = status;
+            }
+        }
+
+        while let Ok(message) = self.art_rx.try_recv() {
+            self.handle_art_message(message);
+        }
+
         if let Some(rx) = &self.scan_rx
             && let Ok(message) = rx.try_recv()
         {
@@ -207,41 +611,76 @@ impl App {
         if let Event::Key(key) = event::read()? {
             let is_ctrl_c = matches!(key.code, KeyCode::Char('c'))
                 && key.modifiers.contains(KeyModifiers::CONTROL);
-            let should_quit = is_ctrl_c || matches!(key.code, KeyCode::Char('q'));
+            let is_typing = matches!(
+                self.state,
+                AppState::AutoTagging {
+                    prompt: Some(_),
+                    ..
+                }
+            );
+            let should_quit = is_ctrl_c || (!is_typing && matches!(key.code, KeyCode::Char('q')));
             if should_quit {
                 self.should_quit = true;
                 return Ok(());
             }
 
             match &self.state {
-                AppState::Scanning {
-                    files_found,
-                    is_complete,
-                    ..
-                } => {
-                    if matches!(key.code, KeyCode::Enter) && *is_complete {
-                        let files = files_found.clone();
-                        let clusters = scanner::cluster_files(files);
+                AppState::Scanning { is_complete, .. } => {
+                    if matches!(key.code, KeyCode::Enter)
+                        && *is_complete
+                        && let Some((clusters, selected_idx)) = self.cluster_list.take()
+                    {
                         self.state = AppState::ClusterList {
                             clusters,
-                            selected_idx: 0,
+                            selected_idx,
                         };
                     }
                 }
+                AppState::AutoTagging {
+                    prompt: Some(_), ..
+                } => self.handle_prompt_key(key.code),
+                AppState::AutoTagging { art: Some(_), .. } => match key.code {
+                    KeyCode::Char('k') | KeyCode::Up => self.move_art_selection(-1),
+                    KeyCode::Char('j') | KeyCode::Down => self.move_art_selection(1),
+                    KeyCode::Enter => self.pick_front_cover(),
+                    KeyCode::Esc | KeyCode::Char('h' | 'c') => self.close_cover_art(),
+                    _ => {}
+                },
                 AppState::AutoTagging { .. } => match key.code {
                     KeyCode::Char('k') | KeyCode::Up => self.select_previous_match(),
                     KeyCode::Char('j') | KeyCode::Down => self.select_next_match(),
                     KeyCode::Char('A') => self.handle_apply(),
                     KeyCode::Char('s') => self.handle_skip(),
                     KeyCode::Char('M') => self.handle_manual_search(),
+                    KeyCode::Char('e') => self.retry_with_encoding_suggestion(),
+                    KeyCode::Char('u') => self.open_prompt(),
+                    KeyCode::Enter | KeyCode::Char('l') | KeyCode::Right => self.expand_group(),
+                    KeyCode::Char('h') | KeyCode::Left => self.collapse_group(),
+                    KeyCode::Char('g') => self.toggle_grouping(),
+                    KeyCode::Char('n') => self.request_more_results(),
+                    KeyCode::Char('c') => self.open_cover_art(),
                     _ => {}
                 },
                 AppState::ClusterList { .. } => match key.code {
                     KeyCode::Up | KeyCode::Char('k') => self.select_previous(),
                     KeyCode::Down | KeyCode::Char('j') => self.select_next(),
-                    KeyCode::Enter => {
-                        todo!("Start MusicBrainz search for selected cluster");
-                    }
+                    KeyCode::Char('d') => self.show_duplicates(),
+                    KeyCode::Char('m') => self.start_sync(),
+                    KeyCode::Char('e') => self.accept_encoding_suggestion(),
+                    KeyCode::Enter => self.search_selected_cluster(),
+                    KeyCode::Char('S') => self.search_selected_singletons(),
+                    _ => {}
+                },
+                AppState::Duplicates { .. } => match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => self.select_previous(),
+                    KeyCode::Down | KeyCode::Char('j') => self.select_next(),
+                    KeyCode::Esc | KeyCode::Char('h') => self.close_duplicates(),
+                    _ => {}
+                },
+                AppState::Syncing { .. } => match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => self.select_previous(),
+                    KeyCode::Down | KeyCode::Char('j') => self.select_next(),
+                    KeyCode::Esc | KeyCode::Char('h') => self.close_sync(),
                     _ => {}
                 },
                 AppState::Error { .. } => {
@@ -254,6 +693,7 @@ impl App {
     }
 
     fn complete_scan(&mut self, files: Vec<AudioFile>) {
+        self.cluster_list = Some((scanner::cluster_files(files.clone()), 0));
         if let AppState::Scanning { path, .. } = &self.state {
             let path = path.clone();
             self.state = AppState::Scanning {
@@ -265,16 +705,590 @@ impl App {
         }
     }
 
+    /// Clear the auto-tagging status if it's showing a retry of a search
+    /// for `cluster`, which has since moved on.
+    fn clear_retry_status(&mut self, cluster: &AlbumCluster) {
+        if !self
+            .retrying
+            .as_ref()
+            .is_some_and(|it| is_same_cluster(it, cluster))
+        {
+            return;
+        }
+        self.retrying = None;
+        if let AppState::AutoTagging { status, .. } = &mut self.state {
+            *status = None;
+        }
+    }
+
     fn show_next_cluster(&mut self) {
         if let Some(pending) = self.pending_clusters.pop_front() {
+            self.set_aside_cluster_list();
             self.state = AppState::AutoTagging {
                 cluster: pending.cluster,
                 results: pending.results,
+                scores: Vec::new(),
+                selected_idx: 0,
+                grouped: true,
+                expanded: HashSet::new(),
+                cursors: pending.cursors,
+                loading_more: false,
+                details: HashMap::new(),
+                prompt: None,
+                status: None,
+                art: None,
+                front_cover: None,
+            };
+            self.rescore();
+            // Prefetch by rank, whichever release groups the candidates are in.
+            let top = match &self.state {
+                AppState::AutoTagging {
+                    results, scores, ..
+                } => matching::ranked(scores, results.len()),
+                _ => Vec::new(),
+            };
+            for idx in top.into_iter().take(PREFETCH_CANDIDATES) {
+                self.request_release_details(idx);
+            }
+        } else if matches!(self.state, AppState::AutoTagging { .. }) {
+            // Nothing left to tag, so fall back to browsing the scanned clusters,
+            // or to the scan progress while more clusters are on their way.
+            self.state = match self.cluster_list.take() {
+                Some((clusters, selected_idx)) => AppState::ClusterList {
+                    clusters,
+                    selected_idx,
+                },
+                None => AppState::Scanning {
+                    path: self.scan_path.clone(),
+                    files_found: Vec::new(),
+                    current_file: None,
+                    is_complete: false,
+                },
+            };
+        }
+    }
+
+    /// Look up the full release for a search result of the current cluster,
+    /// unless it has already been requested.
+    fn request_release_details(&mut self, idx: usize) {
+        let AppState::AutoTagging {
+            results, details, ..
+        } = &mut self.state
+        else {
+            return;
+        };
+        let (Some(candidate), Some(tx)) = (results.get(idx), &self.search_tx) else {
+            return;
+        };
+        if details.contains_key(&candidate.id) {
+            return;
+        }
+        let Some(source) = source_named(&self.sources, candidate.source) else {
+            return;
+        };
+
+        details.insert(candidate.id.clone(), ReleaseLookup::Pending);
+        let tx = tx.clone();
+        let id = candidate.id.clone();
+        self.runtime
+            .spawn(async move { lookup_release_details(source.as_ref(), tx, id).await });
+    }
+
+    fn set_release_lookup(&mut self, id: String, lookup: ReleaseLookup) {
+        // Lookups for a cluster that is no longer shown are dropped; the
+        // response cache makes requesting them again cheap.
+        if let AppState::AutoTagging { details, .. } = &mut self.state
+            && let Some(entry) = details.get_mut(&id)
+        {
+            let is_loaded = matches!(lookup, ReleaseLookup::Loaded { .. });
+            *entry = lookup;
+            // The tracklist gives the titles and durations to compare.
+            if is_loaded {
+                self.rescore();
+            }
+        }
+    }
+
+    /// Score the current cluster's candidates, using their full releases
+    /// where those have been looked up, keeping the same candidate
+    /// selected.
+    fn rescore(&mut self) {
+        let AppState::AutoTagging {
+            cluster,
+            results,
+            scores,
+            selected_idx,
+            grouped,
+            expanded,
+            details,
+            ..
+        } = &mut self.state
+        else {
+            return;
+        };
+        let rows = candidate_rows(results, scores, *grouped, expanded);
+        let selected = rows.get(*selected_idx).map(CandidateRow::candidate);
+
+        *scores = results
+            .iter()
+            .map(|candidate| {
+                let release = match details.get(&candidate.id) {
+                    Some(ReleaseLookup::Loaded { release, .. }) => release,
+                    _ => candidate,
+                };
+                // The mapping is of the release as shown, and rendered from
+                // the score rather than worked out again every frame.
+                let mapping = matching::map_tracks(cluster, release);
+                // A recording match is scored on its track, which the search
+                // result already has.
+                let scored = if candidate.matched_track.is_some() && cluster.is_singleton() {
+                    candidate
+                } else {
+                    release
+                };
+                matching::score(cluster, scored, mapping, &self.weights)
+            })
+            .collect();
+
+        let rows = candidate_rows(results, scores, *grouped, expanded);
+        if let Some(row) = selected.and_then(|idx| row_of(&rows, idx)) {
+            *selected_idx = row;
+        }
+    }
+
+    /// Keep the clusters being browsed, wherever they are shown, to go back
+    /// to once there is nothing left to tag.
+    fn set_aside_cluster_list(&mut self) {
+        match &mut self.state {
+            AppState::ClusterList {
+                clusters,
+                selected_idx: list_idx,
+            }
+            | AppState::Duplicates {
+                clusters, list_idx, ..
+            }
+            | AppState::Syncing {
+                clusters, list_idx, ..
+            } => self.cluster_list = Some((std::mem::take(clusters), *list_idx)),
+            _ => {}
+        }
+    }
+
+    /// Queue a cluster for a MusicBrainz search.
+    fn queue_search(&self, cluster: AlbumCluster) {
+        if let Some(tx) = &self.search_queue_tx {
+            let _ = tx.send(cluster);
+        }
+    }
+
+    fn search_selected_cluster(&mut self) {
+        if let AppState::ClusterList {
+            clusters,
+            selected_idx,
+        } = &self.state
+            && let Some(cluster) = clusters.get(*selected_idx)
+        {
+            self.queue_search(cluster.clone());
+        }
+    }
+
+    /// Search each track of the selected cluster on its own, for loose
+    /// downloads that don't belong to a single album.
+    fn search_selected_singletons(&mut self) {
+        if let AppState::ClusterList {
+            clusters,
+            selected_idx,
+        } = &self.state
+            && let Some(cluster) = clusters.get(*selected_idx)
+        {
+            for singleton in cluster.singletons() {
+                self.queue_search(singleton);
+            }
+        }
+    }
+
+    fn accept_encoding_suggestion(&mut self) {
+        if let AppState::ClusterList {
+            clusters,
+            selected_idx,
+        } = &mut self.state
+            && *selected_idx < clusters.len()
+            && clusters[*selected_idx].encoding_suggestion.is_some()
+        {
+            let cluster = clusters.remove(*selected_idx);
+            let repaired = scanner::accept_encoding_suggestion(cluster);
+            clusters.splice(*selected_idx..*selected_idx, repaired);
+        }
+    }
+
+    /// Repair the current cluster's tags and search again with the corrected
+    /// strings.
+    fn retry_with_encoding_suggestion(&mut self) {
+        if let AppState::AutoTagging { cluster, .. } = &self.state
+            && cluster.encoding_suggestion.is_some()
+        {
+            for repaired in scanner::accept_encoding_suggestion(cluster.clone()) {
+                self.queue_search(repaired);
+            }
+            self.show_next_cluster();
+        }
+    }
+
+    fn show_duplicates(&mut self) {
+        if let AppState::ClusterList {
+            clusters,
+            selected_idx,
+        } = &mut self.state
+        {
+            let clusters = std::mem::take(clusters);
+            let report = duplicates::find_duplicates(&clusters);
+            self.state = AppState::Duplicates {
+                clusters,
+                report,
                 selected_idx: 0,
+                list_idx: *selected_idx,
+            };
+        }
+    }
+
+    fn close_duplicates(&mut self) {
+        if let AppState::Duplicates {
+            clusters, list_idx, ..
+        } = &mut self.state
+        {
+            self.state = AppState::ClusterList {
+                clusters: std::mem::take(clusters),
+                selected_idx: *list_idx,
+            };
+        }
+    }
+
+    /// Look up every cluster tagged with a release MBID and show what would
+    /// change.
+    fn start_sync(&mut self) {
+        let AppState::ClusterList {
+            clusters,
+            selected_idx,
+        } = &mut self.state
+        else {
+            return;
+        };
+        let list_idx = *selected_idx;
+        let clusters = std::mem::take(clusters);
+        let mut entries = Vec::new();
+        for (idx, cluster) in clusters.iter().enumerate() {
+            if cluster.release_mbid().is_none() {
+                continue;
+            }
+            entries.push(SyncEntry {
+                cluster: idx,
+                status: SyncStatus::Pending,
+            });
+            // The lookups share the client's rate limiter, so they are
+            // simply spawned all at once.
+            let client = self.mb_client.clone();
+            let tx = self.sync_tx.clone();
+            let cluster = cluster.clone();
+            self.runtime
+                .spawn(async move { sync_cluster(&client, tx, idx, cluster).await });
+        }
+        self.state = AppState::Syncing {
+            clusters,
+            entries,
+            selected_idx: 0,
+            list_idx,
+        };
+    }
+
+    fn close_sync(&mut self) {
+        if let AppState::Syncing {
+            clusters, list_idx, ..
+        } = &mut self.state
+        {
+            self.state = AppState::ClusterList {
+                clusters: std::mem::take(clusters),
+                selected_idx: *list_idx,
             };
         }
     }
 
+    /// List the cover art of the highlighted release.
+    fn open_cover_art(&mut self) {
+        let AppState::AutoTagging {
+            results,
+            scores,
+            selected_idx,
+            grouped,
+            expanded,
+            status,
+            art,
+            ..
+        } = &mut self.state
+        else {
+            return;
+        };
+        let rows = candidate_rows(results, scores, *grouped, expanded);
+        let Some(candidate) = rows.get(*selected_idx).map(|it| &results[it.candidate()]) else {
+            return;
+        };
+        let Some(client) = &self.cover_art else {
+            *status = Some(String::from("Cover art isn't available offline"));
+            return;
+        };
+        if candidate.source != MUSICBRAINZ {
+            *status = Some(format!(
+                "Cover art is only available for {} releases",
+                MUSICBRAINZ
+            ));
+            return;
+        }
+
+        *art = Some(ArtPicker {
+            release_id: candidate.id.clone(),
+            release: format!("{} - {}", candidate.artist, candidate.title),
+            listing: ArtListing::Pending,
+            selected_idx: 0,
+            status: None,
+        });
+        let client = client.clone();
+        let tx = self.art_tx.clone();
+        let mbid = candidate.id.clone();
+        self.runtime
+            .spawn(async move { list_cover_art(&client, tx, mbid).await });
+    }
+
+    fn handle_art_message(&mut self, message: CoverArtMessage) {
+        // Messages for a picker that has been closed are dropped; the
+        // images stay in the cache for next time.
+        let AppState::AutoTagging { art: Some(art), .. } = &mut self.state else {
+            return;
+        };
+        match message {
+            CoverArtMessage::Retrying(mbid, status) if mbid == art.release_id => {
+                art.status = Some(status);
+                return;
+            }
+            CoverArtMessage::Images(mbid, images) if mbid == art.release_id => {
+                art.listing = ArtListing::Loaded(
+                    images
+                        .into_iter()
+                        .map(|image| ArtEntry {
+                            image,
+                            download: ArtDownload::NotStarted,
+                        })
+                        .collect(),
+                );
+            }
+            CoverArtMessage::ImagesError(mbid, msg) if mbid == art.release_id => {
+                art.listing = ArtListing::Failed(msg);
+            }
+            CoverArtMessage::Downloaded(mbid, id, image) if mbid == art.release_id => {
+                set_art_download(art, &id, ArtDownload::Done(image));
+            }
+            CoverArtMessage::DownloadError(mbid, id, msg) if mbid == art.release_id => {
+                set_art_download(art, &id, ArtDownload::Failed(msg));
+            }
+            _ => return,
+        }
+        // Whatever was being retried has come through or given up.
+        art.status = None;
+        // The listing starts with the front, so that comes first.
+        self.download_highlighted_art();
+    }
+
+    fn move_art_selection(&mut self, step: isize) {
+        if let AppState::AutoTagging { art: Some(art), .. } = &mut self.state
+            && let ArtListing::Loaded(entries) = &art.listing
+            && !entries.is_empty()
+        {
+            art.selected_idx = art
+                .selected_idx
+                .saturating_add_signed(step)
+                .min(entries.len() - 1);
+        }
+        self.download_highlighted_art();
+    }
+
+    /// Download the highlighted image if that hasn't been tried yet.
+    /// Images are only fetched as they are looked at, since each one counts
+    /// towards the MusicBrainz rate limit.
+    fn download_highlighted_art(&mut self) {
+        let AppState::AutoTagging { art: Some(art), .. } = &mut self.state else {
+            return;
+        };
+        let Some(client) = &self.cover_art else {
+            return;
+        };
+        let ArtListing::Loaded(entries) = &mut art.listing else {
+            return;
+        };
+        let Some(entry) = entries.get_mut(art.selected_idx) else {
+            return;
+        };
+        if !matches!(entry.download, ArtDownload::NotStarted) {
+            return;
+        }
+        entry.download = ArtDownload::Pending;
+
+        let client = client.clone();
+        let tx = self.art_tx.clone();
+        let mbid = art.release_id.clone();
+        let image = entry.image.clone();
+        self.runtime
+            .spawn(async move { download_cover_art(&client, tx, mbid, image).await });
+    }
+
+    /// Use the highlighted image as the front cover, once it has been
+    /// downloaded.
+    fn pick_front_cover(&mut self) {
+        let AppState::AutoTagging {
+            art, front_cover, ..
+        } = &mut self.state
+        else {
+            return;
+        };
+        let Some(picker) = art else {
+            return;
+        };
+        let ArtListing::Loaded(entries) = &picker.listing else {
+            return;
+        };
+        if let Some(ArtEntry {
+            image: listed,
+            download: ArtDownload::Done(image),
+        }) = entries.get(picker.selected_idx)
+        {
+            *front_cover = Some(FrontCover {
+                release_id: picker.release_id.clone(),
+                image_id: listed.id(),
+                image: image.clone(),
+            });
+            *art = None;
+        }
+    }
+
+    fn close_cover_art(&mut self) {
+        if let AppState::AutoTagging { art, .. } = &mut self.state {
+            *art = None;
+        }
+    }
+
+    fn open_prompt(&mut self) {
+        if let AppState::AutoTagging { prompt, status, .. } = &mut self.state {
+            *prompt = Some(String::new());
+            *status = None;
+        }
+    }
+
+    fn handle_prompt_key(&mut self, code: KeyCode) {
+        let AppState::AutoTagging {
+            prompt: Some(text), ..
+        } = &mut self.state
+        else {
+            return;
+        };
+        match code {
+            KeyCode::Char(c) => text.push(c),
+            KeyCode::Backspace => {
+                text.pop();
+            }
+            KeyCode::Enter => self.submit_prompt(),
+            KeyCode::Esc => {
+                if let AppState::AutoTagging { prompt, .. } = &mut self.state {
+                    *prompt = None;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Look up the release or release group entered at the prompt.
+    fn submit_prompt(&mut self) {
+        let AppState::AutoTagging {
+            cluster,
+            prompt,
+            status,
+            ..
+        } = &mut self.state
+        else {
+            return;
+        };
+        let Some(text) = prompt.take() else {
+            return;
+        };
+        let Some(tx) = &self.search_tx else {
+            return;
+        };
+
+        *status = Some(String::from("Looking up release..."));
+        let sources = self.sources.clone();
+        let tx = tx.clone();
+        let cluster = cluster.clone();
+        self.runtime
+            .spawn(async move { lookup_reference(&sources, tx, cluster, text).await });
+    }
+
+    /// Put releases found by a manual lookup at the top of the current
+    /// cluster's candidates.
+    fn add_candidates(&mut self, cluster: &AlbumCluster, candidates: Vec<AlbumCandidate>) {
+        let AppState::AutoTagging {
+            cluster: current,
+            results,
+            expanded,
+            details,
+            status,
+            ..
+        } = &mut self.state
+        else {
+            return;
+        };
+        if !is_same_cluster(current, cluster) {
+            return;
+        }
+
+        *status = Some(match candidates.len() {
+            1 => String::from("Added 1 candidate"),
+            n => format!("Added {} candidates", n),
+        });
+        results.retain(|it| {
+            !candidates
+                .iter()
+                .any(|candidate| candidate.source == it.source && candidate.id == it.id)
+        });
+        for candidate in &candidates {
+            // A release lookup already carries the tracklist.
+            if candidate.has_tracklist() {
+                details.insert(
+                    candidate.id.clone(),
+                    ReleaseLookup::Loaded {
+                        release: Box::new(candidate.clone()),
+                        genres: self.genres.propose(&candidate.genres, self.genre_count),
+                    },
+                );
+            }
+        }
+        // Show the editions of a release group that was asked for by name.
+        if let Some(candidate) = candidates.first() {
+            expanded.insert(group_id(candidate).to_string());
+        }
+        results.splice(0..0, candidates);
+        self.rescore();
+        // Select the release that was asked for, wherever it ranks.
+        if let AppState::AutoTagging {
+            results,
+            scores,
+            selected_idx,
+            grouped,
+            expanded,
+            ..
+        } = &mut self.state
+        {
+            let rows = candidate_rows(results, scores, *grouped, expanded);
+            *selected_idx = row_of(&rows, 0).unwrap_or_default();
+        }
+        self.request_release_details(0);
+    }
+
     fn handle_apply(&mut self) {
         // TODO: do something
         self.show_next_cluster();
@@ -290,44 +1304,221 @@ impl App {
     }
 
     fn select_next_match(&mut self) {
-        if let AppState::AutoTagging {
+        self.move_match_selection(|row, len| (row + 1).min(len - 1));
+    }
+
+    fn select_previous_match(&mut self) {
+        self.move_match_selection(|row, _| row.saturating_sub(1));
+    }
+
+    /// Move to another row of candidates and look up the release it shows.
+    fn move_match_selection(&mut self, step: impl Fn(usize, usize) -> usize) {
+        let AppState::AutoTagging {
             results,
+            scores,
             selected_idx,
+            grouped,
+            expanded,
             ..
         } = &mut self.state
-        {
-            if !results.is_empty() {
-                *selected_idx = (*selected_idx + 1).min(results.len() - 1);
-            }
+        else {
+            return;
+        };
+        let rows = candidate_rows(results, scores, *grouped, expanded);
+        if rows.is_empty() {
+            return;
         }
+        *selected_idx = step(*selected_idx, rows.len());
+        let idx = rows[*selected_idx].candidate();
+        self.request_release_details(idx);
     }
 
-    fn select_previous_match(&mut self) {
-        if let AppState::AutoTagging {
+    /// List the editions of the selected release group.
+    fn expand_group(&mut self) {
+        let AppState::AutoTagging {
             results,
+            scores,
             selected_idx,
+            grouped,
+            expanded,
             ..
         } = &mut self.state
+        else {
+            return;
+        };
+        let rows = candidate_rows(results, scores, *grouped, expanded);
+        if let Some(CandidateRow::Group {
+            id,
+            expanded: false,
+            ..
+        }) = rows.get(*selected_idx)
         {
-            if !results.is_empty() {
-                *selected_idx = selected_idx.saturating_sub(1);
-            }
+            expanded.insert(id.clone());
         }
     }
 
-    fn select_next(&mut self) {
-        if let AppState::ClusterList {
-            clusters,
+    /// Fold the editions of the selected release group, or of the group the
+    /// selected edition belongs to, back into one row.
+    fn collapse_group(&mut self) {
+        let AppState::AutoTagging {
+            results,
+            scores,
             selected_idx,
+            grouped,
+            expanded,
+            ..
         } = &mut self.state
-            && !clusters.is_empty()
+        else {
+            return;
+        };
+        let rows = candidate_rows(results, scores, *grouped, expanded);
+        // The group row comes before its editions.
+        let group = rows
+            .get(..=*selected_idx)
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, it)| !matches!(it, CandidateRow::Release { nested: true, .. }));
+        if let Some((row, CandidateRow::Group { id, .. })) = group
+            && expanded.remove(id)
         {
-            *selected_idx = (*selected_idx + 1).min(clusters.len() - 1);
+            *selected_idx = row;
+        }
+    }
+
+    /// Switch between one row per release group and one row per release,
+    /// keeping the same candidate selected.
+    fn toggle_grouping(&mut self) {
+        let AppState::AutoTagging {
+            results,
+            scores,
+            selected_idx,
+            grouped,
+            expanded,
+            ..
+        } = &mut self.state
+        else {
+            return;
+        };
+        let rows = candidate_rows(results, scores, *grouped, expanded);
+        let selected = rows.get(*selected_idx).map(CandidateRow::candidate);
+        *grouped = !*grouped;
+        let rows = candidate_rows(results, scores, *grouped, expanded);
+        *selected_idx = selected
+            .and_then(|idx| row_of(&rows, idx))
+            .unwrap_or_default();
+    }
+
+    /// Fetch the next page of the most precise query that has more results.
+    fn request_more_results(&mut self) {
+        let AppState::AutoTagging {
+            cluster,
+            cursors,
+            loading_more,
+            status,
+            ..
+        } = &mut self.state
+        else {
+            return;
+        };
+        if *loading_more {
+            return;
+        }
+        let (Some(cursor), Some(tx)) = (cursors.first().cloned(), &self.search_tx) else {
+            *status = Some(String::from("No more matches"));
+            return;
+        };
+        let Some(source) = source_named(&self.sources, cursor.source) else {
+            return;
+        };
+
+        *loading_more = true;
+        *status = Some(format!(
+            "Loading more {} matches ({})...",
+            cursor.source,
+            cursor.strategy.label()
+        ));
+        let tx = tx.clone();
+        let cluster = cluster.clone();
+        self.runtime
+            .spawn(async move { load_more(source.as_ref(), tx, cluster, cursor).await });
+    }
+
+    /// Append a further page of results to the current cluster's candidates,
+    /// keeping the same candidate selected.
+    fn append_candidates(
+        &mut self,
+        cluster: &AlbumCluster,
+        candidates: Vec<AlbumCandidate>,
+        next: Option<SearchCursor>,
+    ) {
+        let AppState::AutoTagging {
+            cluster: current,
+            results,
+            cursors,
+            loading_more,
+            status,
+            ..
+        } = &mut self.state
+        else {
+            return;
+        };
+        if !is_same_cluster(current, cluster) {
+            return;
+        }
+
+        let before = results.len();
+        for candidate in candidates {
+            if !results.iter().any(|it| it.is_same(&candidate)) {
+                results.push(candidate);
+            }
+        }
+        // The page came from the first cursor, so it is replaced by where
+        // that query continues.
+        if !cursors.is_empty() {
+            cursors.remove(0);
+        }
+        if let Some(next) = next {
+            cursors.insert(0, next);
+        }
+        *loading_more = false;
+        *status = Some(match results.len() - before {
+            1 => String::from("Added 1 more match"),
+            n => format!("Added {} more matches", n),
+        });
+        // New candidates are ranked last until they are scored.
+        self.rescore();
+    }
+
+    fn select_next(&mut self) {
+        let (len, selected_idx) = match &mut self.state {
+            AppState::ClusterList {
+                clusters,
+                selected_idx,
+            } => (clusters.len(), selected_idx),
+            AppState::Duplicates {
+                report,
+                selected_idx,
+                ..
+            } => (report.identical.len() + report.near.len(), selected_idx),
+            AppState::Syncing {
+                entries,
+                selected_idx,
+                ..
+            } => (entries.len(), selected_idx),
+            _ => return,
+        };
+        if len > 0 {
+            *selected_idx = (*selected_idx + 1).min(len - 1);
         }
     }
 
     fn select_previous(&mut self) {
-        if let AppState::ClusterList { selected_idx, .. } = &mut self.state {
+        if let AppState::ClusterList { selected_idx, .. }
+        | AppState::Duplicates { selected_idx, .. }
+        | AppState::Syncing { selected_idx, .. } = &mut self.state
+        {
             *selected_idx = selected_idx.saturating_sub(1);
         }
     }
@@ -336,3 +1527,46 @@ impl App {
         self.state = AppState::Error { message };
     }
 }
+
+/// Search each cluster received, taking a permit from `workers` for every
+/// search in flight.
+fn spawn_searches(
+    clusters: mpsc::Receiver<AlbumCluster>,
+    workers: Arc<Semaphore>,
+    sources: Vec<Arc<dyn MetadataSource>>,
+    runtime: Handle,
+    search_tx: mpsc::Sender<SearchMessage>,
+) {
+    thread::spawn(move || {
+        while let Ok(cluster) = clusters.recv() {
+            let Ok(permit) = runtime.block_on(Arc::clone(&workers).acquire_owned()) else {
+                break;
+            };
+            let sources = sources.clone();
+            let tx = search_tx.clone();
+            runtime.spawn(async move {
+                if let Err(e) = search_for_cluster(&sources, tx, cluster).await {
+                    eprintln!("Search error: {}", e);
+                }
+                drop(permit);
+            });
+        }
+    });
+}
+
+fn set_art_download(art: &mut ArtPicker, id: &str, download: ArtDownload) {
+    if let ArtListing::Loaded(entries) = &mut art.listing
+        && let Some(entry) = entries.iter_mut().find(|it| it.image.id() == id)
+    {
+        entry.download = download;
+    }
+}
+
+/// Whether two clusters hold the same tracks, even if one is a stale copy.
+fn is_same_cluster(a: &AlbumCluster, b: &AlbumCluster) -> bool {
+    // Singletons split from one cluster share everything but their track.
+    a.base_path == b.base_path
+        && a.album_artist == b.album_artist
+        && a.album == b.album
+        && a.tracks.first().map(|it| &it.path) == b.tracks.first().map(|it| &it.path)
+}
@@ -7,6 +7,7 @@ pub enum AudioCodec {
     Flac,
     Mp3,
     Mp4,
+    Opus,
 }
 
 pub fn codec_name(codec: AudioCodec) -> String {
@@ -16,5 +17,6 @@ pub fn codec_name(codec: AudioCodec) -> String {
         AudioCodec::Mp3 => String::from("MP3"),
         // I don't know who uses this format aside from Apple, hence M4A.
         AudioCodec::Mp4 => String::from("M4A"),
+        AudioCodec::Opus => String::from("Opus"),
     }
 }
@@ -0,0 +1,61 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use std::path::PathBuf;
+
+use crate::{
+    discogs,
+    genres::DEFAULT_GENRE_COUNT,
+    matching::Weights,
+    musicbrainz::{client::ClientConfig, coverart::CoverArtConfig, dump::DumpStore},
+};
+
+/// Settings for a session, gathered from the command line.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The directory to scan for audio files.
+    pub scan_path: PathBuf,
+    /// Whether to descend into symlinked directories and read symlinked
+    /// files.
+    pub follow_symlinks: bool,
+    pub musicbrainz: ClientConfig,
+    /// Where to find cover art, unless working offline.
+    pub cover_art: Option<CoverArtConfig>,
+    /// Also search Discogs, when a token has been given.
+    pub discogs: Option<discogs::client::ClientConfig>,
+    /// Empty the response cache before starting.
+    pub clear_cache: bool,
+    /// Import this MusicBrainz JSON release dump into the offline store
+    /// instead of starting the interface.
+    pub import_dump: Option<PathBuf>,
+    /// Where the offline store lives.
+    pub dump_dir: Option<PathBuf>,
+    /// Genre tree to use instead of the built-in one.
+    pub genre_tree: Option<PathBuf>,
+    /// File listing the only genres to propose.
+    pub genre_whitelist: Option<PathBuf>,
+    /// How many genres to propose for an album.
+    pub genre_count: usize,
+    /// How much each field counts when scoring candidates.
+    pub weights: Weights,
+}
+
+impl Config {
+    pub fn new(scan_path: PathBuf) -> Self {
+        Self {
+            scan_path,
+            follow_symlinks: false,
+            musicbrainz: ClientConfig::default(),
+            cover_art: Some(CoverArtConfig::default()),
+            discogs: None,
+            clear_cache: false,
+            import_dump: None,
+            dump_dir: DumpStore::default_dir(),
+            genre_tree: None,
+            genre_whitelist: None,
+            genre_count: DEFAULT_GENRE_COUNT,
+            weights: Weights::default(),
+        }
+    }
+}
@@ -0,0 +1,281 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use std::time::Duration;
+
+use color_eyre::eyre::{Result, WrapErr};
+use reqwest::header::AUTHORIZATION;
+use serde::{Deserialize, de::DeserializeOwned};
+
+use crate::{
+    musicbrainz::{cache::ResponseCache, limiter::RateLimiter},
+    source::{
+        OnRetry,
+        error::{ApiError, ErrorKind},
+        http::{self, DEFAULT_MAX_ATTEMPTS, DEFAULT_USER_AGENT, RetryPolicy, SearchPage},
+    },
+};
+
+/// Name of the service, as given in errors.
+const SERVICE: &str = "Discogs";
+
+/// Discogs allows authenticated clients 60 requests a minute, counted over
+/// a moving window, so stay well clear of it.
+const RATE_LIMIT: Duration = Duration::from_millis(1500);
+
+/// Number of search results fetched at a time.
+const SEARCH_PAGE_SIZE: usize = 25;
+
+/// Root of the public Discogs API.
+pub const PUBLIC_BASE_URL: &str = "https://api.discogs.com";
+
+/// Where and how to talk to the Discogs API.
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// Root of the API, e.g. `http://localhost:8080` for a local stand-in.
+    pub base_url: String,
+    /// Personal access token, sent with every request.
+    pub token: String,
+    pub user_agent: String,
+    /// Minimum time between requests.  When unset, the public API gets
+    /// [`RATE_LIMIT`] and any other server is not limited at all.
+    pub rate_limit: Option<Duration>,
+    pub max_attempts: u32,
+    pub cache: Option<ResponseCache>,
+}
+
+impl ClientConfig {
+    pub fn new(token: String) -> Self {
+        Self {
+            base_url: PUBLIC_BASE_URL.to_string(),
+            token,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            rate_limit: None,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            cache: ResponseCache::for_service("discogs"),
+        }
+    }
+
+    /// Set the root of the API from a URL, with or without a trailing
+    /// slash.
+    pub fn set_base_url(&mut self, url: &str) {
+        self.base_url = url.trim_end_matches('/').to_string();
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.base_url == PUBLIC_BASE_URL
+    }
+
+    pub fn effective_rate_limit(&self) -> Duration {
+        self.rate_limit.unwrap_or(if self.is_public() {
+            RATE_LIMIT
+        } else {
+            Duration::ZERO
+        })
+    }
+}
+
+/// A release as listed in database search results.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SearchResult {
+    pub id: u64,
+    /// Artist and title, as "Artist - Title".
+    pub title: String,
+    pub year: Option<String>,
+    pub country: Option<String>,
+    /// Format name followed by its descriptions, e.g. `["Vinyl", "LP"]`.
+    pub format: Vec<String>,
+    pub formats: Vec<Format>,
+    pub label: Vec<String>,
+    pub catno: Option<String>,
+    pub barcode: Vec<String>,
+    pub master_id: Option<u64>,
+    pub genre: Vec<String>,
+    /// Narrower genres, e.g. "Boom Bap" under "Hip Hop".
+    pub style: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct SearchResponse {
+    pagination: Pagination,
+    results: Vec<SearchResult>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct Pagination {
+    page: usize,
+    per_page: usize,
+    items: usize,
+}
+
+/// A release with its tracklist.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Release {
+    pub id: u64,
+    pub title: String,
+    pub artists: Vec<Artist>,
+    /// Release date, with unknown parts as zeros, e.g. "1987-00-00".
+    pub released: Option<String>,
+    pub year: Option<u32>,
+    pub country: Option<String>,
+    pub labels: Vec<Label>,
+    pub formats: Vec<Format>,
+    pub identifiers: Vec<Identifier>,
+    pub master_id: Option<u64>,
+    pub genres: Vec<String>,
+    pub styles: Vec<String>,
+    pub tracklist: Vec<Track>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Artist {
+    pub name: String,
+    /// Name the artist is credited as on this release, if different.
+    pub anv: String,
+    /// Joins this artist to the next, e.g. "&" or ",".
+    pub join: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Label {
+    pub name: String,
+    pub catno: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Format {
+    pub name: String,
+    /// Number of media in this format, as a string.
+    pub qty: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Identifier {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Track {
+    /// Position as printed on the release, e.g. "A1" or "2-05".
+    pub position: String,
+    /// "track", or "heading" and "index" for entries that aren't tracks.
+    #[serde(rename = "type_")]
+    pub kind: String,
+    pub title: String,
+    /// Length as "m:ss", or empty when unknown.
+    pub duration: String,
+    pub artists: Vec<Artist>,
+}
+
+/// A Discogs client.  Clones share the rate limiter, so they can be handed
+/// to concurrent tasks.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    token: String,
+    retry: RetryPolicy,
+    cache: Option<ResponseCache>,
+}
+
+impl Client {
+    pub fn new(config: &ClientConfig) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .user_agent(&config.user_agent)
+            .build()
+            .wrap_err("Failed to set up the Discogs client")?;
+        let rate_limit = config.effective_rate_limit();
+        Ok(Self {
+            http,
+            base_url: config.base_url.clone(),
+            token: config.token.clone(),
+            retry: RetryPolicy {
+                limiter: RateLimiter::new(rate_limit, 1),
+                min_delay: rate_limit,
+                max_attempts: config.max_attempts.max(1),
+            },
+            cache: config.cache.clone(),
+        })
+    }
+
+    /// Search the database for releases, with the query parameters from
+    /// [`crate::discogs::query::build`], starting at the given result.
+    pub async fn search_releases(
+        &self,
+        query: &str,
+        offset: usize,
+        on_retry: OnRetry<'_>,
+    ) -> Result<SearchPage<SearchResult>> {
+        // Discogs pages are numbered from 1 rather than offset by result.
+        let url = format!(
+            "{}/database/search?{}&per_page={}&page={}",
+            self.base_url,
+            query,
+            SEARCH_PAGE_SIZE,
+            offset / SEARCH_PAGE_SIZE + 1
+        );
+        let response: SearchResponse = self.get(url, on_retry).await?;
+        let per_page = response.pagination.per_page.max(1);
+
+        Ok(SearchPage {
+            offset: response.pagination.page.saturating_sub(1) * per_page,
+            total: response.pagination.items,
+            entities: response.results,
+        })
+    }
+
+    /// Look up a release with its tracklist.
+    pub async fn lookup_release(&self, id: &str, on_retry: OnRetry<'_>) -> Result<Release> {
+        let url = format!("{}/releases/{}", self.base_url, id);
+        Ok(self.get(url, on_retry).await?)
+    }
+
+    /// Send a request, retrying transient failures with exponential backoff.
+    ///
+    /// Responses are served from the cache when possible.
+    async fn get<T: DeserializeOwned>(
+        &self,
+        url: String,
+        on_retry: OnRetry<'_>,
+    ) -> Result<T, ApiError> {
+        if let Some(json) = self.cache.as_ref().and_then(|it| it.get(&url))
+            && let Ok(result) = serde_json::from_value(json)
+        {
+            return Ok(result);
+        }
+
+        let json = self.retry.run(on_retry, || self.get_json(&url)).await?;
+        let result = serde_json::from_value(json.clone())
+            .map_err(|e| ApiError::new(SERVICE, ErrorKind::Parse, e))?;
+        if let Some(cache) = &self.cache {
+            let _ = cache.put(&url, &json);
+        }
+        Ok(result)
+    }
+
+    async fn get_json(&self, url: &str) -> Result<serde_json::Value, ApiError> {
+        let response = self
+            .http
+            .get(url)
+            .header(AUTHORIZATION, format!("Discogs token={}", self.token))
+            .send()
+            .await
+            .map_err(|e| ApiError::new(SERVICE, ErrorKind::Network, e))?;
+        if let Some(error) = http::status_error(SERVICE, &response) {
+            return Err(error);
+        }
+        http::read_json(SERVICE, response).await
+    }
+}
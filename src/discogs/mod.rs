@@ -0,0 +1,8 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+pub mod client;
+pub mod query;
+pub mod reference;
+pub mod source;
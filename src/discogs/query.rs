@@ -0,0 +1,80 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Parameters for the Discogs database search.
+//!
+//! Discogs searches by field rather than with a query language, so each
+//! [`SearchStrategy`] becomes a set of URL parameters.  It has no fuzzy
+//! matching and doesn't index ISRCs, so those strategies are skipped.
+
+use crate::{
+    models::AlbumCluster,
+    search::SearchStrategy,
+    source::{
+        http::percent_encode,
+        normalize::{clean_whitespace, normalize_artist, normalize_title},
+    },
+};
+
+/// Build the URL parameters for a search strategy, or `None` when the
+/// cluster lacks what the strategy needs or Discogs can't search that way.
+pub fn build(strategy: SearchStrategy, cluster: &AlbumCluster) -> Option<String> {
+    let identifiers = &cluster.identifiers;
+    let artist = clean_whitespace(&cluster.album_artist);
+    let album = clean_whitespace(&cluster.album);
+    let track_artist = cluster
+        .tracks
+        .first()
+        .and_then(|it| it.artist.as_deref())
+        .map(clean_whitespace)
+        .unwrap_or_else(|| artist.clone());
+
+    let params = match strategy {
+        SearchStrategy::Barcode => {
+            let barcode: String = identifiers
+                .barcode
+                .as_ref()?
+                .chars()
+                .filter(char::is_ascii_alphanumeric)
+                .collect();
+            vec![("barcode", barcode)]
+        }
+        SearchStrategy::CatalogNumber => {
+            let mut params = vec![("catno", identifiers.catalog_number.clone()?)];
+            if let Some(label) = &identifiers.label {
+                params.push(("label", label.clone()));
+            }
+            params
+        }
+        SearchStrategy::Exact => vec![("artist", artist), ("release_title", album)],
+        SearchStrategy::Normalized => vec![
+            ("artist", normalize_artist(&artist)),
+            ("release_title", normalize_title(&album)),
+        ],
+        SearchStrategy::AlbumOnly => vec![("release_title", normalize_title(&album))],
+        SearchStrategy::Recording => {
+            let title = clean_whitespace(cluster.tracks.first()?.title.as_ref()?);
+            vec![("artist", track_artist), ("track", title)]
+        }
+        SearchStrategy::RecordingNormalized => {
+            let title = cluster.tracks.first()?.title.as_ref()?;
+            vec![
+                ("artist", normalize_artist(&track_artist)),
+                ("track", normalize_title(title)),
+            ]
+        }
+        SearchStrategy::ArtistFuzzy | SearchStrategy::Isrc | SearchStrategy::Manual => {
+            return None;
+        }
+    };
+
+    if params.iter().any(|(_, value)| value.is_empty()) {
+        return None;
+    }
+    let params: Vec<String> = params
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, percent_encode(&value)))
+        .collect();
+    Some(format!("type=release&{}", params.join("&")))
+}
@@ -0,0 +1,71 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Parsing of Discogs release IDs and URLs entered by the user.
+
+/// Parse a release ID written as on Discogs pages, e.g. `r249504` or
+/// `[r249504]`, or a URL such as
+/// `https://www.discogs.com/release/249504-Rick-Astley-Never-Gonna-Give-You-Up`.
+///
+/// Returns the numeric ID.
+pub fn parse_reference(input: &str) -> Option<String> {
+    let input = input.trim();
+    let bare = input.trim_start_matches('[').trim_end_matches(']');
+    if let Some(id) = bare.strip_prefix('r').filter(|it| is_id(it)) {
+        return Some(id.to_string());
+    }
+
+    if !input.contains("discogs.com") {
+        return None;
+    }
+    let mut segments = input
+        .split(['/', '?', '#'])
+        .skip_while(|it| *it != "release");
+    segments.next()?;
+    // The ID is followed by a slug of the artist and title.
+    let id = segments.next()?.split('-').next()?;
+    is_id(id).then(|| id.to_string())
+}
+
+fn is_id(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn release_ids_and_urls_are_parsed() {
+        let id = Some(String::from("249504"));
+        assert_eq!(parse_reference("r249504"), id);
+        assert_eq!(parse_reference(" [r249504] "), id);
+        assert_eq!(
+            parse_reference(
+                "https://www.discogs.com/release/249504-Rick-Astley-Never-Gonna-Give-You-Up"
+            ),
+            id
+        );
+        assert_eq!(
+            parse_reference("https://www.discogs.com/release/249504"),
+            id
+        );
+        assert_eq!(
+            parse_reference("https://www.discogs.com/release/249504?ev=rr#images"),
+            id
+        );
+    }
+
+    #[test]
+    fn other_input_is_not_a_reference() {
+        assert_eq!(parse_reference("249504"), None);
+        assert_eq!(parse_reference("r"), None);
+        assert_eq!(parse_reference("rick astley"), None);
+        assert_eq!(
+            parse_reference("https://www.discogs.com/master/96559"),
+            None
+        );
+        assert_eq!(parse_reference("https://example.com/release/249504"), None);
+    }
+}
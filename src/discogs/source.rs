@@ -0,0 +1,492 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Discogs as a [`MetadataSource`].
+
+use std::time::Duration;
+
+use crate::{
+    credit::UNKNOWN_ARTIST_NAME,
+    discogs::{
+        client::{Artist, Client, Format, Release, SearchResult, Track},
+        query,
+        reference::parse_reference,
+    },
+    models::AlbumCluster,
+    search::SearchStrategy,
+    source::{
+        AlbumCandidate, BoxFuture, CandidatePage, GenreTag, LabelCredit, MediumCandidate,
+        MetadataSource, OnRetry, ReleaseGroup, TrackCandidate,
+    },
+};
+
+pub const SOURCE_NAME: &str = "Discogs";
+
+/// Number of sides on each disc, for vinyl and cassette positions like
+/// "C2".
+const SIDES_PER_MEDIUM: u32 = 2;
+
+/// Which medium a track is on, going by its position.
+#[derive(Debug, Clone, PartialEq)]
+enum MediumRef {
+    /// A numbered disc, or the record a side letter is on.
+    Number(u32),
+    /// A medium named by its format, e.g. "DVD" in "DVD3".
+    Label(String),
+}
+
+impl MetadataSource for Client {
+    fn name(&self) -> &'static str {
+        SOURCE_NAME
+    }
+
+    fn query(&self, strategy: SearchStrategy, cluster: &AlbumCluster) -> Option<String> {
+        query::build(strategy, cluster)
+    }
+
+    fn search<'a>(
+        &'a self,
+        strategy: SearchStrategy,
+        query: &'a str,
+        offset: usize,
+        on_retry: OnRetry<'a>,
+    ) -> BoxFuture<'a, color_eyre::Result<CandidatePage>> {
+        Box::pin(async move {
+            let page = self.search_releases(query, offset, on_retry).await?;
+            let next_offset = page.next_offset();
+            Ok(CandidatePage {
+                candidates: page
+                    .entities
+                    .into_iter()
+                    .map(|result| search_candidate(result, strategy))
+                    .collect(),
+                next_offset,
+            })
+        })
+    }
+
+    fn lookup<'a>(&'a self, id: &'a str) -> BoxFuture<'a, color_eyre::Result<AlbumCandidate>> {
+        Box::pin(async move {
+            let release = self.lookup_release(id, &|_| {}).await?;
+            Ok(release_candidate(release, SearchStrategy::Manual))
+        })
+    }
+
+    fn lookup_reference<'a>(
+        &'a self,
+        input: &str,
+    ) -> Option<BoxFuture<'a, color_eyre::Result<Vec<AlbumCandidate>>>> {
+        let id = parse_reference(input)?;
+        Some(Box::pin(async move {
+            let release = self.lookup_release(&id, &|_| {}).await?;
+            Ok(vec![release_candidate(release, SearchStrategy::Manual)])
+        }))
+    }
+}
+
+/// A search result, which has no tracklist or track counts.
+fn search_candidate(result: SearchResult, strategy: SearchStrategy) -> AlbumCandidate {
+    // Search results only carry the artist as part of the title.
+    let (artist, title) = match result.title.split_once(" - ") {
+        Some((artist, title)) => (strip_disambiguation(artist).to_string(), title.to_string()),
+        None => (UNKNOWN_ARTIST_NAME.to_string(), result.title),
+    };
+    let mut media = media_for(&result.formats);
+    if media.is_empty()
+        && let Some(format) = result.format.first()
+    {
+        media.push(medium(1, Some(format.clone()), Vec::new()));
+    }
+
+    AlbumCandidate {
+        source: SOURCE_NAME,
+        id: result.id.to_string(),
+        group: group_for(result.master_id, &title),
+        title,
+        artist,
+        date: result.year.filter(|it| !it.is_empty()),
+        country: result.country.filter(|it| !it.is_empty()),
+        barcode: result
+            .barcode
+            .into_iter()
+            .map(|it| it.trim().to_string())
+            .find(|it| !it.is_empty()),
+        // Catalog numbers aren't tied to labels here, so only the first
+        // label gets one.
+        labels: result
+            .label
+            .into_iter()
+            .enumerate()
+            .map(|(idx, name)| LabelCredit {
+                name: Some(name),
+                catalog_number: result.catno.clone().filter(|_| idx == 0).and_then(catno),
+            })
+            .collect(),
+        media,
+        genres: genre_tags(result.genre, result.style),
+        matched_track: None,
+        strategy,
+    }
+}
+
+/// A release looked up with its tracklist.
+fn release_candidate(release: Release, strategy: SearchStrategy) -> AlbumCandidate {
+    let release_id = release.id;
+    let mut grouped: Vec<(MediumRef, MediumCandidate)> = Vec::new();
+    for track in release.tracklist.iter().filter(|it| it.kind == "track") {
+        let key = medium_ref(&track.position);
+        let track = track_candidate(release_id, track);
+        match grouped.iter_mut().find(|(it, _)| *it == key) {
+            Some((_, medium)) => {
+                medium.track_count += 1;
+                medium.tracks.push(track);
+            }
+            None => {
+                let position = match key {
+                    MediumRef::Number(it) => it,
+                    // Named media come in the order they're listed.
+                    MediumRef::Label(_) => grouped.len() as u32 + 1,
+                };
+                grouped.push((key, medium(position, None, vec![track])));
+            }
+        }
+    }
+    let mut media: Vec<MediumCandidate> = grouped.into_iter().map(|(_, it)| it).collect();
+    let formats = media_for(&release.formats);
+    if media.is_empty() {
+        media = formats;
+    } else {
+        for (idx, medium) in media.iter_mut().enumerate() {
+            medium.format = formats
+                .get(idx)
+                .or(formats.last())
+                .and_then(|it| it.format.clone());
+        }
+    }
+
+    AlbumCandidate {
+        source: SOURCE_NAME,
+        id: release_id.to_string(),
+        group: group_for(release.master_id, &release.title),
+        title: release.title,
+        artist: format_artists(&release.artists).unwrap_or_else(|| UNKNOWN_ARTIST_NAME.to_string()),
+        date: release
+            .released
+            .as_deref()
+            .map(|it| it.trim_end_matches("-00").to_string())
+            .filter(|it| !it.is_empty() && it != "0000")
+            .or(release.year.filter(|it| *it > 0).map(|it| it.to_string())),
+        country: release.country.filter(|it| !it.is_empty()),
+        barcode: release
+            .identifiers
+            .into_iter()
+            .find(|it| it.kind == "Barcode")
+            .map(|it| it.value.trim().to_string()),
+        labels: release
+            .labels
+            .into_iter()
+            .map(|it| LabelCredit {
+                name: Some(strip_disambiguation(&it.name).to_string()),
+                catalog_number: it.catno.and_then(catno),
+            })
+            .collect(),
+        media,
+        genres: genre_tags(release.genres, release.styles),
+        matched_track: None,
+        strategy,
+    }
+}
+
+/// Discogs doesn't count votes, so every genre and style counts once.
+fn genre_tags(genres: Vec<String>, styles: Vec<String>) -> Vec<GenreTag> {
+    genres
+        .into_iter()
+        .chain(styles)
+        .map(|name| GenreTag { name, count: 1 })
+        .collect()
+}
+
+fn track_candidate(release_id: u64, track: &Track) -> TrackCandidate {
+    TrackCandidate {
+        // Tracks have no IDs of their own.
+        id: format!("{}/{}", release_id, track.position),
+        title: track.title.clone(),
+        artist: format_artists(&track.artists),
+        number: track.position.clone(),
+        length: parse_duration(&track.duration),
+    }
+}
+
+fn medium(position: u32, format: Option<String>, tracks: Vec<TrackCandidate>) -> MediumCandidate {
+    MediumCandidate {
+        position: Some(position),
+        title: None,
+        format,
+        track_count: tracks.len() as u32,
+        tracks,
+    }
+}
+
+/// One medium for each item of each format, e.g. two for "2×Vinyl".
+fn media_for(formats: &[Format]) -> Vec<MediumCandidate> {
+    let mut media = Vec::new();
+    for format in formats {
+        let quantity: u32 = format
+            .qty
+            .as_deref()
+            .and_then(|it| it.trim().parse().ok())
+            .unwrap_or(1);
+        for _ in 0..quantity {
+            media.push(medium(
+                media.len() as u32 + 1,
+                Some(format.name.clone()),
+                Vec::new(),
+            ));
+        }
+    }
+    media
+}
+
+/// The medium a track position is on: "2-05" and "CD2-5" are on the second
+/// disc, "C1" is on the second record, "DVD1" is on the DVD, and plain
+/// numbers are on the first disc.
+fn medium_ref(position: &str) -> MediumRef {
+    let position = position.trim();
+    if let Some((medium, _)) = position.split_once(['-', '.']) {
+        return MediumRef::Number(
+            medium
+                .trim_start_matches(|c: char| !c.is_ascii_digit())
+                .parse()
+                .unwrap_or(1)
+                .max(1),
+        );
+    }
+
+    let prefix: String = position
+        .chars()
+        .take_while(char::is_ascii_alphabetic)
+        .collect();
+    // A repeated letter is a side too, like the "AA" side of a single.
+    let is_side = prefix.chars().all(|c| prefix.starts_with(c));
+    if !is_side && prefix.len() < position.len() {
+        return MediumRef::Label(prefix);
+    }
+    match prefix.chars().next() {
+        Some(side) if side.is_ascii_uppercase() => {
+            MediumRef::Number((side as u32 - 'A' as u32) / SIDES_PER_MEDIUM + 1)
+        }
+        _ => MediumRef::Number(1),
+    }
+}
+
+/// The master release, which collects every edition of a release.
+fn group_for(master_id: Option<u64>, title: &str) -> Option<ReleaseGroup> {
+    master_id.filter(|it| *it > 0).map(|id| ReleaseGroup {
+        id: format!("master/{}", id),
+        title: title.to_string(),
+        kind: None,
+    })
+}
+
+/// Join artists into a single name, as Discogs displays them.
+fn format_artists(artists: &[Artist]) -> Option<String> {
+    if artists.is_empty() {
+        return None;
+    }
+    let mut name = String::new();
+    for (idx, artist) in artists.iter().enumerate() {
+        name.push_str(if artist.anv.is_empty() {
+            strip_disambiguation(&artist.name)
+        } else {
+            &artist.anv
+        });
+        if idx + 1 < artists.len() {
+            match artist.join.trim() {
+                "," => name.push_str(", "),
+                "" => name.push(' '),
+                join => name.push_str(&format!(" {} ", join)),
+            }
+        }
+    }
+    Some(name)
+}
+
+/// Drop the number Discogs appends to tell artists and labels with the
+/// same name apart, e.g. "Nirvana (2)".
+fn strip_disambiguation(name: &str) -> &str {
+    match name
+        .trim_end()
+        .strip_suffix(')')
+        .and_then(|it| it.rsplit_once(" ("))
+    {
+        Some((base, number))
+            if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            base
+        }
+        _ => name.trim_end(),
+    }
+}
+
+/// Discogs writes "none" for releases without a catalog number.
+fn catno(catno: String) -> Option<String> {
+    let catno = catno.trim();
+    (!catno.is_empty() && !catno.eq_ignore_ascii_case("none")).then(|| catno.to_string())
+}
+
+/// Parse a duration like "4:20" or "1:02:03".
+fn parse_duration(text: &str) -> Option<Duration> {
+    let mut seconds: u64 = 0;
+    for part in text.trim().split(':') {
+        seconds = seconds * 60 + part.parse::<u64>().ok()?;
+    }
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        discogs::client::ClientConfig,
+        musicbrainz::fake::{FakeServer, Fixture},
+    };
+
+    fn client(server: &FakeServer) -> Client {
+        let mut config = ClientConfig::new(String::from("token"));
+        config.set_base_url(&server.url());
+        config.cache = None;
+        Client::new(&config).unwrap()
+    }
+
+    fn numbers(medium: &MediumCandidate) -> Vec<&str> {
+        medium.tracks.iter().map(|it| it.number.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn search_pages_are_converted_to_offsets() {
+        let server = FakeServer::start(Fixture::load_for("discogs", "release_search")).await;
+        let client = client(&server);
+        let query = "artist=Rick%20Astley";
+
+        let first = client
+            .search(SearchStrategy::Normalized, query, 0, &|_| {})
+            .await
+            .unwrap();
+        let second = client
+            .search(SearchStrategy::Normalized, query, 25, &|_| {})
+            .await
+            .unwrap();
+
+        assert_eq!(first.candidates.len(), 25);
+        assert_eq!(first.next_offset, Some(25));
+        assert_eq!(second.candidates.len(), 2);
+        assert_eq!(second.next_offset, None);
+        let requests = server.requests();
+        assert!(requests[0].ends_with("&per_page=25&page=1"));
+        assert!(requests[1].ends_with("&per_page=25&page=2"));
+
+        let candidate = &first.candidates[0];
+        assert_eq!(candidate.id, "249504");
+        assert_eq!(candidate.artist, "Rick Astley");
+        assert_eq!(candidate.title, "Whenever You Need Somebody");
+        assert_eq!(
+            candidate.labels[0].catalog_number.as_deref(),
+            Some("PL 71529")
+        );
+        assert_eq!(first.candidates[1].labels[0].catalog_number, None);
+        assert_eq!(candidate.media.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn sides_are_grouped_into_records() {
+        let server = FakeServer::start(Fixture::load_for("discogs", "release_lookup")).await;
+
+        let candidate = client(&server).lookup("249504").await.unwrap();
+
+        assert_eq!(candidate.artist, "Rick Astley");
+        assert_eq!(candidate.date.as_deref(), Some("1987-11"));
+        assert_eq!(candidate.barcode.as_deref(), Some("5012394152922"));
+        assert_eq!(candidate.labels[0].name.as_deref(), Some("RCA"));
+        assert_eq!(candidate.group.as_ref().unwrap().id, "master/96559");
+        // The heading isn't a track.
+        assert_eq!(candidate.track_count(), 5);
+        assert_eq!(candidate.media.len(), 2);
+        assert_eq!(numbers(&candidate.media[0]), ["A1", "A2", "B1"]);
+        assert_eq!(numbers(&candidate.media[1]), ["C1", "D1"]);
+        assert!(
+            candidate
+                .media
+                .iter()
+                .all(|it| it.format.as_deref() == Some("Vinyl"))
+        );
+
+        let tracks = &candidate.media[1].tracks;
+        assert_eq!(tracks[0].id, "249504/C1");
+        assert_eq!(
+            tracks[0].artist.as_deref(),
+            Some("Rick & Stock, Aitken & Waterman")
+        );
+        assert_eq!(tracks[0].length, Some(Duration::from_secs(220)));
+        assert_eq!(tracks[1].length, None);
+    }
+
+    #[tokio::test]
+    async fn numbered_and_named_discs_are_separate_media() {
+        let server = FakeServer::start(Fixture::load_for("discogs", "release_lookup")).await;
+
+        let candidate = client(&server).lookup("1000").await.unwrap();
+
+        let media = &candidate.media;
+        assert_eq!(
+            media.iter().map(|it| it.position).collect::<Vec<_>>(),
+            [Some(1), Some(2), Some(3)]
+        );
+        assert_eq!(numbers(&media[0]), ["1-01", "1-02"]);
+        assert_eq!(numbers(&media[1]), ["2-01"]);
+        assert_eq!(numbers(&media[2]), ["DVD1", "DVD2"]);
+        assert_eq!(
+            media
+                .iter()
+                .map(|it| it.format.as_deref())
+                .collect::<Vec<_>>(),
+            [Some("CD"), Some("CD"), Some("DVD")]
+        );
+        assert_eq!(candidate.date.as_deref(), Some("2004"));
+        assert_eq!(candidate.labels[0].catalog_number, None);
+    }
+
+    #[test]
+    fn track_positions_give_the_medium() {
+        let number = MediumRef::Number;
+        assert_eq!(medium_ref("5"), number(1));
+        assert_eq!(medium_ref("2-05"), number(2));
+        assert_eq!(medium_ref("CD2-5"), number(2));
+        assert_eq!(medium_ref("3.1"), number(3));
+        assert_eq!(medium_ref("A1"), number(1));
+        assert_eq!(medium_ref("B2"), number(1));
+        assert_eq!(medium_ref("C1"), number(2));
+        assert_eq!(medium_ref("AA"), number(1));
+        assert_eq!(medium_ref("AA1"), number(1));
+        assert_eq!(medium_ref("CD1"), MediumRef::Label(String::from("CD")));
+        assert_eq!(medium_ref("DVD12"), MediumRef::Label(String::from("DVD")));
+    }
+
+    #[test]
+    fn durations_are_parsed() {
+        assert_eq!(parse_duration("4:20"), Some(Duration::from_secs(260)));
+        assert_eq!(parse_duration(" 1:02:03 "), Some(Duration::from_secs(3723)));
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("0:00"), None);
+        assert_eq!(parse_duration("4m20s"), None);
+    }
+
+    #[test]
+    fn disambiguation_numbers_are_stripped() {
+        assert_eq!(strip_disambiguation("Nirvana (2)"), "Nirvana");
+        assert_eq!(strip_disambiguation("Nirvana (12) "), "Nirvana");
+        assert_eq!(strip_disambiguation("Love (Live)"), "Love (Live)");
+        assert_eq!(strip_disambiguation("Band ()"), "Band ()");
+        assert_eq!(strip_disambiguation("(2)"), "(2)");
+    }
+}
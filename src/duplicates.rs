@@ -0,0 +1,315 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Detection of duplicate audio across album clusters.
+
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use crate::{codecs::codec_name, models::AlbumCluster};
+
+/// Maximum difference between two track durations for them to be
+/// considered the same recording.
+const DURATION_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// Relative difference between average bitrates above which two clusters are
+/// reported as having different bitrates.
+const BITRATE_TOLERANCE: f64 = 0.1;
+
+#[derive(Debug, Clone, Default)]
+pub struct DuplicateReport {
+    /// Files whose audio payloads are byte-for-byte identical.
+    pub identical: Vec<IdenticalAudio>,
+    /// Clusters that appear to hold the same album in a different encoding.
+    pub near: Vec<NearDuplicate>,
+}
+
+impl DuplicateReport {
+    pub fn is_empty(&self) -> bool {
+        self.identical.is_empty() && self.near.is_empty()
+    }
+}
+
+/// A group of files sharing the same audio digest, spread over at least two
+/// clusters.
+#[derive(Debug, Clone)]
+pub struct IdenticalAudio {
+    pub hash: String,
+    /// Each file along with the index of its cluster.
+    pub files: Vec<(usize, PathBuf)>,
+}
+
+/// A pair of clusters with the same album artist and title and matching
+/// track durations.
+#[derive(Debug, Clone)]
+pub struct NearDuplicate {
+    /// Indices into the analysed clusters.
+    pub clusters: (usize, usize),
+    pub differences: Vec<String>,
+}
+
+/// Find identical audio and near-duplicate clusters.
+pub fn find_duplicates(clusters: &[AlbumCluster]) -> DuplicateReport {
+    DuplicateReport {
+        identical: find_identical(clusters),
+        near: find_near_duplicates(clusters),
+    }
+}
+
+fn find_identical(clusters: &[AlbumCluster]) -> Vec<IdenticalAudio> {
+    let mut by_hash: HashMap<&str, Vec<(usize, PathBuf)>> = HashMap::new();
+
+    for (idx, cluster) in clusters.iter().enumerate() {
+        for track in &cluster.tracks {
+            if let Some(digest) = &track.audio_digest {
+                by_hash
+                    .entry(digest.hash.as_str())
+                    .or_default()
+                    .push((idx, track.path.clone()));
+            }
+        }
+    }
+
+    let mut groups: Vec<IdenticalAudio> = by_hash
+        .into_iter()
+        // Copies within one cluster are the album's own business.
+        .filter(|(_, files)| files.iter().any(|(idx, _)| *idx != files[0].0))
+        .map(|(hash, files)| IdenticalAudio {
+            hash: hash.to_string(),
+            files,
+        })
+        .collect();
+    groups.sort_by(|a, b| a.files.cmp(&b.files));
+    groups
+}
+
+fn find_near_duplicates(clusters: &[AlbumCluster]) -> Vec<NearDuplicate> {
+    let mut by_album: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    for (idx, cluster) in clusters.iter().enumerate() {
+        by_album
+            .entry((normalize(&cluster.album_artist), normalize(&cluster.album)))
+            .or_default()
+            .push(idx);
+    }
+
+    let mut near = Vec::new();
+    for indices in by_album.values() {
+        for (i, &a) in indices.iter().enumerate() {
+            for &b in &indices[i + 1..] {
+                let (first, second) = (&clusters[a], &clusters[b]);
+                if !durations_match(first, second) {
+                    continue;
+                }
+                let differences = encoding_differences(first, second);
+                if !differences.is_empty() {
+                    near.push(NearDuplicate {
+                        clusters: (a.min(b), a.max(b)),
+                        differences,
+                    });
+                }
+            }
+        }
+    }
+    near.sort_by_key(|it| it.clusters);
+    near
+}
+
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn durations_match(a: &AlbumCluster, b: &AlbumCluster) -> bool {
+    a.track_count() == b.track_count()
+        && a.total_discs == b.total_discs
+        && a.tracks
+            .iter()
+            .zip(&b.tracks)
+            .all(|(x, y)| match (x.duration, y.duration) {
+                (Some(x), Some(y)) => x.abs_diff(y) <= DURATION_TOLERANCE,
+                _ => false,
+            })
+}
+
+fn encoding_differences(a: &AlbumCluster, b: &AlbumCluster) -> Vec<String> {
+    let mut differences = Vec::new();
+
+    let format = |cluster: &AlbumCluster| {
+        cluster
+            .codec()
+            .map(codec_name)
+            .unwrap_or_else(|| String::from("Mixed"))
+    };
+    if a.codec() != b.codec() {
+        differences.push(format!("format {} vs {}", format(a), format(b)));
+    }
+
+    if let (Some(x), Some(y)) = (a.average_bitrate(), b.average_bitrate()) {
+        let (lo, hi) = (x.min(y) as f64, x.max(y) as f64);
+        if hi > 0.0 && (hi - lo) / hi > BITRATE_TOLERANCE {
+            differences.push(format!("bitrate {} vs {} kbps", x, y));
+        }
+    }
+
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        codecs::AudioCodec,
+        models::{AudioDigest, AudioFile, MusicBrainzIds, ReleaseIdentifiers, ReplayGain},
+    };
+
+    /// A file lasting `millis`, whose audio payload has the given hash and
+    /// works out at `kbps`.
+    fn file(path: &str, codec: AudioCodec, millis: u64, hash: &str, kbps: u64) -> AudioFile {
+        AudioFile {
+            path: PathBuf::from(path),
+            codec,
+            title: None,
+            artist: None,
+            album_artist: None,
+            album: None,
+            track_number: None,
+            total_tracks: None,
+            disc_number: None,
+            total_discs: None,
+            genre: None,
+            year: None,
+            duration: Some(Duration::from_millis(millis)),
+            duration_source: None,
+            legacy_encoding: None,
+            replay_gain: ReplayGain::default(),
+            identifiers: ReleaseIdentifiers::default(),
+            isrc: None,
+            musicbrainz: MusicBrainzIds::default(),
+            audio_digest: Some(AudioDigest {
+                hash: String::from(hash),
+                size: kbps * millis / 8,
+            }),
+        }
+    }
+
+    fn cluster(artist: &str, album: &str, tracks: Vec<AudioFile>) -> AlbumCluster {
+        AlbumCluster {
+            album: String::from(album),
+            album_artist: String::from(artist),
+            tracks,
+            base_path: PathBuf::from("/music"),
+            total_discs: 1,
+            encoding_suggestion: None,
+            identifiers: ReleaseIdentifiers::default(),
+        }
+    }
+
+    /// One cluster of a single FLAC track lasting `millis` at `kbps`.
+    fn album(millis: u64, kbps: u64) -> AlbumCluster {
+        let path = format!("/music/{}-{}.flac", millis, kbps);
+        cluster(
+            "The Testers",
+            "Fixture Songs",
+            vec![file(&path, AudioCodec::Flac, millis, &path, kbps)],
+        )
+    }
+
+    #[test]
+    fn identical_audio_across_clusters_is_grouped() {
+        let clusters = [
+            cluster(
+                "The Testers",
+                "Fixture Songs",
+                vec![
+                    file("/a/1.flac", AudioCodec::Flac, 200_000, "same", 900),
+                    file("/a/2.flac", AudioCodec::Flac, 250_000, "other", 900),
+                ],
+            ),
+            cluster(
+                "Various Artists",
+                "Test Hits",
+                vec![file("/b/7.flac", AudioCodec::Flac, 200_000, "same", 900)],
+            ),
+        ];
+
+        let report = find_duplicates(&clusters);
+
+        assert_eq!(report.identical.len(), 1);
+        assert_eq!(report.identical[0].hash, "same");
+        assert_eq!(
+            report.identical[0].files,
+            [
+                (0, PathBuf::from("/a/1.flac")),
+                (1, PathBuf::from("/b/7.flac"))
+            ]
+        );
+        assert!(report.near.is_empty());
+    }
+
+    #[test]
+    fn copies_inside_one_cluster_are_ignored() {
+        let clusters = [cluster(
+            "The Testers",
+            "Fixture Songs",
+            vec![
+                file("/a/1.flac", AudioCodec::Flac, 200_000, "same", 900),
+                file("/a/1 (copy).flac", AudioCodec::Flac, 200_000, "same", 900),
+            ],
+        )];
+
+        assert!(find_duplicates(&clusters).is_empty());
+    }
+
+    #[test]
+    fn near_duplicates_share_artist_and_album() {
+        let track = |path, codec| file(path, codec, 200_000, path, 900);
+        let clusters = [
+            cluster(
+                "The Testers",
+                "Fixture Songs",
+                vec![track("/a/1.flac", AudioCodec::Flac)],
+            ),
+            // Another artist's album of the same name isn't a copy.
+            cluster(
+                "Other Band",
+                "Fixture Songs",
+                vec![track("/b/1.mp3", AudioCodec::Mp3)],
+            ),
+            // Case and punctuation don't matter.
+            cluster(
+                "the testers!",
+                "FIXTURE SONGS",
+                vec![track("/c/1.mp3", AudioCodec::Mp3)],
+            ),
+        ];
+
+        let report = find_duplicates(&clusters);
+
+        assert_eq!(report.near.len(), 1);
+        assert_eq!(report.near[0].clusters, (0, 2));
+        assert_eq!(report.near[0].differences, ["format FLAC vs MP3"]);
+    }
+
+    #[test]
+    fn durations_and_bitrates_have_a_tolerance() {
+        let near = |a: AlbumCluster, b: AlbumCluster| {
+            find_duplicates(&[a, b])
+                .near
+                .into_iter()
+                .map(|it| it.differences)
+                .collect::<Vec<_>>()
+        };
+
+        // Two seconds apart is still the same track, and a tenth less
+        // bitrate is still the same encoding.
+        assert!(near(album(200_000, 1000), album(202_000, 900)).is_empty());
+        assert_eq!(
+            near(album(200_000, 1000), album(202_000, 899)),
+            [["bitrate 1000 vs 899 kbps"]]
+        );
+        assert!(near(album(200_000, 1000), album(202_001, 899)).is_empty());
+    }
+}
@@ -0,0 +1,260 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Picking album genres from the genres and tags sources attach to
+//! releases.
+//!
+//! Tags are free text, so most of them aren't genres at all.  They are
+//! matched against a tree of known genres, which also gives each genre its
+//! canonical spelling, e.g. "hip hop" and "hiphop" both become "Hip-Hop".
+//! When a whitelist is given, genres left off it fall back to their nearest
+//! whitelisted parent, so "Boom Bap" becomes "Hip-Hop" if only broad genres
+//! are allowed.
+//!
+//! The tree is written one genre per line, with children indented under
+//! their parent.  Other spellings follow an `=`, separated by commas:
+//!
+//! ```text
+//! Hip-Hop = rap
+//!   Boom Bap
+//!   Trap
+//! ```
+
+use std::{collections::HashMap, fs, path::Path};
+
+use color_eyre::eyre::{Result, WrapErr, bail};
+
+use crate::{paths::escape_path, source::GenreTag};
+
+/// The tree used unless another is given.
+const DEFAULT_TREE: &str = include_str!("genres.txt");
+
+/// How many genres are proposed unless told otherwise.
+pub const DEFAULT_GENRE_COUNT: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct GenreTree {
+    genres: Vec<Genre>,
+    /// Index into `genres` by [`key`] of each name and spelling.
+    by_key: HashMap<String, usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Genre {
+    name: String,
+    parent: Option<usize>,
+    allowed: bool,
+}
+
+impl Default for GenreTree {
+    fn default() -> Self {
+        Self::parse(DEFAULT_TREE).expect("built-in genre tree is valid")
+    }
+}
+
+impl GenreTree {
+    /// Read a tree from a file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read genre tree '{}'", escape_path(path)))?;
+        Self::parse(&text).wrap_err_with(|| format!("Invalid genre tree '{}'", escape_path(path)))
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut tree = Self {
+            genres: Vec::new(),
+            by_key: HashMap::new(),
+        };
+        // Indentation and index of each genre on the path to the current
+        // line.
+        let mut ancestors: Vec<(usize, usize)> = Vec::new();
+
+        for (line_idx, line) in text.lines().enumerate() {
+            let content = line.split('#').next().unwrap_or_default();
+            if content.trim().is_empty() {
+                continue;
+            }
+            let indent = content.len() - content.trim_start().len();
+            while ancestors.last().is_some_and(|(it, _)| *it >= indent) {
+                ancestors.pop();
+            }
+
+            let (name, aliases) = match content.split_once('=') {
+                Some((name, aliases)) => (name.trim(), aliases),
+                None => (content.trim(), ""),
+            };
+            if name.is_empty() {
+                bail!("Line {}: missing genre name", line_idx + 1);
+            }
+            let idx = tree.genres.len();
+            for spelling in std::iter::once(name).chain(aliases.split(',').map(str::trim)) {
+                let key = key(spelling);
+                if key.is_empty() {
+                    continue;
+                }
+                if let Some(other) = tree.by_key.insert(key, idx)
+                    && other != idx
+                {
+                    bail!(
+                        "Line {}: '{}' is already listed under '{}'",
+                        line_idx + 1,
+                        spelling,
+                        tree.genres[other].name
+                    );
+                }
+            }
+            tree.genres.push(Genre {
+                name: name.to_string(),
+                parent: ancestors.last().map(|(_, it)| *it),
+                allowed: true,
+            });
+            ancestors.push((indent, idx));
+        }
+        Ok(tree)
+    }
+
+    /// Only allow the genres listed in a file, one per line.  Genres that
+    /// aren't in the tree are added without a parent.
+    pub fn load_whitelist(&mut self, path: &Path) -> Result<()> {
+        let text = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read genre whitelist '{}'", escape_path(path)))?;
+        self.set_whitelist(
+            text.lines()
+                .map(|it| it.split('#').next().unwrap_or_default().trim())
+                .filter(|it| !it.is_empty()),
+        );
+        Ok(())
+    }
+
+    fn set_whitelist<'a>(&mut self, names: impl IntoIterator<Item = &'a str>) {
+        for genre in &mut self.genres {
+            genre.allowed = false;
+        }
+        for name in names {
+            match self.by_key.get(&key(name)) {
+                Some(&idx) => self.genres[idx].allowed = true,
+                None => {
+                    self.by_key.insert(key(name), self.genres.len());
+                    self.genres.push(Genre {
+                        name: name.to_string(),
+                        parent: None,
+                        allowed: true,
+                    });
+                }
+            }
+        }
+    }
+
+    /// The canonical, allowed genre for a tag, or `None` if the tag isn't a
+    /// known genre or neither it nor any of its parents are allowed.
+    pub fn canonicalize(&self, tag: &str) -> Option<&str> {
+        let mut idx = *self.by_key.get(&key(tag))?;
+        loop {
+            let genre = &self.genres[idx];
+            if genre.allowed {
+                return Some(&genre.name);
+            }
+            idx = genre.parent?;
+        }
+    }
+
+    /// The genres with the most votes, at most `count` of them.
+    pub fn propose(&self, tags: &[GenreTag], count: usize) -> Vec<String> {
+        let mut votes: Vec<(&str, u32)> = Vec::new();
+        for tag in tags {
+            let Some(name) = self.canonicalize(&tag.name) else {
+                continue;
+            };
+            // Tags can share a canonical genre, e.g. "rap" and "hip hop".
+            match votes.iter_mut().find(|(it, _)| *it == name) {
+                Some((_, total)) => *total += tag.count,
+                None => votes.push((name, tag.count)),
+            }
+        }
+        // The sort is stable, so ties keep the order the source gave.
+        votes.sort_by_key(|(_, total)| std::cmp::Reverse(*total));
+        votes
+            .into_iter()
+            .take(count)
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+}
+
+/// Spellings that differ only in case, spacing or punctuation are the same
+/// genre.
+fn key(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str, count: u32) -> GenreTag {
+        GenreTag {
+            name: String::from(name),
+            count,
+        }
+    }
+
+    #[test]
+    fn built_in_tree_parses() {
+        let tree = GenreTree::default();
+        assert!(tree.genres.len() > 100);
+        assert_eq!(tree.canonicalize("Rock"), Some("Rock"));
+    }
+
+    #[test]
+    fn spellings_become_the_canonical_name() {
+        let tree = GenreTree::default();
+        assert_eq!(tree.canonicalize("hip hop"), Some("Hip-Hop"));
+        assert_eq!(tree.canonicalize("HIPHOP"), Some("Hip-Hop"));
+        assert_eq!(tree.canonicalize("rap"), Some("Hip-Hop"));
+        assert_eq!(tree.canonicalize("dirty south"), Some("Southern Hip-Hop"));
+        assert_eq!(tree.canonicalize("seen live"), None);
+    }
+
+    #[test]
+    fn genres_off_the_whitelist_fall_back_to_a_parent() {
+        let mut tree = GenreTree::default();
+        tree.set_whitelist(["Hip-Hop", "Chiptune"]);
+
+        assert_eq!(tree.canonicalize("Boom Bap"), Some("Hip-Hop"));
+        assert_eq!(tree.canonicalize("jazz hip-hop"), Some("Hip-Hop"));
+        assert_eq!(tree.canonicalize("indie rock"), None);
+        // Whitelisted genres the tree lacks are added as they are.
+        assert_eq!(tree.canonicalize("chiptune"), Some("Chiptune"));
+    }
+
+    #[test]
+    fn votes_for_spellings_of_one_genre_add_up() {
+        let tree = GenreTree::default();
+        let tags = [
+            tag("rock", 3),
+            tag("rap", 2),
+            tag("hip hop", 2),
+            tag("seen live", 9),
+            tag("boom bap", 1),
+        ];
+
+        assert_eq!(tree.propose(&tags, 3), ["Hip-Hop", "Rock", "Boom Bap"]);
+        assert_eq!(tree.propose(&tags, 1), ["Hip-Hop"]);
+    }
+
+    #[test]
+    fn indentation_gives_the_parent() {
+        let mut tree =
+            GenreTree::parse("Electronic = electronica\n  House\n    Deep House\n  Techno\n")
+                .unwrap();
+        tree.set_whitelist(["Electronic", "House"]);
+
+        assert_eq!(tree.canonicalize("deep house"), Some("House"));
+        assert_eq!(tree.canonicalize("techno"), Some("Electronic"));
+        assert!(GenreTree::parse("Rock\nrock = hard rock\n").is_err());
+    }
+}
@@ -2,46 +2,208 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
-use std::{env, path::PathBuf};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use color_eyre::eyre::Result;
 use color_eyre::eyre::WrapErr;
 use color_eyre::eyre::bail;
+use color_eyre::eyre::eyre;
+
+use crate::{
+    config::Config,
+    matching::Weights,
+    musicbrainz::{cache::ResponseCache, client::ClientConfig, coverart::CoverArtConfig, dump},
+    paths::escape_path,
+};
 
 mod app;
 mod codecs;
+mod config;
 mod credit;
+mod discogs;
+mod duplicates;
+mod genres;
+mod matching;
 mod models;
 mod musicbrainz;
+mod paths;
 mod scanner;
+mod search;
+mod source;
 mod ui;
 
 fn main() -> Result<()> {
     color_eyre::install()?;
+    // Parse arguments first so errors aren't swallowed by the alternate screen.
+    let config = parse_args()?;
+    if config.clear_cache {
+        for cache in ["musicbrainz", "discogs", "coverart"]
+            .into_iter()
+            .filter_map(ResponseCache::for_service)
+        {
+            cache.clear()?;
+        }
+    }
+    if let Some(path) = &config.import_dump {
+        return import_dump(path, &config);
+    }
+    let mut app = app::App::new(config)?;
     let terminal = ratatui::init();
-    let scan_path = parse_args()?;
-    let mut app = app::App::new(scan_path);
     let app_res = app.run(terminal);
     ratatui::restore();
     app_res
 }
 
-fn parse_args() -> Result<PathBuf> {
-    let args: Vec<String> = env::args().collect();
+fn parse_args() -> Result<Config> {
+    let mut path = None;
+    let mut follow_symlinks = false;
+    let mut musicbrainz = ClientConfig::default();
+    let mut clear_cache = false;
+    let mut import_dump = None;
+    let mut offline = false;
+    let mut dump_dir = None;
+    let mut discogs_token = env::var("DISCOGS_TOKEN").ok().filter(|it| !it.is_empty());
+    let mut discogs_url = None;
+    let mut cover_art = CoverArtConfig::default();
+    let mut genre_tree = None;
+    let mut genre_whitelist = None;
+    let mut genre_count = None;
+    let mut weights = Weights::default();
+
+    // Paths aren't necessarily valid UTF-8, so keep arguments as `OsString`s.
+    let mut args = env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| match args.next().map(|it| it.into_string()) {
+            Some(Ok(value)) => Ok(value),
+            Some(Err(_)) => Err(eyre!("Value for '{}' is not valid UTF-8", flag)),
+            None => Err(eyre!("Missing value for '{}'", flag)),
+        };
+        match arg.to_str() {
+            Some("-L" | "--follow-symlinks") => follow_symlinks = true,
+            Some("--mb-server") => musicbrainz.set_server(&value("--mb-server")?),
+            Some("--no-cache") => musicbrainz.cache = None,
+            Some("--clear-cache") => clear_cache = true,
+            Some("--import-dump") => import_dump = Some(PathBuf::from(value("--import-dump")?)),
+            Some("--offline") => offline = true,
+            Some("--dump-dir") => dump_dir = Some(PathBuf::from(value("--dump-dir")?)),
+            Some("--caa-url") => cover_art.set_base_url(&value("--caa-url")?),
+            Some("--discogs-token") => discogs_token = Some(value("--discogs-token")?),
+            Some("--discogs-url") => discogs_url = Some(value("--discogs-url")?),
+            Some("--genre-tree") => genre_tree = Some(PathBuf::from(value("--genre-tree")?)),
+            Some("--genre-whitelist") => {
+                genre_whitelist = Some(PathBuf::from(value("--genre-whitelist")?));
+            }
+            Some("--genres") => {
+                genre_count = Some(
+                    value("--genres")?
+                        .parse()
+                        .map_err(|_| eyre!("'--genres' takes a number of genres"))?,
+                );
+            }
+            Some("--weight") => weights.set(&value("--weight")?)?,
+            Some("--user-agent") => musicbrainz.user_agent = value("--user-agent")?,
+            Some("--rate-limit") => {
+                let secs: f64 = value("--rate-limit")?
+                    .parse()
+                    .ok()
+                    .filter(|it: &f64| it.is_finite() && *it >= 0.0)
+                    .ok_or_else(|| eyre!("'--rate-limit' takes a number of seconds"))?;
+                musicbrainz.rate_limit = Some(Duration::from_secs_f64(secs));
+            }
+            Some("--burst") => {
+                musicbrainz.burst = value("--burst")?
+                    .parse()
+                    .ok()
+                    .filter(|it| *it > 0)
+                    .ok_or_else(|| eyre!("'--burst' takes a positive number of requests"))?;
+            }
+            Some(flag) if flag.starts_with('-') => bail!("Unknown option '{}'", flag),
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => bail!("Unexpected argument '{}'", escape_path(arg.as_ref())),
+        }
+    }
 
-    let path = if args.len() > 1 {
-        PathBuf::from(&args[1])
-    } else {
-        env::current_dir().context("Failed to get current directory")?
+    let path = match path {
+        Some(path) => path,
+        None => env::current_dir().context("Failed to get current directory")?,
     };
 
     if !path.exists() {
-        bail!("Path '{}' does not exist", path.display());
+        bail!("Path '{}' does not exist", escape_path(&path));
     }
 
     if !path.is_dir() {
-        bail!("Path '{}' is not a directory", path.display());
+        bail!("Path '{}' is not a directory", escape_path(&path));
     }
 
-    Ok(path)
+    let discogs = match (discogs_token, discogs_url) {
+        (Some(token), url) => {
+            let mut discogs = discogs::client::ClientConfig::new(token);
+            if let Some(url) = url {
+                discogs.set_base_url(&url);
+            }
+            discogs.user_agent = musicbrainz.user_agent.clone();
+            // '--no-cache' applies to every service.
+            if musicbrainz.cache.is_none() {
+                discogs.cache = None;
+            }
+            Some(discogs)
+        }
+        (None, Some(_)) => bail!("'--discogs-url' needs a token from '--discogs-token'"),
+        (None, None) => None,
+    };
+
+    cover_art.user_agent = musicbrainz.user_agent.clone();
+    if musicbrainz.cache.is_none() {
+        cover_art.cache = None;
+    }
+
+    let mut config = Config::new(path);
+    config.follow_symlinks = follow_symlinks;
+    config.musicbrainz = musicbrainz;
+    config.cover_art = Some(cover_art);
+    config.discogs = discogs;
+    config.clear_cache = clear_cache;
+    config.import_dump = import_dump;
+    if dump_dir.is_some() {
+        config.dump_dir = dump_dir;
+    }
+    config.genre_tree = genre_tree;
+    config.genre_whitelist = genre_whitelist;
+    if let Some(count) = genre_count {
+        config.genre_count = count;
+    }
+    config.weights = weights;
+    if offline {
+        let Some(dir) = &config.dump_dir else {
+            bail!("No location for the offline store; pass --dump-dir");
+        };
+        config.musicbrainz.offline_dump = Some(dir.clone());
+        config.cover_art = None;
+        config.discogs = None;
+    }
+    Ok(config)
+}
+
+/// Import a release dump into the offline store, reporting progress on the
+/// terminal.
+fn import_dump(path: &Path, config: &Config) -> Result<()> {
+    let Some(dir) = &config.dump_dir else {
+        bail!("No location for the offline store; pass --dump-dir");
+    };
+    eprintln!(
+        "Importing {} into {}...",
+        escape_path(path),
+        escape_path(dir)
+    );
+    let summary = dump::import(path, dir, |count| eprintln!("{} releases", count))?;
+    eprintln!(
+        "Imported {} releases ({} lines skipped)",
+        summary.releases, summary.skipped
+    );
+    Ok(())
 }
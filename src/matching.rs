@@ -0,0 +1,842 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Scoring how well a candidate release matches a cluster.
+//!
+//! Each field is compared on its own, giving a similarity from 0 to 1, and
+//! the score is the average of the similarities weighted by [`Weights`].
+//! Fields that either side lacks, such as the track titles of a search
+//! result without a tracklist, are left out rather than counted as
+//! mismatches.
+
+use std::{fmt, time::Duration};
+
+use color_eyre::eyre::{Result, bail, eyre};
+
+use crate::{
+    models::{AlbumCluster, AudioFile},
+    source::{
+        AlbumCandidate, TrackCandidate,
+        normalize::{normalize_artist, normalize_title},
+    },
+};
+
+/// Durations this close are the same track, allowing for how rips and
+/// releases round their lengths.
+const DURATION_TOLERANCE: Duration = Duration::from_secs(3);
+
+/// Durations this much further apart than the tolerance have nothing in
+/// common.
+const DURATION_LIMIT: Duration = Duration::from_secs(30);
+
+/// Years this far apart have nothing in common.
+const YEAR_LIMIT: u32 = 10;
+
+/// How much the title, duration and position count towards the cost of
+/// pairing a file with a track.
+const TITLE_COST: f64 = 0.5;
+const DURATION_COST: f64 = 0.3;
+const POSITION_COST: f64 = 0.2;
+
+/// Cost of leaving a file or a track unpaired.  A file and a track are only
+/// paired when that costs less than leaving both out, so they need to have
+/// more than a little in common.
+const UNPAIRED_COST: f64 = 0.35;
+
+/// What a cluster and a candidate are compared on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    AlbumArtist,
+    Album,
+    TrackCount,
+    TrackTitles,
+    TrackDurations,
+    Year,
+    DiscCount,
+    /// Medium format, e.g. CD or vinyl.
+    Media,
+}
+
+impl Field {
+    pub const ALL: [Field; 8] = [
+        Field::AlbumArtist,
+        Field::Album,
+        Field::TrackCount,
+        Field::TrackTitles,
+        Field::TrackDurations,
+        Field::Year,
+        Field::DiscCount,
+        Field::Media,
+    ];
+
+    /// Name of the field, as given to `--weight`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::AlbumArtist => "artist",
+            Field::Album => "album",
+            Field::TrackCount => "tracks",
+            Field::TrackTitles => "titles",
+            Field::TrackDurations => "durations",
+            Field::Year => "year",
+            Field::DiscCount => "discs",
+            Field::Media => "media",
+        }
+    }
+}
+
+/// How much each field counts towards a score.  Only the ratios between
+/// weights matter, and a weight of 0 ignores the field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Weights {
+    pub album_artist: f64,
+    pub album: f64,
+    pub track_count: f64,
+    pub track_titles: f64,
+    pub track_durations: f64,
+    pub year: f64,
+    pub disc_count: f64,
+    pub media: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            album_artist: 3.0,
+            album: 3.0,
+            track_count: 2.0,
+            track_titles: 3.0,
+            track_durations: 2.0,
+            year: 1.0,
+            disc_count: 1.0,
+            media: 1.0,
+        }
+    }
+}
+
+impl Weights {
+    pub fn get(&self, field: Field) -> f64 {
+        match field {
+            Field::AlbumArtist => self.album_artist,
+            Field::Album => self.album,
+            Field::TrackCount => self.track_count,
+            Field::TrackTitles => self.track_titles,
+            Field::TrackDurations => self.track_durations,
+            Field::Year => self.year,
+            Field::DiscCount => self.disc_count,
+            Field::Media => self.media,
+        }
+    }
+
+    fn get_mut(&mut self, field: Field) -> &mut f64 {
+        match field {
+            Field::AlbumArtist => &mut self.album_artist,
+            Field::Album => &mut self.album,
+            Field::TrackCount => &mut self.track_count,
+            Field::TrackTitles => &mut self.track_titles,
+            Field::TrackDurations => &mut self.track_durations,
+            Field::Year => &mut self.year,
+            Field::DiscCount => &mut self.disc_count,
+            Field::Media => &mut self.media,
+        }
+    }
+
+    /// Change one weight from a setting such as `year=0.5`.
+    pub fn set(&mut self, setting: &str) -> Result<()> {
+        let (name, weight) = setting
+            .split_once('=')
+            .ok_or_else(|| eyre!("Expected FIELD=WEIGHT, got '{}'", setting))?;
+        let Some(field) = Field::ALL.into_iter().find(|it| it.name() == name.trim()) else {
+            let names: Vec<&str> = Field::ALL.iter().map(Field::name).collect();
+            bail!(
+                "Unknown field '{}'; expected one of {}",
+                name,
+                names.join(", ")
+            );
+        };
+        *self.get_mut(field) = weight
+            .trim()
+            .parse()
+            .ok()
+            .filter(|it: &f64| it.is_finite() && *it >= 0.0)
+            .ok_or_else(|| eyre!("Weight for '{}' must be a number of at least 0", name))?;
+        Ok(())
+    }
+}
+
+/// How well a candidate matches a cluster.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MatchScore {
+    /// Similarity of each field both sides have, from 0 to 1.
+    pub fields: Vec<(Field, f64)>,
+    /// Weighted average of the similarities, from 0 to 1.
+    pub total: f64,
+    /// Which file would be tagged as which track, kept so that it is only
+    /// worked out once per candidate.
+    pub mapping: TrackMapping,
+}
+
+impl MatchScore {
+    /// The score as a whole percentage.
+    pub fn percent(&self) -> u32 {
+        (self.total * 100.0).round() as u32
+    }
+}
+
+impl fmt::Display for MatchScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.percent())
+    }
+}
+
+/// Where a track is on a release, as indices into its media and the
+/// medium's tracks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackPosition {
+    pub medium: usize,
+    pub track: usize,
+}
+
+/// Which file would be tagged as which track of a release.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackMapping {
+    /// Indices into the cluster's tracks, with the track each is paired
+    /// with.
+    pub pairs: Vec<(usize, TrackPosition)>,
+    /// Files that aren't on the release, as indices into the cluster's
+    /// tracks.
+    pub unmatched_files: Vec<usize>,
+    /// Tracks of the release that no file was found for.
+    pub missing_tracks: Vec<TrackPosition>,
+}
+
+impl TrackMapping {
+    /// The file paired with a track, as an index into the cluster's tracks.
+    pub fn file_for(&self, position: TrackPosition) -> Option<usize> {
+        self.pairs
+            .iter()
+            .find(|(_, it)| *it == position)
+            .map(|(file, _)| *file)
+    }
+}
+
+/// Pair the files of a cluster with the tracks of a release.
+///
+/// Files are paired with the tracks they have the most in common with, by
+/// title, duration and disc and track number, choosing the pairs with the
+/// least cost overall rather than going by position.  So a missing, extra
+/// or reordered track only affects itself.
+pub fn map_tracks(cluster: &AlbumCluster, candidate: &AlbumCandidate) -> TrackMapping {
+    let tracks: Vec<(TrackPosition, u32, &TrackCandidate)> = candidate
+        .media
+        .iter()
+        .enumerate()
+        .flat_map(|(medium, it)| {
+            let disc = it.position.unwrap_or(medium as u32 + 1);
+            it.tracks
+                .iter()
+                .enumerate()
+                .map(move |(track, it)| (TrackPosition { medium, track }, disc, it))
+        })
+        .collect();
+    let files = &cluster.tracks;
+
+    // Each file and each track also gets a stand-in to be paired with,
+    // which stands for leaving it unpaired.
+    let size = files.len() + tracks.len();
+    let mut costs = vec![vec![0.0; size]; size];
+    for (row, file) in costs.iter_mut().zip(files) {
+        for (cost, (position, disc, track)) in row.iter_mut().zip(&tracks) {
+            *cost = pair_cost(file, *disc, position.track as u32 + 1, track);
+        }
+        row[tracks.len()..].fill(UNPAIRED_COST);
+    }
+    for row in costs.iter_mut().skip(files.len()) {
+        row[..tracks.len()].fill(UNPAIRED_COST);
+    }
+
+    let mut mapping = TrackMapping::default();
+    let mut is_paired = vec![false; tracks.len()];
+    for (file, col) in assign(&costs).into_iter().take(files.len()).enumerate() {
+        match tracks.get(col) {
+            Some((position, ..)) => {
+                mapping.pairs.push((file, *position));
+                is_paired[col] = true;
+            }
+            None => mapping.unmatched_files.push(file),
+        }
+    }
+    mapping.missing_tracks = tracks
+        .iter()
+        .zip(is_paired)
+        .filter(|(_, paired)| !paired)
+        .map(|((position, ..), _)| *position)
+        .collect();
+    mapping
+}
+
+/// Cost of tagging a file as a track, from 0 for a perfect match to 1 for
+/// nothing in common.  Like scores, what the file lacks is left out.
+fn pair_cost(file: &AudioFile, disc: u32, number: u32, track: &TrackCandidate) -> f64 {
+    let mut costs = Vec::new();
+    if let Some(title) = file.title.as_deref()
+        && let Some(similarity) =
+            similarity(&normalize_title(title), &normalize_title(&track.title))
+    {
+        costs.push((TITLE_COST, 1.0 - similarity));
+    }
+    if let (Some(duration), Some(length)) = (file.duration, track.length) {
+        costs.push((DURATION_COST, 1.0 - duration_similarity(duration, length)));
+    }
+    if let Some(file_number) = file.track_number {
+        let same_disc = file.disc_number.unwrap_or(1) == disc;
+        let cost = match (file_number == number, same_disc) {
+            (true, true) => 0.0,
+            (true, false) => 0.5,
+            (false, _) => 1.0,
+        };
+        costs.push((POSITION_COST, cost));
+    }
+
+    let total_weight: f64 = costs.iter().map(|(weight, _)| weight).sum();
+    if total_weight == 0.0 {
+        return 1.0;
+    }
+    costs
+        .iter()
+        .map(|(weight, cost)| weight * cost)
+        .sum::<f64>()
+        / total_weight
+}
+
+/// Solve the assignment problem for a square matrix of costs with the
+/// Hungarian algorithm, returning the column assigned to each row so that
+/// the total cost is as low as possible.
+fn assign(costs: &[Vec<f64>]) -> Vec<usize> {
+    let size = costs.len();
+    // Rows and columns count from 1 here, leaving column 0 as the start of
+    // each augmenting path.
+    let mut row_potential = vec![0.0; size + 1];
+    let mut col_potential = vec![0.0; size + 1];
+    let mut row_of_col = vec![0; size + 1];
+    let mut prev_col = vec![0; size + 1];
+
+    for row in 1..=size {
+        row_of_col[0] = row;
+        let mut col = 0;
+        let mut min_slack = vec![f64::INFINITY; size + 1];
+        let mut visited = vec![false; size + 1];
+        // Grow a tree of tight edges until it reaches a free column.
+        loop {
+            visited[col] = true;
+            let current = row_of_col[col];
+            let mut delta = f64::INFINITY;
+            let mut next = 0;
+            for j in 1..=size {
+                if visited[j] {
+                    continue;
+                }
+                let slack = costs[current - 1][j - 1] - row_potential[current] - col_potential[j];
+                if slack < min_slack[j] {
+                    min_slack[j] = slack;
+                    prev_col[j] = col;
+                }
+                if min_slack[j] < delta {
+                    delta = min_slack[j];
+                    next = j;
+                }
+            }
+            for j in 0..=size {
+                if visited[j] {
+                    row_potential[row_of_col[j]] += delta;
+                    col_potential[j] -= delta;
+                } else {
+                    min_slack[j] -= delta;
+                }
+            }
+            col = next;
+            if row_of_col[col] == 0 {
+                break;
+            }
+        }
+        // Flip the path back to the start.
+        while col != 0 {
+            let prev = prev_col[col];
+            row_of_col[col] = row_of_col[prev];
+            col = prev;
+        }
+    }
+
+    let mut assigned = vec![0; size];
+    for (col, row) in row_of_col.into_iter().enumerate().skip(1) {
+        assigned[row - 1] = col - 1;
+    }
+    assigned
+}
+
+/// Score a candidate against a cluster, given how [`map_tracks`] pairs the
+/// cluster's files with the candidate's tracks.
+///
+/// A single file matched through a recording is compared with that track
+/// rather than with the whole release, which would have other tracks, and
+/// the mapping is only kept for showing the release.  Otherwise files are
+/// compared with the tracks they are paired with, and files left unpaired
+/// count against the titles.
+pub fn score(
+    cluster: &AlbumCluster,
+    candidate: &AlbumCandidate,
+    mapping: TrackMapping,
+    weights: &Weights,
+) -> MatchScore {
+    let mut fields = Vec::new();
+    let mut compare = |field: Field, similarity: Option<f64>| {
+        if let Some(similarity) = similarity {
+            fields.push((field, similarity));
+        }
+    };
+
+    let matched = candidate
+        .matched_track
+        .as_ref()
+        .filter(|_| cluster.is_singleton());
+    let artist = matched
+        .and_then(|it| it.artist.as_deref())
+        .unwrap_or(&candidate.artist);
+    compare(
+        Field::AlbumArtist,
+        similarity(
+            &normalize_artist(&cluster.album_artist),
+            &normalize_artist(artist),
+        ),
+    );
+    compare(
+        Field::Album,
+        similarity(
+            &normalize_title(&cluster.album),
+            &normalize_title(&candidate.title),
+        ),
+    );
+
+    let (pairs, unpaired): (Vec<(&AudioFile, &TrackCandidate)>, usize) = match matched {
+        Some(track) => (
+            cluster
+                .tracks
+                .first()
+                .map(|it| (it, track))
+                .into_iter()
+                .collect(),
+            0,
+        ),
+        None => {
+            compare(
+                Field::TrackCount,
+                (candidate.track_count() > 0).then(|| {
+                    count_similarity(cluster.track_count(), candidate.track_count() as usize)
+                }),
+            );
+            compare(
+                Field::DiscCount,
+                (!candidate.media.is_empty()).then(|| {
+                    count_similarity(cluster.total_discs.max(1) as usize, candidate.media.len())
+                }),
+            );
+            let pairs = mapping
+                .pairs
+                .iter()
+                .map(|(file, position)| {
+                    let track = &candidate.media[position.medium].tracks[position.track];
+                    (&cluster.tracks[*file], track)
+                })
+                .collect();
+            // Without a tracklist, nothing could have been paired.
+            let unpaired = if candidate.has_tracklist() {
+                mapping.unmatched_files.len()
+            } else {
+                0
+            };
+            (pairs, unpaired)
+        }
+    };
+    compare(
+        Field::TrackTitles,
+        average(
+            pairs
+                .iter()
+                .filter_map(|(file, track)| {
+                    similarity(
+                        &normalize_title(file.title.as_deref()?),
+                        &normalize_title(&track.title),
+                    )
+                })
+                .chain(std::iter::repeat_n(0.0, unpaired)),
+        ),
+    );
+    compare(
+        Field::TrackDurations,
+        average(
+            pairs.iter().filter_map(|(file, track)| {
+                Some(duration_similarity(file.duration?, track.length?))
+            }),
+        ),
+    );
+
+    compare(
+        Field::Year,
+        cluster
+            .year()
+            .zip(candidate.year())
+            .map(|(a, b)| 1.0 - a.abs_diff(b).min(YEAR_LIMIT) as f64 / YEAR_LIMIT as f64),
+    );
+    let formats: Vec<&str> = candidate
+        .media
+        .iter()
+        .filter_map(|it| it.format.as_deref())
+        .collect();
+    compare(
+        Field::Media,
+        cluster
+            .identifiers
+            .media
+            .as_deref()
+            .filter(|_| !formats.is_empty())
+            .map(|media| {
+                let found = formats
+                    .iter()
+                    .any(|it| it.eq_ignore_ascii_case(media.trim()));
+                if found { 1.0 } else { 0.0 }
+            }),
+    );
+
+    let (sum, total_weight) = fields
+        .iter()
+        .map(|(field, similarity)| (weights.get(*field) * similarity, weights.get(*field)))
+        .fold((0.0, 0.0), |(sum, total), (it, weight)| {
+            (sum + it, total + weight)
+        });
+    MatchScore {
+        total: if total_weight > 0.0 {
+            sum / total_weight
+        } else {
+            0.0
+        },
+        fields,
+        mapping,
+    }
+}
+
+/// Indices of candidates from the best score to the worst, keeping the
+/// search order between equal scores.  Candidates without a score yet come
+/// last.
+pub fn ranked(scores: &[MatchScore], count: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..count).collect();
+    let total = |idx: usize| scores.get(idx).map_or(-1.0, |it| it.total);
+    order.sort_by(|a, b| total(*b).total_cmp(&total(*a)));
+    order
+}
+
+/// Similarity of two normalized names, ignoring case, or `None` if either
+/// is missing.
+fn similarity(a: &str, b: &str) -> Option<f64> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+    Some(strsim::normalized_levenshtein(
+        &a.to_lowercase(),
+        &b.to_lowercase(),
+    ))
+}
+
+fn count_similarity(a: usize, b: usize) -> f64 {
+    a.min(b) as f64 / a.max(b).max(1) as f64
+}
+
+fn duration_similarity(a: Duration, b: Duration) -> f64 {
+    let diff = a.abs_diff(b).saturating_sub(DURATION_TOLERANCE);
+    1.0 - diff.min(DURATION_LIMIT).as_secs_f64() / DURATION_LIMIT.as_secs_f64()
+}
+
+fn average(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), it| (sum + it, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use crate::{
+        codecs::AudioCodec,
+        models::{MusicBrainzIds, ReleaseIdentifiers, ReplayGain},
+        search::SearchStrategy,
+        source::MediumCandidate,
+    };
+
+    const TRACKS: [(&str, u64); 3] = [
+        ("Opening Test", 200),
+        ("Assertion Blues", 250),
+        ("Fixture Song", 180),
+    ];
+
+    /// A file tagged as the given track of [`candidate`].
+    fn file(number: u32, (title, secs): (&str, u64)) -> AudioFile {
+        AudioFile {
+            path: PathBuf::from(format!("/music/{}.flac", number)),
+            codec: AudioCodec::Flac,
+            title: Some(String::from(title)),
+            artist: Some(String::from("The Testers")),
+            album_artist: Some(String::from("The Testers")),
+            album: Some(String::from("Fixture Songs")),
+            track_number: Some(number),
+            total_tracks: Some(TRACKS.len() as u32),
+            disc_number: Some(1),
+            total_discs: Some(1),
+            genre: None,
+            year: Some(2001),
+            duration: Some(Duration::from_secs(secs)),
+            duration_source: None,
+            legacy_encoding: None,
+            replay_gain: ReplayGain::default(),
+            identifiers: ReleaseIdentifiers::default(),
+            isrc: None,
+            musicbrainz: MusicBrainzIds::default(),
+            audio_digest: None,
+        }
+    }
+
+    fn cluster(tracks: Vec<AudioFile>) -> AlbumCluster {
+        AlbumCluster {
+            album: String::from("Fixture Songs"),
+            album_artist: String::from("The Testers"),
+            tracks,
+            base_path: PathBuf::from("/music"),
+            total_discs: 1,
+            encoding_suggestion: None,
+            identifiers: ReleaseIdentifiers {
+                media: Some(String::from("CD")),
+                ..ReleaseIdentifiers::default()
+            },
+        }
+    }
+
+    /// The files of the whole release, in order.
+    fn full_cluster() -> AlbumCluster {
+        cluster(
+            TRACKS
+                .into_iter()
+                .enumerate()
+                .map(|(idx, it)| file(idx as u32 + 1, it))
+                .collect(),
+        )
+    }
+
+    fn candidate(tracks: &[(&str, u64)]) -> AlbumCandidate {
+        AlbumCandidate {
+            source: "MusicBrainz",
+            id: String::from("release"),
+            title: String::from("Fixture Songs"),
+            artist: String::from("The Testers"),
+            date: Some(String::from("2001-05-01")),
+            country: None,
+            barcode: None,
+            labels: Vec::new(),
+            group: None,
+            media: vec![MediumCandidate {
+                position: Some(1),
+                title: None,
+                format: Some(String::from("CD")),
+                track_count: tracks.len() as u32,
+                tracks: tracks
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, (title, secs))| TrackCandidate {
+                        id: format!("recording-{}", idx + 1),
+                        title: String::from(*title),
+                        artist: None,
+                        number: (idx + 1).to_string(),
+                        length: Some(Duration::from_secs(*secs)),
+                    })
+                    .collect(),
+            }],
+            genres: Vec::new(),
+            matched_track: None,
+            strategy: SearchStrategy::Exact,
+        }
+    }
+
+    fn score_of(
+        cluster: &AlbumCluster,
+        candidate: &AlbumCandidate,
+        weights: &Weights,
+    ) -> MatchScore {
+        score(cluster, candidate, map_tracks(cluster, candidate), weights)
+    }
+
+    fn scored_fields(score: &MatchScore) -> Vec<Field> {
+        score.fields.iter().map(|(field, _)| *field).collect()
+    }
+
+    #[test]
+    fn identical_release_scores_full_marks() {
+        let score = score_of(&full_cluster(), &candidate(&TRACKS), &Weights::default());
+
+        assert_eq!(score.percent(), 100);
+        assert_eq!(scored_fields(&score).len(), Field::ALL.len());
+        assert!(score.fields.iter().all(|(_, it)| *it == 1.0));
+    }
+
+    #[test]
+    fn fields_either_side_lacks_are_left_out() {
+        // A search result with only a track count, and no date or format.
+        let mut summary = candidate(&TRACKS);
+        summary.date = None;
+        summary.media[0].format = None;
+        summary.media[0].tracks.clear();
+        let score = score_of(&full_cluster(), &summary, &Weights::default());
+        assert_eq!(
+            scored_fields(&score),
+            [
+                Field::AlbumArtist,
+                Field::Album,
+                Field::TrackCount,
+                Field::DiscCount
+            ]
+        );
+        assert_eq!(score.percent(), 100);
+
+        // Files without durations, years or a format.
+        let mut files = full_cluster();
+        files.identifiers.media = None;
+        for file in &mut files.tracks {
+            file.duration = None;
+            file.year = None;
+        }
+        let score = score_of(&files, &candidate(&TRACKS), &Weights::default());
+        assert!(!scored_fields(&score).contains(&Field::TrackDurations));
+        assert!(!scored_fields(&score).contains(&Field::Year));
+        assert!(!scored_fields(&score).contains(&Field::Media));
+        assert_eq!(score.percent(), 100);
+    }
+
+    #[test]
+    fn zero_weight_ignores_a_field() {
+        let mut reissue = candidate(&TRACKS);
+        reissue.date = Some(String::from("2021"));
+        let mut weights = Weights::default();
+        assert!(score_of(&full_cluster(), &reissue, &weights).percent() < 100);
+
+        weights.set("year=0").unwrap();
+        assert_eq!(score_of(&full_cluster(), &reissue, &weights).percent(), 100);
+    }
+
+    #[test]
+    fn weight_settings_are_checked() {
+        let mut weights = Weights::default();
+        weights.set(" titles = 0.5").unwrap();
+        assert_eq!(weights.track_titles, 0.5);
+
+        for setting in [
+            "colour=1", "year=-1", "year=NaN", "year=inf", "year=", "year",
+        ] {
+            assert!(weights.set(setting).is_err(), "{}", setting);
+        }
+        assert_eq!(
+            Weights {
+                track_titles: Weights::default().track_titles,
+                ..weights
+            },
+            Weights::default()
+        );
+    }
+
+    #[test]
+    fn ranking_keeps_search_order_between_equal_scores() {
+        let scored = |total| MatchScore {
+            total,
+            ..MatchScore::default()
+        };
+        let scores = [scored(0.5), scored(0.9), scored(0.5), scored(0.0)];
+
+        // The last two candidates haven't been scored yet.
+        assert_eq!(ranked(&scores, 6), [1, 0, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn assignment_has_the_least_total_cost() {
+        // Going by rows alone would take 1 twice.
+        let costs = vec![
+            vec![1.0, 4.0, 5.0],
+            vec![2.0, 9.0, 8.0],
+            vec![3.0, 8.0, 1.0],
+        ];
+        assert_eq!(assign(&costs), [1, 0, 2]);
+
+        // A padding row or column, which costs nothing, takes whatever is
+        // left over.
+        let costs = vec![
+            vec![5.0, 1.0, 9.0],
+            vec![1.0, 2.0, 9.0],
+            vec![0.0, 0.0, 0.0],
+        ];
+        assert_eq!(assign(&costs), [1, 0, 2]);
+        let costs = vec![
+            vec![3.0, 1.0, 0.0],
+            vec![1.0, 4.0, 0.0],
+            vec![2.0, 2.0, 0.0],
+        ];
+        assert_eq!(assign(&costs), [1, 0, 2]);
+    }
+
+    fn at(track: usize) -> TrackPosition {
+        TrackPosition { medium: 0, track }
+    }
+
+    #[test]
+    fn files_in_order_map_to_their_tracks() {
+        let mapping = map_tracks(&full_cluster(), &candidate(&TRACKS));
+
+        assert_eq!(mapping.pairs, [(0, at(0)), (1, at(1)), (2, at(2))]);
+        assert!(mapping.unmatched_files.is_empty());
+        assert!(mapping.missing_tracks.is_empty());
+    }
+
+    #[test]
+    fn missing_file_leaves_its_track_out() {
+        let mut cluster = full_cluster();
+        cluster.tracks.remove(1);
+        let mapping = map_tracks(&cluster, &candidate(&TRACKS));
+
+        assert_eq!(mapping.pairs, [(0, at(0)), (1, at(2))]);
+        assert!(mapping.unmatched_files.is_empty());
+        assert_eq!(mapping.missing_tracks, [at(1)]);
+    }
+
+    #[test]
+    fn extra_file_is_left_unmatched() {
+        let mut cluster = full_cluster();
+        cluster.tracks.insert(1, file(9, ("Hidden Bonus Jam", 600)));
+        let mapping = map_tracks(&cluster, &candidate(&TRACKS));
+
+        assert_eq!(mapping.pairs, [(0, at(0)), (2, at(1)), (3, at(2))]);
+        assert_eq!(mapping.unmatched_files, [1]);
+        assert!(mapping.missing_tracks.is_empty());
+    }
+
+    #[test]
+    fn reordered_files_follow_their_titles() {
+        // The files are numbered in the order they are in, as a playlist
+        // rip would be, but the titles and durations give them away.
+        let cluster = cluster(
+            [TRACKS[2], TRACKS[0], TRACKS[1]]
+                .into_iter()
+                .enumerate()
+                .map(|(idx, it)| file(idx as u32 + 1, it))
+                .collect(),
+        );
+        let mapping = map_tracks(&cluster, &candidate(&TRACKS));
+
+        assert_eq!(mapping.pairs, [(0, at(2)), (1, at(0)), (2, at(1))]);
+        assert!(mapping.unmatched_files.is_empty());
+        assert!(mapping.missing_tracks.is_empty());
+    }
+}
@@ -4,8 +4,13 @@
 
 use std::{path::PathBuf, time::Duration};
 
+use encoding_rs::Encoding;
+
 use crate::codecs::AudioCodec;
 
+/// Album gains further apart than this come from different analyses.
+const ALBUM_GAIN_TOLERANCE: f32 = 0.01;
+
 /// A single audio file with extracted metadata.
 #[derive(Debug, Clone)]
 pub struct AudioFile {
@@ -16,11 +21,148 @@ pub struct AudioFile {
     pub album_artist: Option<String>,
     pub album: Option<String>,
     pub track_number: Option<u32>,
+    #[allow(dead_code)]
     pub total_tracks: Option<u32>,
     pub disc_number: Option<u32>,
     pub total_discs: Option<u32>,
     pub genre: Option<String>,
+    /// Year of release, from whichever date tag the format has.
+    pub year: Option<u32>,
     pub duration: Option<Duration>,
+    pub duration_source: Option<DurationSource>,
+    /// The probable real encoding of text tags that were stored as Latin-1.
+    pub legacy_encoding: Option<&'static Encoding>,
+    pub replay_gain: ReplayGain,
+    pub identifiers: ReleaseIdentifiers,
+    /// International Standard Recording Code, identifying the recording.
+    pub isrc: Option<String>,
+    /// IDs written by an earlier MusicBrainz tagging.
+    pub musicbrainz: MusicBrainzIds,
+    pub audio_digest: Option<AudioDigest>,
+}
+
+impl AudioFile {
+    /// Average bitrate of the audio payload in kbit/s.
+    pub fn bitrate(&self) -> Option<u32> {
+        let size = self.audio_digest.as_ref()?.size;
+        let secs = self.duration?.as_secs_f64();
+        if secs <= 0.0 {
+            return None;
+        }
+        Some((size as f64 * 8.0 / secs / 1000.0).round() as u32)
+    }
+}
+
+/// Tags that identify the physical release a file was ripped from.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReleaseIdentifiers {
+    pub barcode: Option<String>,
+    pub catalog_number: Option<String>,
+    pub label: Option<String>,
+    /// Medium format as named by MusicBrainz, e.g. "CD".
+    pub media: Option<String>,
+}
+
+/// MusicBrainz IDs of what a file was tagged as, using the tags Picard
+/// writes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MusicBrainzIds {
+    pub release_id: Option<String>,
+    /// The track on the release, which tells apart the same recording
+    /// appearing twice.
+    pub release_track_id: Option<String>,
+    pub recording_id: Option<String>,
+}
+
+/// Loudness normalisation tags found in a file.  Gains are in dB.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+    /// `R128_TRACK_GAIN` from Opus files, relative to the output gain in the
+    /// Opus header.
+    pub r128_track_gain: Option<f32>,
+    pub r128_album_gain: Option<f32>,
+    /// Apple's Sound Check adjustment from an `iTunNORM` tag.
+    pub sound_check: Option<f32>,
+}
+
+impl ReplayGain {
+    pub fn has_track_gain(&self) -> bool {
+        self.track_gain.is_some() || self.r128_track_gain.is_some()
+    }
+
+    pub fn album_gain(&self) -> Option<f32> {
+        self.album_gain.or(self.r128_album_gain)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// How completely a cluster has been analysed for ReplayGain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayGainStatus {
+    /// No track carries any loudness tags.
+    None,
+    /// Some tracks, or some of the track/album values, are missing.
+    Partial,
+    /// Every track has both track and album gain.
+    Complete,
+    /// The tracks disagree on the album gain, so they weren't analysed
+    /// together.
+    Inconsistent,
+}
+
+impl ReplayGainStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReplayGainStatus::None => "none",
+            ReplayGainStatus::Partial => "partial",
+            ReplayGainStatus::Complete => "complete",
+            ReplayGainStatus::Inconsistent => "inconsistent",
+        }
+    }
+}
+
+/// How the duration of an audio file was determined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DurationSource {
+    /// Read from the container's stream information (FLAC, MP4).
+    StreamInfo,
+    /// Frame count from a Xing/Info header.
+    Xing,
+    /// Frame count from a Xing/Info header, corrected for the encoder delay
+    /// and padding recorded in a LAME tag.
+    Lame,
+    /// Frame count from a Fraunhofer VBRI header.
+    Vbri,
+    /// Counted by scanning every frame in the file.
+    FrameScan,
+}
+
+impl DurationSource {
+    pub fn label(&self) -> &'static str {
+        match self {
+            DurationSource::StreamInfo => "stream info",
+            DurationSource::Xing => "Xing",
+            DurationSource::Lame => "LAME",
+            DurationSource::Vbri => "VBRI",
+            DurationSource::FrameScan => "frame scan",
+        }
+    }
+}
+
+/// A hash of a file's audio payload, excluding any embedded metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioDigest {
+    /// Hex-encoded BLAKE3 hash of the audio payload.
+    pub hash: String,
+    /// Size of the audio payload in bytes.
+    pub size: u64,
 }
 
 /// A cluser of files that are likely to belong to the same album.
@@ -34,6 +176,19 @@ pub struct AlbumCluster {
     pub tracks: Vec<AudioFile>,
     pub base_path: PathBuf,
     pub total_discs: u32,
+    pub encoding_suggestion: Option<EncodingSuggestion>,
+    /// Identifiers shared by most of the tracks.
+    pub identifiers: ReleaseIdentifiers,
+}
+
+/// A proposed re-decoding of a cluster's mis-encoded tags.
+#[derive(Debug, Clone)]
+pub struct EncodingSuggestion {
+    pub encoding: &'static Encoding,
+    /// The album artist as it would read after repair.
+    pub album_artist: String,
+    /// The album as it would read after repair.
+    pub album: String,
 }
 
 impl AlbumCluster {
@@ -41,6 +196,49 @@ impl AlbumCluster {
         self.tracks.len()
     }
 
+    /// Whether the cluster holds a single track, which is matched against
+    /// recordings rather than releases.
+    pub fn is_singleton(&self) -> bool {
+        self.tracks.len() == 1
+    }
+
+    /// Split the cluster into one cluster per track, so that each file can
+    /// be tagged as a single without an album.
+    pub fn singletons(&self) -> Vec<AlbumCluster> {
+        self.tracks
+            .iter()
+            .map(|track| AlbumCluster {
+                album: self.album.clone(),
+                album_artist: track
+                    .artist
+                    .clone()
+                    .unwrap_or_else(|| self.album_artist.clone()),
+                tracks: vec![track.clone()],
+                base_path: self.base_path.clone(),
+                total_discs: 1,
+                encoding_suggestion: None,
+                identifiers: track.identifiers.clone(),
+            })
+            .collect()
+    }
+
+    /// The release MBID the tracks were tagged with, if they agree on one.
+    pub fn release_mbid(&self) -> Option<&str> {
+        let mut ids = self
+            .tracks
+            .iter()
+            .filter_map(|it| it.musicbrainz.release_id.as_deref());
+        let first = ids.next()?;
+        ids.all(|it| it == first).then_some(first)
+    }
+
+    /// The year the tracks were tagged with, if they agree on one.
+    pub fn year(&self) -> Option<u32> {
+        let mut years = self.tracks.iter().filter_map(|it| it.year);
+        let first = years.next()?;
+        years.all(|it| it == first).then_some(first)
+    }
+
     /// Get the audio codec shared by all files in the cluster, if any.
     pub fn codec(&self) -> Option<AudioCodec> {
         let first_track = self.tracks.first()?;
@@ -50,4 +248,123 @@ impl AlbumCluster {
         }
         Some(codec)
     }
+
+    /// Summarise the ReplayGain tags of the cluster's tracks.
+    pub fn replay_gain_status(&self) -> ReplayGainStatus {
+        let gains: Vec<&ReplayGain> = self.tracks.iter().map(|it| &it.replay_gain).collect();
+        if gains.iter().all(|it| it.is_empty()) {
+            return ReplayGainStatus::None;
+        }
+
+        let mut album_gains = gains.iter().filter_map(|it| it.album_gain());
+        if let Some(first) = album_gains.next()
+            && album_gains.any(|it| (it - first).abs() > ALBUM_GAIN_TOLERANCE)
+        {
+            return ReplayGainStatus::Inconsistent;
+        }
+
+        if gains
+            .iter()
+            .all(|it| it.has_track_gain() && it.album_gain().is_some())
+        {
+            ReplayGainStatus::Complete
+        } else {
+            ReplayGainStatus::Partial
+        }
+    }
+
+    /// Average bitrate across all tracks with a known bitrate, in kbit/s.
+    pub fn average_bitrate(&self) -> Option<u32> {
+        let bitrates: Vec<u32> = self.tracks.iter().filter_map(AudioFile::bitrate).collect();
+        if bitrates.is_empty() {
+            return None;
+        }
+        Some(bitrates.iter().sum::<u32>() / bitrates.len() as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(replay_gain: ReplayGain) -> AudioFile {
+        AudioFile {
+            path: PathBuf::from("/music/track.flac"),
+            codec: AudioCodec::Flac,
+            title: None,
+            artist: None,
+            album_artist: None,
+            album: None,
+            track_number: None,
+            total_tracks: None,
+            disc_number: None,
+            total_discs: None,
+            genre: None,
+            year: None,
+            duration: None,
+            duration_source: None,
+            legacy_encoding: None,
+            replay_gain,
+            identifiers: ReleaseIdentifiers::default(),
+            isrc: None,
+            musicbrainz: MusicBrainzIds::default(),
+            audio_digest: None,
+        }
+    }
+
+    fn cluster(gains: Vec<ReplayGain>) -> AlbumCluster {
+        AlbumCluster {
+            album: String::from("Fixture Songs"),
+            album_artist: String::from("The Testers"),
+            tracks: gains.into_iter().map(track).collect(),
+            base_path: PathBuf::from("/music"),
+            total_discs: 1,
+            encoding_suggestion: None,
+            identifiers: ReleaseIdentifiers::default(),
+        }
+    }
+
+    fn gain(track_gain: f32, album_gain: f32) -> ReplayGain {
+        ReplayGain {
+            track_gain: Some(track_gain),
+            album_gain: Some(album_gain),
+            ..ReplayGain::default()
+        }
+    }
+
+    #[test]
+    fn untagged_cluster_has_no_replay_gain() {
+        let status = cluster(vec![ReplayGain::default(); 2]).replay_gain_status();
+        assert_eq!(status, ReplayGainStatus::None);
+    }
+
+    #[test]
+    fn missing_values_are_partial() {
+        let track_only = ReplayGain {
+            track_gain: Some(-6.0),
+            ..ReplayGain::default()
+        };
+        let status = cluster(vec![gain(-6.0, -7.0), track_only]).replay_gain_status();
+        assert_eq!(status, ReplayGainStatus::Partial);
+
+        let status = cluster(vec![gain(-6.0, -7.0), ReplayGain::default()]).replay_gain_status();
+        assert_eq!(status, ReplayGainStatus::Partial);
+    }
+
+    #[test]
+    fn track_and_album_gain_on_every_track_is_complete() {
+        let r128 = ReplayGain {
+            r128_track_gain: Some(-1.0),
+            r128_album_gain: Some(-7.0),
+            ..ReplayGain::default()
+        };
+        let status = cluster(vec![gain(-6.0, -7.0), gain(-8.0, -7.0), r128]).replay_gain_status();
+        assert_eq!(status, ReplayGainStatus::Complete);
+    }
+
+    #[test]
+    fn differing_album_gains_are_inconsistent() {
+        let status = cluster(vec![gain(-6.0, -7.0), gain(-8.0, -7.5)]).replay_gain_status();
+        assert_eq!(status, ReplayGainStatus::Inconsistent);
+    }
 }
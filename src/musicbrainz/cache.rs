@@ -0,0 +1,217 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Persistent cache of MusicBrainz API responses.
+//!
+//! Each response is stored as a JSON file named after the hash of its
+//! request, so that repeated sessions on the same library don't have to wait
+//! on the rate limit again.
+
+use std::{
+    env, fs,
+    io::ErrorKind,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use color_eyre::eyre::{Result, WrapErr};
+
+use crate::paths::escape_path;
+
+/// How long a cached response is used before asking the server again.
+const DEFAULT_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Total size of cached responses above which the oldest are dropped.
+const DEFAULT_MAX_BYTES: u64 = 100 * 1024 * 1024;
+
+const CACHE_EXTENSION: &str = "json";
+
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    pub dir: PathBuf,
+    pub ttl: Duration,
+    pub max_bytes: u64,
+}
+
+impl ResponseCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            ttl: DEFAULT_TTL,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+
+    /// Cache in `$XDG_CACHE_HOME/tuners/musicbrainz`, falling back to
+    /// `~/.cache`.  Returns `None` when neither variable is set.
+    pub fn in_default_dir() -> Option<Self> {
+        Self::for_service("musicbrainz")
+    }
+
+    /// Cache responses from another service alongside those from
+    /// MusicBrainz, in a directory of its own.
+    pub fn for_service(name: &str) -> Option<Self> {
+        let base = env::var_os("XDG_CACHE_HOME")
+            .filter(|it| !it.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(Self::new(base.join("tuners").join(name)))
+    }
+
+    /// Look up the response to a request, ignoring expired entries.
+    pub fn get(&self, key: &str) -> Option<serde_json::Value> {
+        let path = self.entry_path(key);
+        let age = fs::metadata(&path)
+            .and_then(|it| it.modified())
+            .ok()
+            .and_then(|it| it.elapsed().ok())?;
+        if age > self.ttl {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+
+        let contents = fs::read(&path).ok()?;
+        serde_json::from_slice(&contents).ok()
+    }
+
+    /// Store the response to a request, evicting old entries if the cache
+    /// has grown past its size limit.
+    pub fn put(&self, key: &str, value: &serde_json::Value) -> Result<()> {
+        fs::create_dir_all(&self.dir).wrap_err_with(|| {
+            format!(
+                "Failed to create cache directory {}",
+                escape_path(&self.dir)
+            )
+        })?;
+
+        // Write to a temporary file first so that readers never see a
+        // partially written entry.
+        let path = self.entry_path(key);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(value)?)?;
+        fs::rename(&tmp, &path)?;
+
+        self.evict()
+    }
+
+    /// Remove every cached response.
+    pub fn clear(&self) -> Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e)
+                .wrap_err_with(|| format!("Failed to clear cache at {}", escape_path(&self.dir))),
+            _ => Ok(()),
+        }
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        let hash = blake3::hash(key.as_bytes()).to_hex();
+        self.dir.join(format!("{}.{}", hash, CACHE_EXTENSION))
+    }
+
+    /// Drop the least recently written entries until the cache fits within
+    /// `max_bytes`.
+    fn evict(&self) -> Result<()> {
+        let mut entries: Vec<(SystemTime, u64, PathBuf)> = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_none_or(|it| it != CACHE_EXTENSION) {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            entries.push((metadata.modified()?, metadata.len(), path));
+        }
+
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort();
+        for (_, size, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            fs::remove_file(&path)?;
+            total -= size;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_cache(name: &str) -> ResponseCache {
+        ResponseCache::new(env::temp_dir().join(format!(
+            "tuners-cache-{}-{}",
+            name,
+            std::process::id()
+        )))
+    }
+
+    /// Make an entry look as if it was written `age` ago.
+    fn age(cache: &ResponseCache, key: &str, age: Duration) {
+        fs::File::options()
+            .write(true)
+            .open(cache.entry_path(key))
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+    }
+
+    #[test]
+    fn expired_entries_miss() {
+        let cache = ResponseCache {
+            ttl: Duration::from_secs(60),
+            ..temp_cache("ttl")
+        };
+        cache.put("fresh", &json!({ "id": 1 })).unwrap();
+        cache.put("stale", &json!({ "id": 2 })).unwrap();
+        age(&cache, "stale", Duration::from_secs(120));
+
+        let fresh = cache.get("fresh");
+        let stale = cache.get("stale");
+        let is_removed = !cache.entry_path("stale").exists();
+        let missing = cache.get("missing");
+        cache.clear().unwrap();
+
+        assert_eq!(fresh, Some(json!({ "id": 1 })));
+        assert_eq!(stale, None);
+        assert!(is_removed);
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn oldest_entries_are_evicted_past_the_size_limit() {
+        // Each entry is a 10 byte string, so two fit.
+        let cache = ResponseCache {
+            max_bytes: 20,
+            ..temp_cache("evict")
+        };
+        cache.put("a", &json!("aaaaaaaa")).unwrap();
+        cache.put("b", &json!("bbbbbbbb")).unwrap();
+        age(&cache, "a", Duration::from_secs(30));
+        age(&cache, "b", Duration::from_secs(20));
+        cache.put("c", &json!("cccccccc")).unwrap();
+
+        let kept = ["a", "b", "c"].map(|it| cache.get(it).is_some());
+        cache.clear().unwrap();
+
+        assert_eq!(kept, [false, true, true]);
+    }
+
+    #[test]
+    fn clear_empties_the_cache() {
+        let cache = temp_cache("clear");
+        cache.put("a", &json!(1)).unwrap();
+        cache.put("b", &json!(2)).unwrap();
+
+        cache.clear().unwrap();
+
+        assert!(!cache.dir.exists());
+        assert_eq!(cache.get("a"), None);
+        // Clearing an empty cache is fine too.
+        cache.clear().unwrap();
+    }
+}
@@ -2,63 +2,523 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
-use std::time::{Duration, Instant};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
-use color_eyre::eyre::{Result, eyre};
+use color_eyre::eyre::{Result, WrapErr};
 use musicbrainz_rs::{
-    entity::release::{Release, ReleaseSearchQuery},
+    ApiRequest, MusicBrainzClient,
+    entity::{
+        BrowseResult, recording::Recording, release::Release, release_group::ReleaseGroup,
+        search::SearchResult,
+    },
     prelude::*,
 };
-use tokio::time::sleep;
+use serde::de::DeserializeOwned;
 
+use crate::{
+    musicbrainz::{cache::ResponseCache, dump::DumpStore, limiter::RateLimiter},
+    source::{
+        OnRetry,
+        error::{ApiError, ErrorKind},
+        http::{
+            self, DEFAULT_MAX_ATTEMPTS, DEFAULT_USER_AGENT, RetryPolicy, SearchPage, percent_encode,
+        },
+    },
+};
+
+/// Name of the service, as given in errors.
+const SERVICE: &str = "MusicBrainz";
+
+/// The rate limit of the public MusicBrainz servers.
 const RATE_LIMIT: Duration = Duration::from_secs(1);
 
+/// Requests that may be made back to back after a quiet period.
+pub const DEFAULT_BURST: u32 = 1;
+
+/// Most releases returned when browsing, the maximum the API allows.
+const BROWSE_LIMIT: u8 = 100;
+
+/// Number of search results fetched at a time, the API's default.
+const SEARCH_PAGE_SIZE: u8 = 25;
+
+/// Host of the public MusicBrainz server.
+pub const PUBLIC_HOST: &str = "musicbrainz.org";
+
+/// Where and how to talk to a MusicBrainz server.
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// Host (and optional port) of the server, e.g. `localhost:5000`.
+    pub host: String,
+    pub user_agent: String,
+    /// Minimum time between requests.  When unset, the public servers get
+    /// [`RATE_LIMIT`] and any other host is not limited at all.
+    pub rate_limit: Option<Duration>,
+    /// Requests allowed in quick succession before the rate limit applies.
+    pub burst: u32,
+    /// Number of attempts made for a request that keeps failing with
+    /// transient errors.
+    pub max_attempts: u32,
+    /// Where to keep responses between sessions, if anywhere.
+    pub cache: Option<ResponseCache>,
+    /// Answer queries from an imported dump in this directory instead of
+    /// asking the server.
+    pub offline_dump: Option<PathBuf>,
+}
+
+impl ClientConfig {
+    /// Set the server from a host name or URL such as
+    /// `http://localhost:5000/ws/2`.
+    ///
+    /// Requests are always made over plain HTTP, as `musicbrainz_rs` does.
+    pub fn set_server(&mut self, server: &str) {
+        let host = server
+            .trim_start_matches("http://")
+            .trim_start_matches("https://");
+        let host = host.split('/').next().unwrap_or(host);
+        self.host = host.to_string();
+    }
+
+    /// Whether the host is one of the public MusicBrainz servers, including
+    /// mirrors like beta.musicbrainz.org.
+    pub fn is_public(&self) -> bool {
+        let name = self.host.split(':').next().unwrap_or(&self.host);
+        name == PUBLIC_HOST || name.ends_with(&format!(".{}", PUBLIC_HOST))
+    }
+
+    pub fn effective_rate_limit(&self) -> Duration {
+        self.rate_limit.unwrap_or(if self.is_public() {
+            RATE_LIMIT
+        } else {
+            Duration::ZERO
+        })
+    }
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            host: PUBLIC_HOST.to_string(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            rate_limit: None,
+            burst: DEFAULT_BURST,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            cache: ResponseCache::in_default_dir(),
+            offline_dump: None,
+        }
+    }
+}
+
+impl<T> SearchPage<T> {
+    fn from_result(result: SearchResult<T>) -> Self {
+        Self {
+            offset: result.offset.max(0) as usize,
+            total: result.count.max(0) as usize,
+            entities: result.entities,
+        }
+    }
+}
+
+/// A MusicBrainz client.  Clones share the rate limiter, so they can be
+/// handed to concurrent tasks.
+#[derive(Debug, Clone)]
 pub struct Client {
-    last_request: Option<Instant>,
+    backend: Backend,
+    inner: MusicBrainzClient,
+    retry: RetryPolicy,
+    cache: Option<ResponseCache>,
+}
+
+/// Where queries are answered.
+#[derive(Debug, Clone)]
+enum Backend {
+    Server,
+    Dump(Arc<DumpStore>),
 }
 
 impl Client {
-    pub fn new() -> Self {
-        Self { last_request: None }
+    pub fn new(config: &ClientConfig) -> Result<Self> {
+        let backend = match &config.offline_dump {
+            Some(dir) => Backend::Dump(Arc::new(DumpStore::open(dir)?)),
+            None => Backend::Server,
+        };
+
+        let mut inner = MusicBrainzClient::default();
+        inner.musicbrainz_domain = config.host.clone();
+        inner
+            .set_user_agent(&config.user_agent)
+            .wrap_err_with(|| format!("Invalid user agent '{}'", config.user_agent))?;
+        // Requests are throttled here instead, so that local servers can be
+        // queried without a limit.
+        inner.drop_ratelimit();
+
+        let rate_limit = config.effective_rate_limit();
+        Ok(Self {
+            backend,
+            inner,
+            retry: RetryPolicy {
+                limiter: RateLimiter::new(rate_limit, config.burst),
+                min_delay: rate_limit,
+                max_attempts: config.max_attempts.max(1),
+            },
+            cache: config.cache.clone(),
+        })
+    }
+
+    /// How requests are paced and retried, for clients of related services
+    /// that should count towards the same limit.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry.clone()
+    }
+
+    /// Send a request, retrying transient failures with exponential backoff.
+    ///
+    /// Responses are served from the cache when possible.  `on_retry` is
+    /// called before waiting for each retry.
+    async fn get<T: DeserializeOwned>(
+        &self,
+        url: String,
+        on_retry: OnRetry<'_>,
+    ) -> Result<T, ApiError> {
+        // MusicBrainz queries are case-insensitive, and so are MBIDs.
+        let cache_key = url.to_lowercase();
+        if let Some(json) = self.cache.as_ref().and_then(|it| it.get(&cache_key))
+            && let Ok(result) = parse_json(json, &url)
+        {
+            return Ok(result);
+        }
+
+        let json = self.retry.run(on_retry, || self.get_json(&url)).await?;
+        let result = parse_json(json.clone(), &url)?;
+        if let Some(cache) = &self.cache {
+            // The cache is only an optimisation, so a failure to write it
+            // shouldn't fail the search.
+            let _ = cache.put(&cache_key, &json);
+        }
+        Ok(result)
+    }
+
+    async fn get_json(&self, url: &str) -> Result<serde_json::Value, ApiError> {
+        let response = ApiRequest::new(url.to_string())
+            .send_raw(&self.inner)
+            .await
+            .map_err(|e| ApiError::new(SERVICE, ErrorKind::Network, e))?;
+        if let Some(error) = http::status_error(SERVICE, &response) {
+            return Err(error);
+        }
+        http::read_json(SERVICE, response).await
+    }
+
+    /// Search for releases with a Lucene query, starting at the given
+    /// result.
+    pub async fn search_releases(
+        &self,
+        query: &str,
+        offset: usize,
+        on_retry: OnRetry<'_>,
+    ) -> Result<SearchPage<Release>> {
+        if let Backend::Dump(store) = &self.backend {
+            return store.search_releases(query, offset, SEARCH_PAGE_SIZE as usize);
+        }
+        let query = format!("query={}", percent_encode(query));
+        let url = Release::search(query)
+            .limit(SEARCH_PAGE_SIZE)
+            .offset(offset.min(u16::MAX as usize) as u16)
+            .as_api_request(&self.inner)
+            .url;
+        let result: SearchResult<Release> = self.get(url, on_retry).await?;
+
+        Ok(SearchPage::from_result(result))
+    }
+
+    /// Search for recordings with a Lucene query, starting at the given
+    /// result.  Each recording lists the releases it appears on.
+    pub async fn search_recordings(
+        &self,
+        query: &str,
+        offset: usize,
+        on_retry: OnRetry<'_>,
+    ) -> Result<SearchPage<Recording>> {
+        if let Backend::Dump(store) = &self.backend {
+            return store.search_recordings(query, offset, SEARCH_PAGE_SIZE as usize);
+        }
+        let query = format!("query={}", percent_encode(query));
+        let url = Recording::search(query)
+            .limit(SEARCH_PAGE_SIZE)
+            .offset(offset.min(u16::MAX as usize) as u16)
+            .as_api_request(&self.inner)
+            .url;
+        let result: SearchResult<Recording> = self.get(url, on_retry).await?;
+
+        Ok(SearchPage::from_result(result))
     }
 
-    /// Enforce the API's rate limit.
-    async fn throttle(&mut self) {
-        if let Some(last) = self.last_request {
-            let elapsed = last.elapsed();
-            if elapsed < RATE_LIMIT {
-                let wait = RATE_LIMIT - elapsed;
-                sleep(wait).await;
+    /// Look up a release with its tracklist, artist credits, labels, release
+    /// group, medium formats, and the genres and tags of the release.
+    pub async fn lookup_release(&self, mbid: &str, on_retry: OnRetry<'_>) -> Result<Release> {
+        if let Backend::Dump(store) = &self.backend {
+            return store.lookup_release(mbid);
+        }
+        let url = Release::fetch()
+            .id(mbid)
+            .with_recordings()
+            .with_artist_credits()
+            .with_labels()
+            .with_release_groups()
+            .with_media()
+            .with_genres()
+            .with_tags()
+            .as_api_request(&self.inner)
+            .url;
+        Ok(self.get(url, on_retry).await?)
+    }
+
+    /// Like [`Client::lookup_release`], with the genres and tags of the
+    /// release group too, which is where most of them are.  That takes an
+    /// extra request, so only callers proposing genres ask for it.
+    pub async fn lookup_release_with_genres(
+        &self,
+        mbid: &str,
+        on_retry: OnRetry<'_>,
+    ) -> Result<Release> {
+        let mut release = self.lookup_release(mbid, on_retry).await?;
+        // The release group comes without its tags.  A dump has no separate
+        // release groups to ask.
+        if let Backend::Server = self.backend
+            && let Some(group) = &mut release.release_group
+            && group.tags.is_none()
+            && group.genres.is_none()
+        {
+            let url = ReleaseGroup::fetch()
+                .id(&group.id)
+                .with_genres()
+                .with_tags()
+                .as_api_request(&self.inner)
+                .url;
+            // Genres are only a suggestion, so the release is still worth
+            // showing without them.
+            if let Ok(tagged) = self.get::<ReleaseGroup>(url, on_retry).await {
+                group.genres = tagged.genres;
+                group.tags = tagged.tags;
             }
         }
-        self.last_request = Some(Instant::now());
+        Ok(release)
     }
 
-    /// Search for releases by album artist and album.
-    pub async fn search_release(
-        &mut self,
-        album_artist: &str,
-        album: &str,
+    /// List the releases in a release group.
+    pub async fn browse_release_group(
+        &self,
+        mbid: &str,
+        on_retry: OnRetry<'_>,
     ) -> Result<Vec<Release>> {
-        self.throttle().await;
+        if let Backend::Dump(store) = &self.backend {
+            return store.browse_release_group(mbid);
+        }
+        let url = Release::browse()
+            .by_release_group(mbid)
+            .with_artist_credits()
+            .with_labels()
+            .with_medias()
+            .limit(BROWSE_LIMIT)
+            .as_api_request(&self.inner)
+            .url;
+
+        let result: BrowseResult<Release> = self.get(url, on_retry).await?;
+        Ok(result.entities)
+    }
+}
+
+fn parse_json<T: DeserializeOwned>(json: serde_json::Value, url: &str) -> Result<T, ApiError> {
+    ApiRequest::parse_json(json, url).map_err(|e| match e {
+        musicbrainz_rs::Error::MusicbrainzError(..) | musicbrainz_rs::Error::NotFound(_) => {
+            ApiError::new(SERVICE, ErrorKind::Rejected, e)
+        }
+        _ => ApiError::new(SERVICE, ErrorKind::Parse, e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::musicbrainz::fake::{FakeServer, Fixture};
 
-        let query = ReleaseSearchQuery::query_builder()
-            .artist_name(album_artist)
-            .and()
-            .release(album)
-            .build();
+    const RELEASE_ID: &str = "b84ee12a-09ef-421b-82de-0441a926375b";
+    const BARCODE_QUERY: &str = "barcode:5012345678900";
 
-        let result = Release::search(query)
-            .execute()
+    #[tokio::test]
+    async fn search_reads_a_page_of_releases() {
+        let server = FakeServer::start(Fixture::load("release_search")).await;
+        let client = Client::new(&server.config()).unwrap();
+
+        let page = client
+            .search_releases(BARCODE_QUERY, 0, &|_| {})
             .await
-            .map_err(|e| eyre!("MusicBrainz API error: {}", e))?;
+            .unwrap();
 
-        Ok(result.entities)
+        assert_eq!(page.entities.len(), 1);
+        assert_eq!(page.entities[0].id, RELEASE_ID);
+        assert_eq!(page.entities[0].title, "Fixture Songs");
+        assert_eq!(page.total, 30);
+        assert_eq!(page.next_offset(), Some(1));
+        assert_eq!(
+            server.requests(),
+            [format!(
+                "/ws/2/release?fmt=json&query={}&limit=25&offset=0",
+                BARCODE_QUERY
+            )]
+        );
     }
-}
 
-impl Default for Client {
-    fn default() -> Self {
-        Self::new()
+    #[tokio::test]
+    async fn search_without_matches_is_empty() {
+        let server = FakeServer::start(Fixture::load("empty")).await;
+        let client = Client::new(&server.config()).unwrap();
+
+        let page = client
+            .search_releases("release:\"Nothing\"", 0, &|_| {})
+            .await
+            .unwrap();
+
+        assert!(page.entities.is_empty());
+        assert_eq!(page.next_offset(), None);
+    }
+
+    #[tokio::test]
+    async fn lookup_includes_release_group_tags() {
+        let server = FakeServer::start(Fixture::load("release_lookup")).await;
+        let client = Client::new(&server.config()).unwrap();
+
+        let release = client
+            .lookup_release_with_genres(RELEASE_ID, &|_| {})
+            .await
+            .unwrap();
+
+        let media = release.media.as_deref().unwrap();
+        let titles: Vec<&str> = media[0]
+            .tracks
+            .iter()
+            .flatten()
+            .map(|it| it.title.as_str())
+            .collect();
+        assert_eq!(titles, ["Opening Test", "Assertion Blues", "Teardown"]);
+        let group_tags: Vec<&str> = release
+            .release_group
+            .as_ref()
+            .and_then(|it| it.tags.as_deref())
+            .unwrap()
+            .iter()
+            .map(|it| it.name.as_str())
+            .collect();
+        assert_eq!(group_tags, ["rock", "hip hop"]);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn plain_lookup_skips_the_release_group() {
+        let server = FakeServer::start(Fixture::load("release_lookup")).await;
+        let client = Client::new(&server.config()).unwrap();
+
+        let release = client.lookup_release(RELEASE_ID, &|_| {}).await.unwrap();
+
+        let group = release.release_group.unwrap();
+        assert!(group.tags.is_none());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn unknown_release_is_rejected_without_retrying() {
+        let server = FakeServer::start(Fixture::default()).await;
+        let client = Client::new(&server.config()).unwrap();
+
+        let error = client
+            .lookup_release(RELEASE_ID, &|_| panic!("retried a rejected request"))
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("request rejected"), "{}", error);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_request_is_retried() {
+        let server = FakeServer::start(Fixture::load("rate_limited")).await;
+        let client = Client::new(&server.config()).unwrap();
+        let retries = Mutex::new(Vec::new());
+
+        let page = client
+            .search_releases(BARCODE_QUERY, 0, &|retry| {
+                retries
+                    .lock()
+                    .unwrap()
+                    .push((retry.attempt, retry.error.kind, retry.delay));
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(page.entities.len(), 1);
+        assert_eq!(
+            *retries.lock().unwrap(),
+            [(2, ErrorKind::RateLimited, Duration::ZERO)]
+        );
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn retry_after_date_is_honoured() {
+        let fixture: Fixture = serde_json::from_value(serde_json::json!({
+            "routes": [{
+                "path": "/ws/2/release",
+                "responses": [
+                    {
+                        "status": 503,
+                        "headers": { "Retry-After": "Wed, 21 Oct 2015 07:28:00 GMT" }
+                    },
+                    {
+                        "body": {
+                            "created": "2025-06-01T12:00:00.000Z",
+                            "count": 0,
+                            "offset": 0,
+                            "releases": []
+                        }
+                    }
+                ]
+            }]
+        }))
+        .unwrap();
+        let server = FakeServer::start(fixture).await;
+        let client = Client::new(&server.config()).unwrap();
+        let retries = Mutex::new(Vec::new());
+
+        client
+            .search_releases(BARCODE_QUERY, 0, &|retry| {
+                retries
+                    .lock()
+                    .unwrap()
+                    .push((retry.error.kind, retry.delay));
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *retries.lock().unwrap(),
+            [(ErrorKind::RateLimited, Duration::ZERO)]
+        );
+    }
+
+    #[tokio::test]
+    async fn unavailable_server_is_given_up_on() {
+        let server = FakeServer::start(Fixture::load("unavailable")).await;
+        let mut config = server.config();
+        config.max_attempts = 3;
+        let client = Client::new(&config).unwrap();
+
+        let error = client
+            .search_releases(BARCODE_QUERY, 0, &|_| {})
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("rate limited"), "{}", error);
+        assert_eq!(server.requests().len(), 3);
     }
 }
@@ -0,0 +1,500 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Cover Art Archive client.
+//!
+//! The archive holds the artwork for MusicBrainz releases.  Its requests
+//! share the MusicBrainz client's rate limiter, and the images themselves
+//! are downloaded to a cache directory, one at a time as they are looked at,
+//! so they can be picked from.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+    time::SystemTime,
+};
+
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use serde::Deserialize;
+
+use crate::{
+    musicbrainz::{cache::ResponseCache, client::Client as MbClient},
+    paths::escape_path,
+    source::{
+        OnRetry,
+        error::{ApiError, ErrorKind, RetryAttempt},
+        http::{self, DEFAULT_MAX_ATTEMPTS, DEFAULT_USER_AGENT, RetryPolicy},
+    },
+};
+
+/// Root of the public Cover Art Archive.
+pub const PUBLIC_BASE_URL: &str = "https://coverartarchive.org";
+
+/// Name of the service, as given in errors.
+const SERVICE: &str = "Cover Art Archive";
+
+/// Total size of downloaded images above which the oldest are dropped.
+const DEFAULT_MAX_IMAGE_BYTES: u64 = 200 * 1024 * 1024;
+
+/// Image types worth tagging with, in the order they are listed.
+const IMAGE_TYPES: [&str; 3] = ["Front", "Back", "Booklet"];
+
+/// Where and how to talk to the Cover Art Archive.
+#[derive(Debug, Clone)]
+pub struct CoverArtConfig {
+    /// Root of the archive, e.g. `http://localhost:8080` for a local
+    /// stand-in.
+    pub base_url: String,
+    pub user_agent: String,
+    pub max_attempts: u32,
+    /// Where to keep image listings between sessions, if anywhere.  The
+    /// images themselves go in an `images` directory inside it.
+    pub cache: Option<ResponseCache>,
+    /// Total size of downloaded images to keep.
+    pub max_image_bytes: u64,
+}
+
+impl Default for CoverArtConfig {
+    fn default() -> Self {
+        Self {
+            base_url: PUBLIC_BASE_URL.to_string(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            cache: ResponseCache::for_service("coverart"),
+            max_image_bytes: DEFAULT_MAX_IMAGE_BYTES,
+        }
+    }
+}
+
+impl CoverArtConfig {
+    /// Set the root of the archive from a URL, with or without a trailing
+    /// slash.
+    pub fn set_base_url(&mut self, url: &str) {
+        self.base_url = url.trim_end_matches('/').to_string();
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct Listing {
+    images: Vec<Image>,
+}
+
+/// An image of a release, as listed by the archive.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Image {
+    /// A number, though older listings give it as a string.
+    id: serde_json::Value,
+    /// URL of the full-size image.
+    pub image: String,
+    /// What the image shows, e.g. `["Front"]` or `["Back", "Spine"]`.
+    pub types: Vec<String>,
+    pub comment: String,
+}
+
+impl Image {
+    pub fn id(&self) -> String {
+        match &self.id {
+            serde_json::Value::String(id) => id.clone(),
+            id => id.to_string(),
+        }
+    }
+
+    /// The first of the types we are interested in, e.g. "Front".
+    pub fn kind(&self) -> &str {
+        IMAGE_TYPES
+            .into_iter()
+            .find(|kind| self.types.iter().any(|it| it == kind))
+            .unwrap_or("Other")
+    }
+}
+
+/// An image downloaded to the cache.
+#[derive(Debug, Clone)]
+pub struct CachedImage {
+    pub path: PathBuf,
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// Width and height in pixels, when the format is one we can read.
+    pub dimensions: Option<(u32, u32)>,
+}
+
+impl CachedImage {
+    fn read(path: PathBuf) -> Result<Self> {
+        let bytes =
+            fs::read(&path).wrap_err_with(|| format!("Failed to read {}", escape_path(&path)))?;
+        Ok(Self {
+            dimensions: image_dimensions(&bytes),
+            size: bytes.len() as u64,
+            path,
+        })
+    }
+}
+
+pub enum CoverArtMessage {
+    /// The front, back and booklet images of a release.
+    Images(String, Vec<Image>),
+    ImagesError(String, String),
+    /// An image of a release, by its ID, has been downloaded.
+    Downloaded(String, String, CachedImage),
+    DownloadError(String, String, String),
+    /// A request for a release's images is being retried, as described.
+    Retrying(String, String),
+}
+
+/// A Cover Art Archive client.  Clones share the rate limiter, so they can
+/// be handed to concurrent tasks.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    retry: RetryPolicy,
+    cache: Option<ResponseCache>,
+    image_dir: PathBuf,
+    max_image_bytes: u64,
+}
+
+impl Client {
+    /// Create a client whose requests count towards the MusicBrainz
+    /// client's rate limit.
+    pub fn new(config: &CoverArtConfig, musicbrainz: &MbClient) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .user_agent(&config.user_agent)
+            .build()
+            .wrap_err("Failed to set up the Cover Art Archive client")?;
+        // Without a cache the images still need to go somewhere to be
+        // looked at.
+        let image_dir = match &config.cache {
+            Some(cache) => cache.dir.join("images"),
+            None => env::temp_dir().join("tuners-coverart"),
+        };
+        Ok(Self {
+            http,
+            base_url: config.base_url.clone(),
+            retry: RetryPolicy {
+                max_attempts: config.max_attempts.max(1),
+                ..musicbrainz.retry_policy()
+            },
+            cache: config.cache.clone(),
+            image_dir,
+            max_image_bytes: config.max_image_bytes,
+        })
+    }
+
+    /// List the front, back and booklet images of a release.  Releases
+    /// without artwork have none.
+    pub async fn list_images(&self, mbid: &str, on_retry: OnRetry<'_>) -> Result<Vec<Image>> {
+        let url = format!("{}/release/{}", self.base_url, mbid);
+        let json = match self.cache.as_ref().and_then(|it| it.get(&url)) {
+            Some(json) => json,
+            None => {
+                // The archive has nothing at all for releases without
+                // artwork.
+                let Some(bytes) = self.get(&url, on_retry).await? else {
+                    return Ok(Vec::new());
+                };
+                let json: serde_json::Value =
+                    serde_json::from_slice(&bytes).map_err(parse_error)?;
+                if let Some(cache) = &self.cache {
+                    let _ = cache.put(&url, &json);
+                }
+                json
+            }
+        };
+        let listing: Listing = serde_json::from_value(json).map_err(parse_error)?;
+
+        let mut images: Vec<Image> = listing
+            .images
+            .into_iter()
+            .filter(|it| IMAGE_TYPES.contains(&it.kind()))
+            .collect();
+        images.sort_by_key(|it| IMAGE_TYPES.iter().position(|kind| *kind == it.kind()));
+        Ok(images)
+    }
+
+    /// Download an image of a release, unless it is already cached.
+    pub async fn download(
+        &self,
+        mbid: &str,
+        image: &Image,
+        on_retry: OnRetry<'_>,
+    ) -> Result<CachedImage> {
+        let path = self.image_dir.join(image_file_name(mbid, image));
+        if path.exists() {
+            return CachedImage::read(path);
+        }
+
+        let bytes = self
+            .get(&image.image, on_retry)
+            .await?
+            .ok_or_else(|| eyre!("Image {} is missing from the archive", image.id()))?;
+        fs::create_dir_all(&self.image_dir).wrap_err_with(|| {
+            format!(
+                "Failed to create image directory {}",
+                escape_path(&self.image_dir)
+            )
+        })?;
+        // Write to a temporary file first so that a cut-off download isn't
+        // mistaken for the image next time.
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, &bytes)
+            .and_then(|_| fs::rename(&tmp, &path))
+            .wrap_err_with(|| format!("Failed to save {}", escape_path(&path)))?;
+        evict_images(&self.image_dir, self.max_image_bytes, &path).wrap_err_with(|| {
+            format!(
+                "Failed to clean up image directory {}",
+                escape_path(&self.image_dir)
+            )
+        })?;
+
+        Ok(CachedImage {
+            dimensions: image_dimensions(&bytes),
+            size: bytes.len() as u64,
+            path,
+        })
+    }
+
+    /// Send a request, retrying transient failures with exponential backoff.
+    ///
+    /// Returns `None` when there is nothing at the URL.
+    async fn get(&self, url: &str, on_retry: OnRetry<'_>) -> Result<Option<Vec<u8>>, ApiError> {
+        self.retry.run(on_retry, || self.get_bytes(url)).await
+    }
+
+    async fn get_bytes(&self, url: &str) -> Result<Option<Vec<u8>>, ApiError> {
+        // Image URLs redirect to wherever the file is stored, which reqwest
+        // follows.
+        let response = self
+            .http
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| ApiError::new(SERVICE, ErrorKind::Network, e))?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if let Some(error) = http::status_error(SERVICE, &response) {
+            return Err(error);
+        }
+
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| ApiError::new(SERVICE, ErrorKind::Network, e))?;
+        Ok(Some(bytes.to_vec()))
+    }
+}
+
+/// List the images of a release.
+pub async fn list_cover_art(client: &Client, tx: Sender<CoverArtMessage>, mbid: String) {
+    let on_retry = |retry: &RetryAttempt| {
+        let status = retry.describe("Listing cover art...");
+        let _ = tx.send(CoverArtMessage::Retrying(mbid.clone(), status));
+    };
+    let message = match client.list_images(&mbid, &on_retry).await {
+        Ok(images) => CoverArtMessage::Images(mbid, images),
+        Err(e) => CoverArtMessage::ImagesError(mbid, e.to_string()),
+    };
+    let _ = tx.send(message);
+}
+
+/// Download an image of a release.
+pub async fn download_cover_art(
+    client: &Client,
+    tx: Sender<CoverArtMessage>,
+    mbid: String,
+    image: Image,
+) {
+    let on_retry = |retry: &RetryAttempt| {
+        let status = retry.describe(&format!("Downloading {}...", image.kind()));
+        let _ = tx.send(CoverArtMessage::Retrying(mbid.clone(), status));
+    };
+    let message = match client.download(&mbid, &image, &on_retry).await {
+        Ok(cached) => CoverArtMessage::Downloaded(mbid, image.id(), cached),
+        Err(e) => CoverArtMessage::DownloadError(mbid, image.id(), e.to_string()),
+    };
+    let _ = tx.send(message);
+}
+
+/// Where an image is kept, e.g. `<mbid>-<id>.jpg`.  Both IDs come from the
+/// server, so anything but letters, digits and dashes is replaced to keep
+/// the name inside the image directory.
+fn image_file_name(mbid: &str, image: &Image) -> String {
+    let safe = |text: &str| -> String {
+        text.chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    };
+    let extension = Path::new(image.image.rsplit('/').next().unwrap_or_default())
+        .extension()
+        .and_then(|it| it.to_str())
+        .filter(|it| {
+            !it.is_empty() && it.len() <= 4 && it.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+        .unwrap_or("jpg")
+        .to_lowercase();
+    format!("{}-{}.{}", safe(mbid), safe(&image.id()), extension)
+}
+
+/// Drop the least recently downloaded images until the directory fits
+/// within `max_bytes`, keeping the one just downloaded.
+fn evict_images(dir: &Path, max_bytes: u64, keep: &Path) -> Result<()> {
+    let mut entries: Vec<(SystemTime, u64, PathBuf)> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            entries.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+    }
+
+    let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    entries.sort();
+    for (_, size, path) in entries {
+        if total <= max_bytes {
+            break;
+        }
+        if path != keep {
+            fs::remove_file(&path)?;
+            total -= size;
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_error(error: serde_json::Error) -> ApiError {
+    ApiError::new(SERVICE, ErrorKind::Parse, error)
+}
+
+/// Width and height of a JPEG, PNG or GIF image, read from its header.
+fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |at: usize| Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+    let be32 = |at: usize| Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let le16 = |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // The IHDR chunk always comes first.
+        return Some((be32(16)?, be32(20)?));
+    }
+    if bytes.starts_with(b"GIF8") {
+        return Some((le16(6)?, le16(8)?));
+    }
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+
+    // Walk the JPEG segments until a start-of-frame, which holds the size.
+    let mut at = 2;
+    loop {
+        if *bytes.get(at)? != 0xFF {
+            return None;
+        }
+        let marker = *bytes.get(at + 1)?;
+        match marker {
+            // Padding before a marker.
+            0xFF => at += 1,
+            // Markers without a length.
+            0x01 | 0xD0..=0xD7 => at += 2,
+            // Every start-of-frame except DHT, JPG and DAC, which share the
+            // range.
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                return Some((be16(at + 7)?, be16(at + 5)?));
+            }
+            _ => at += 2 + be16(at + 2)? as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::musicbrainz::fake::{FakeServer, Fixture};
+
+    const MBID: &str = "b84ee12a-09ef-421b-82de-0441a926375b";
+
+    fn client(server: &FakeServer, dir: &Path) -> Client {
+        let config = CoverArtConfig {
+            base_url: server.url(),
+            cache: Some(ResponseCache::new(dir.to_path_buf())),
+            ..CoverArtConfig::default()
+        };
+        Client::new(&config, &MbClient::new(&server.config()).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn images_are_listed_front_first_and_downloaded_once() {
+        let server = FakeServer::start(Fixture::load_for("coverart", "release_images")).await;
+        let dir = env::temp_dir().join(format!("tuners-coverart-{}", std::process::id()));
+        let client = client(&server, &dir);
+
+        let images = client.list_images(MBID, &|_| {}).await.unwrap();
+        let kinds: Vec<&str> = images.iter().map(|it| it.kind()).collect();
+        assert_eq!(kinds, ["Front", "Back"]);
+
+        let back = client.download(MBID, &images[1], &|_| {}).await.unwrap();
+        let requests = server.requests().len();
+        let again = client.download(MBID, &images[1], &|_| {}).await.unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        // The server's ID can't lead outside the image directory.
+        assert_eq!(
+            back.path,
+            dir.join("images").join(format!("{}-___2.png", MBID))
+        );
+        assert_eq!(back.size, "\"back\"".len() as u64);
+        assert_eq!(again.path, back.path);
+        assert_eq!(server.requests().len(), requests);
+    }
+
+    #[test]
+    fn oldest_images_are_evicted_first() {
+        let dir = env::temp_dir().join(format!("tuners-evict-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let now = SystemTime::now();
+        let write = |name: &str, age: u64| {
+            let path = dir.join(name);
+            fs::write(&path, [0; 10]).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(now - Duration::from_secs(age))
+                .unwrap();
+            path
+        };
+        let oldest = write("a.jpg", 30);
+        let older = write("b.jpg", 20);
+        let newest = write("c.jpg", 10);
+        // The image just downloaded stays even when it is the oldest.
+        let keep = write("d.jpg", 40);
+
+        evict_images(&dir, 20, &keep).unwrap();
+        let exists = [&oldest, &older, &newest, &keep].map(|it| it.exists());
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(exists, [false, false, true, true]);
+    }
+
+    #[test]
+    fn file_names_stay_in_the_image_directory() {
+        let image = Image {
+            id: serde_json::json!("../../etc/passwd"),
+            image: String::from("http://example.org/a/b"),
+            ..Image::default()
+        };
+        assert_eq!(image_file_name("../x", &image), "___x-______etc_passwd.jpg");
+    }
+}
@@ -0,0 +1,1029 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Offline search against a local copy of the MusicBrainz release dump.
+//!
+//! The JSON dumps hold one release per line, in the same format the web
+//! service returns.  Importing copies the releases into a store directory
+//! along with an on-disk inverted index of the fields our queries use, so
+//! that the queries built in [`crate::musicbrainz::query`] can be answered
+//! without a network connection, and without loading the index into memory.
+//!
+//! The index is made of three files next to the releases:
+//!
+//! - `terms.fst` maps each `field\0token` to where its postings start.
+//! - `postings.bin` holds, for each term, a count followed by the positions
+//!   of the releases containing it, in ascending order.
+//! - `records.bin` holds a fixed-size record per release: where it starts in
+//!   the releases file, and how many words its title and artist have.
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    env,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use color_eyre::eyre::{Result, WrapErr, bail};
+use fst::{
+    Automaton, IntoStreamer, Map, MapBuilder, Streamer,
+    automaton::{Levenshtein, Str},
+};
+use memmap2::Mmap;
+use musicbrainz_rs::entity::{
+    artist_credit::ArtistCredit,
+    recording::Recording,
+    release::{Release, Track},
+};
+
+use crate::{paths::escape_path, source::http::SearchPage};
+
+const RELEASES_FILE: &str = "releases.jsonl";
+const RECORDS_FILE: &str = "records.bin";
+const POSTINGS_FILE: &str = "postings.bin";
+const TERMS_FILE: &str = "terms.fst";
+
+/// Size of a release's entry in the records file.
+const RECORD_SIZE: usize = 12;
+
+/// How many releases to import between progress reports.
+const PROGRESS_INTERVAL: usize = 10_000;
+
+/// How many terms to collect in memory before sorting them out to a run
+/// file during import.
+const RUN_SIZE: usize = 1_000_000;
+
+/// A local store of imported releases.
+#[derive(Debug)]
+pub struct DumpStore {
+    /// The one handle on the releases file, shared by every read.
+    releases: Mutex<BufReader<File>>,
+    records: Mmap,
+    postings: Mmap,
+    terms: Map<Mmap>,
+}
+
+/// Where a release is and how long its ranked fields are.
+#[derive(Debug, Clone, Copy)]
+struct Record {
+    offset: u64,
+    title_words: u16,
+    artist_words: u16,
+}
+
+/// Outcome of importing a dump.
+#[derive(Debug, Clone, Copy)]
+pub struct ImportSummary {
+    pub releases: usize,
+    /// Lines that weren't releases the client could read.
+    pub skipped: usize,
+}
+
+/// One `field:value` part of a query.
+#[derive(Debug, Clone, PartialEq)]
+struct Clause {
+    field: String,
+    value: ClauseValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClauseValue {
+    /// Every token must be present.
+    Terms(Vec<String>),
+    /// Any of the words must be present, give or take a typo.
+    Fuzzy(Vec<String>),
+    /// An inclusive numeric range.
+    Range(u64, u64),
+}
+
+impl DumpStore {
+    /// Store in `$XDG_DATA_HOME/tuners/musicbrainz-dump`, falling back to
+    /// `~/.local/share`.  Returns `None` when neither variable is set.
+    pub fn default_dir() -> Option<PathBuf> {
+        let base = env::var_os("XDG_DATA_HOME")
+            .filter(|it| !it.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+            })?;
+        Some(base.join("tuners").join("musicbrainz-dump"))
+    }
+
+    /// Open a store created by [`import`].
+    pub fn open(dir: &Path) -> Result<Self> {
+        let open = |name: &str| {
+            File::open(dir.join(name)).wrap_err_with(|| {
+                format!(
+                    "No imported dump in {}; run with --import-dump first",
+                    escape_path(dir)
+                )
+            })
+        };
+        // SAFETY: an import only ever replaces these files by renaming new
+        // ones over them, so the mapped files are never written to.
+        let map = |name: &str| -> Result<Mmap> { Ok(unsafe { Mmap::map(&open(name)?)? }) };
+
+        let terms = Map::new(map(TERMS_FILE)?).wrap_err_with(|| {
+            format!("Corrupt dump index {}", escape_path(&dir.join(TERMS_FILE)))
+        })?;
+        Ok(Self {
+            releases: Mutex::new(BufReader::new(open(RELEASES_FILE)?)),
+            records: map(RECORDS_FILE)?,
+            postings: map(POSTINGS_FILE)?,
+            terms,
+        })
+    }
+
+    /// Search releases with one of our Lucene queries.  Only the releases
+    /// on the requested page are read from the store.
+    pub fn search_releases(
+        &self,
+        query: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<SearchPage<Release>> {
+        let clauses = parse_query(query);
+        let mut ranked = Vec::new();
+        for idx in self.matching(&clauses)? {
+            ranked.push((release_score(self.record(idx)?, &clauses), idx));
+        }
+        // Best first, keeping dump order among equals.
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let entities = ranked
+            .iter()
+            .skip(offset)
+            .take(limit)
+            .map(|(_, idx)| self.read(*idx))
+            .collect::<Result<_>>()?;
+        Ok(SearchPage {
+            entities,
+            offset,
+            total: ranked.len(),
+        })
+    }
+
+    /// Search recordings with one of our Lucene queries.  Each result lists
+    /// the one release it was found on.
+    ///
+    /// Releases are read only until the page is full and one more recording
+    /// is known to follow it, so the total is a lower bound that's still
+    /// enough to tell whether there's a next page.
+    pub fn search_recordings(
+        &self,
+        query: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<SearchPage<Recording>> {
+        let clauses = parse_query(query);
+        let wanted = offset + limit + 1;
+        let mut recordings = Vec::new();
+        for idx in self.matching(&clauses)? {
+            if recordings.len() >= wanted {
+                break;
+            }
+            let release = self.read(idx)?;
+            let tracks = release
+                .media
+                .iter()
+                .flatten()
+                .flat_map(|it| it.tracks.iter().flatten());
+            for track in tracks {
+                if !track_matches(track, &release, &clauses) {
+                    continue;
+                }
+                if let Some(mut recording) = track.recording.clone() {
+                    recording.releases = Some(vec![release.clone()]);
+                    recordings.push(recording);
+                }
+            }
+        }
+
+        Ok(page(recordings, offset, limit))
+    }
+
+    pub fn lookup_release(&self, mbid: &str) -> Result<Release> {
+        match self.postings("mbid", &mbid.to_lowercase())?.first() {
+            Some(&idx) => self.read(idx),
+            None => bail!("Release {} is not in the imported dump", mbid),
+        }
+    }
+
+    pub fn browse_release_group(&self, mbid: &str) -> Result<Vec<Release>> {
+        self.postings("rgid", &mbid.to_lowercase())?
+            .into_iter()
+            .map(|idx| self.read(idx))
+            .collect()
+    }
+
+    /// Releases matching every term clause, in dump order.  Ranges only
+    /// narrow recording searches, so they're checked per track instead.
+    fn matching(&self, clauses: &[Clause]) -> Result<Vec<u32>> {
+        let mut result: Option<Vec<u32>> = None;
+        for clause in clauses {
+            let found = match &clause.value {
+                ClauseValue::Terms(words) => words
+                    .iter()
+                    .map(|it| self.postings(&clause.field, it))
+                    .collect::<Result<Vec<_>>>()?,
+                // Lucene ORs the words in a group.
+                ClauseValue::Fuzzy(words) => {
+                    let mut ids = Vec::new();
+                    for word in words {
+                        ids.extend(self.near_postings(&clause.field, word)?);
+                    }
+                    ids.sort_unstable();
+                    ids.dedup();
+                    vec![ids]
+                }
+                ClauseValue::Range(..) => continue,
+            };
+            for ids in found {
+                result = Some(match result {
+                    Some(it) => intersect(&it, &ids),
+                    None => ids,
+                });
+            }
+        }
+
+        Ok(result.unwrap_or_default())
+    }
+
+    /// Releases with a token in a field.
+    fn postings(&self, field: &str, token: &str) -> Result<Vec<u32>> {
+        match self.terms.get(term_key(field, token)) {
+            Some(at) => self.postings_at(at),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Releases with a token in a field within a typo or two of a word.
+    fn near_postings(&self, field: &str, word: &str) -> Result<Vec<u32>> {
+        let distance = if word.chars().count() > 4 { 2 } else { 1 };
+        // Levenshtein distance ignores a shared prefix, so matching the
+        // whole key only allows edits to the token.
+        let Ok(near) = Levenshtein::new(&term_key(field, word), distance) else {
+            // Too long a word to build the automaton for.
+            return self.postings(field, word);
+        };
+        let prefix = term_key(field, "");
+        let automaton = Str::new(&prefix).starts_with().intersection(near);
+
+        let mut ids = Vec::new();
+        let mut stream = self.terms.search(automaton).into_stream();
+        while let Some((_, at)) = stream.next() {
+            ids.extend(self.postings_at(at)?);
+        }
+        Ok(ids)
+    }
+
+    fn postings_at(&self, at: u64) -> Result<Vec<u32>> {
+        let at = at as usize;
+        let Some(count) = self.postings.get(at..at + 4).map(read_u32) else {
+            bail!("Dump index refers to missing postings");
+        };
+        let end = at + 4 + count as usize * 4;
+        let Some(ids) = self.postings.get(at + 4..end) else {
+            bail!("Dump index refers to missing postings");
+        };
+        Ok(ids.chunks_exact(4).map(read_u32).collect())
+    }
+
+    fn record(&self, idx: u32) -> Result<Record> {
+        let start = idx as usize * RECORD_SIZE;
+        let Some(bytes) = self.records.get(start..start + RECORD_SIZE) else {
+            bail!("Dump index refers to a missing release");
+        };
+        Ok(Record {
+            offset: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            title_words: u16::from_le_bytes(bytes[8..10].try_into().unwrap()),
+            artist_words: u16::from_le_bytes(bytes[10..].try_into().unwrap()),
+        })
+    }
+
+    fn read(&self, idx: u32) -> Result<Release> {
+        let offset = self.record(idx)?.offset;
+        let mut releases = self.releases.lock().unwrap();
+        releases.seek(SeekFrom::Start(offset))?;
+        let mut line = String::new();
+        releases.read_line(&mut line)?;
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+/// Import a MusicBrainz JSON release dump, or any subset of its lines, into
+/// a store, replacing whatever was imported before.
+///
+/// Terms are collected in sorted runs on disk and merged into the index at
+/// the end, so a full dump can be imported in bounded memory.
+pub fn import(dump: &Path, dir: &Path, progress: impl Fn(usize)) -> Result<ImportSummary> {
+    let input = BufReader::new(
+        File::open(dump).wrap_err_with(|| format!("Failed to open {}", escape_path(dump)))?,
+    );
+    fs::create_dir_all(dir).wrap_err_with(|| format!("Failed to create {}", escape_path(dir)))?;
+
+    // Write next to the old store and swap at the end, so a failed import
+    // leaves it intact.
+    let tmp = |name: &str| dir.join(format!("{}.tmp", name));
+    let mut releases = BufWriter::new(File::create(tmp(RELEASES_FILE))?);
+    let mut records = BufWriter::new(File::create(tmp(RECORDS_FILE))?);
+    let mut terms = Vec::new();
+    let mut runs = Vec::new();
+    let mut summary = ImportSummary {
+        releases: 0,
+        skipped: 0,
+    };
+    let mut offset = 0u64;
+
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Ok(release) = serde_json::from_str::<Release>(line) else {
+            summary.skipped += 1;
+            continue;
+        };
+
+        let idx = summary.releases as u32;
+        let words = |text: &str| tokenize(text).len().min(u16::MAX as usize) as u16;
+        records.write_all(&offset.to_le_bytes())?;
+        records.write_all(&words(&release.title).to_le_bytes())?;
+        records.write_all(&words(&credit_text(release.artist_credit.as_deref())).to_le_bytes())?;
+
+        terms.push((term_key("mbid", &release.id.to_lowercase()), idx));
+        for (field, tokens) in release_fields(&release) {
+            terms.extend(tokens.iter().map(|it| (term_key(field, it), idx)));
+        }
+        if terms.len() >= RUN_SIZE {
+            runs.push(write_run(dir, runs.len(), &mut terms)?);
+        }
+
+        releases.write_all(line.as_bytes())?;
+        releases.write_all(b"\n")?;
+        offset += line.len() as u64 + 1;
+
+        summary.releases += 1;
+        if summary.releases.is_multiple_of(PROGRESS_INTERVAL) {
+            progress(summary.releases);
+        }
+    }
+    releases.flush()?;
+    records.flush()?;
+    runs.push(write_run(dir, runs.len(), &mut terms)?);
+
+    let merged = merge_runs(&runs, &tmp(TERMS_FILE), &tmp(POSTINGS_FILE));
+    for run in &runs {
+        let _ = fs::remove_file(run);
+    }
+    merged?;
+
+    for name in [RELEASES_FILE, RECORDS_FILE, POSTINGS_FILE, TERMS_FILE] {
+        fs::rename(tmp(name), dir.join(name))?;
+    }
+
+    Ok(summary)
+}
+
+/// Sort terms and write them out as a run file, emptying `terms`.
+fn write_run(dir: &Path, number: usize, terms: &mut Vec<(String, u32)>) -> Result<PathBuf> {
+    terms.sort_unstable();
+    let path = dir.join(format!("run-{}.tmp", number));
+    let mut output = BufWriter::new(File::create(&path)?);
+    for (key, idx) in terms.drain(..) {
+        output.write_all(&(key.len() as u32).to_le_bytes())?;
+        output.write_all(key.as_bytes())?;
+        output.write_all(&idx.to_le_bytes())?;
+    }
+    output.flush()?;
+    Ok(path)
+}
+
+/// Read the next term from a run file.
+fn read_term(run: &mut impl Read) -> Result<Option<(Vec<u8>, u32)>> {
+    let mut len = [0; 4];
+    match run.read_exact(&mut len) {
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+    let mut key = vec![0; u32::from_le_bytes(len) as usize];
+    run.read_exact(&mut key)?;
+    let mut idx = [0; 4];
+    run.read_exact(&mut idx)?;
+    Ok(Some((key, u32::from_le_bytes(idx))))
+}
+
+/// Merge sorted run files into the terms and postings files.
+fn merge_runs(runs: &[PathBuf], terms_path: &Path, postings_path: &Path) -> Result<()> {
+    let mut readers = runs
+        .iter()
+        .map(|it| Ok(BufReader::new(File::open(it)?)))
+        .collect::<Result<Vec<_>>>()?;
+    // Ties go to the earlier run, whose releases come first, so each term's
+    // postings come out in ascending order.
+    let mut heap = BinaryHeap::new();
+    for (run, reader) in readers.iter_mut().enumerate() {
+        if let Some((key, idx)) = read_term(reader)? {
+            heap.push(Reverse((key, run, idx)));
+        }
+    }
+
+    let mut terms = MapBuilder::new(BufWriter::new(File::create(terms_path)?))?;
+    let mut postings = BufWriter::new(File::create(postings_path)?);
+    let mut written = 0u64;
+    let mut current: Option<(Vec<u8>, Vec<u32>)> = None;
+    let mut flush = |key: &[u8], ids: &[u32]| -> Result<()> {
+        terms.insert(key, written)?;
+        postings.write_all(&(ids.len() as u32).to_le_bytes())?;
+        for idx in ids {
+            postings.write_all(&idx.to_le_bytes())?;
+        }
+        written += 4 * (ids.len() as u64 + 1);
+        Ok(())
+    };
+
+    while let Some(Reverse((key, run, idx))) = heap.pop() {
+        if let Some((key, idx)) = read_term(&mut readers[run])? {
+            heap.push(Reverse((key, run, idx)));
+        }
+        match &mut current {
+            Some((current_key, ids)) if *current_key == key => {
+                // A token repeated within one release.
+                if ids.last() != Some(&idx) {
+                    ids.push(idx);
+                }
+            }
+            _ => {
+                if let Some((key, ids)) = current.replace((key, vec![idx])) {
+                    flush(&key, &ids)?;
+                }
+            }
+        }
+    }
+    if let Some((key, ids)) = current {
+        flush(&key, &ids)?;
+    }
+
+    postings.flush()?;
+    terms.finish()?;
+    Ok(())
+}
+
+fn term_key(field: &str, token: &str) -> String {
+    format!("{}\0{}", field, token)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().unwrap())
+}
+
+/// Members of both sorted lists.
+fn intersect(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut both = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                both.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    both
+}
+
+/// The tokens of a release under each query field.
+fn release_fields(release: &Release) -> Vec<(&'static str, Vec<String>)> {
+    let media = release.media.as_deref().unwrap_or_default();
+    let tracks: Vec<&Track> = media
+        .iter()
+        .flat_map(|it| it.tracks.iter().flatten())
+        .collect();
+    let labels = release.label_info.as_deref().unwrap_or_default();
+
+    let mut artists = credit_text(release.artist_credit.as_deref());
+    for track in &tracks {
+        artists.push(' ');
+        artists.push_str(&credit_text(track.artist_credit.as_deref()));
+    }
+    let mut recordings = String::new();
+    let mut isrcs = Vec::new();
+    for track in &tracks {
+        recordings.push_str(&track.title);
+        recordings.push(' ');
+        if let Some(recording) = &track.recording {
+            recordings.push_str(&recording.title);
+            recordings.push(' ');
+            isrcs.extend(recording.isrcs.iter().flatten().map(|it| it.to_lowercase()));
+        }
+    }
+    let track_count: u32 = media.iter().map(|it| it.track_count).sum();
+
+    vec![
+        (
+            "barcode",
+            release.barcode.iter().map(|it| compact(it)).collect(),
+        ),
+        (
+            "catno",
+            tokenize(
+                &labels
+                    .iter()
+                    .filter_map(|it| it.catalog_number.as_deref())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+        ),
+        (
+            "label",
+            tokenize(
+                &labels
+                    .iter()
+                    .filter_map(|it| it.label.as_ref().map(|it| it.name.as_str()))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+        ),
+        ("artistname", tokenize(&artists)),
+        ("release", tokenize(&release.title)),
+        (
+            "format",
+            tokenize(
+                &media
+                    .iter()
+                    .filter_map(|it| it.format.as_deref())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+        ),
+        ("tracks", vec![track_count.to_string()]),
+        (
+            "rgid",
+            release
+                .release_group
+                .iter()
+                .map(|it| it.id.to_lowercase())
+                .collect(),
+        ),
+        ("recording", tokenize(&recordings)),
+        ("isrc", isrcs),
+    ]
+}
+
+/// How well a release fits the text clauses, from 0 upwards.  Like
+/// Lucene's length norm, a title or artist made up of only the query's
+/// words scores higher than one that has others besides.
+fn release_score(record: Record, clauses: &[Clause]) -> f64 {
+    clauses
+        .iter()
+        .filter_map(|clause| {
+            let ClauseValue::Terms(words) = &clause.value else {
+                return None;
+            };
+            let length = match clause.field.as_str() {
+                "release" => record.title_words,
+                "artistname" => record.artist_words,
+                _ => return None,
+            };
+            let matched = words.len().max(1) as f64;
+            Some(matched / matched.max(length as f64))
+        })
+        .sum()
+}
+
+/// Whether a track satisfies the clauses a recording search narrows by.
+fn track_matches(track: &Track, release: &Release, clauses: &[Clause]) -> bool {
+    let recording = track.recording.as_ref();
+    let length = track.length.or(recording.and_then(|it| it.length));
+    let credit = |credit: Option<&[ArtistCredit]>| tokenize(&credit_text(credit));
+
+    clauses
+        .iter()
+        .all(|clause| match (clause.field.as_str(), &clause.value) {
+            ("recording", ClauseValue::Terms(words)) => {
+                let mut titles = tokenize(&track.title);
+                titles.extend(recording.map(|it| tokenize(&it.title)).unwrap_or_default());
+                words.iter().all(|it| titles.contains(it))
+            }
+            ("artistname", ClauseValue::Terms(words)) => {
+                let mut artists = credit(track.artist_credit.as_deref());
+                artists.extend(credit(release.artist_credit.as_deref()));
+                words.iter().all(|it| artists.contains(it))
+            }
+            ("isrc", ClauseValue::Terms(words)) => recording
+                .and_then(|it| it.isrcs.as_ref())
+                .is_some_and(|isrcs| {
+                    words
+                        .iter()
+                        .all(|word| isrcs.iter().any(|it| it.eq_ignore_ascii_case(word)))
+                }),
+            ("dur", ClauseValue::Range(lo, hi)) => {
+                length.is_some_and(|it| (*lo..=*hi).contains(&(it as u64)))
+            }
+            _ => true,
+        })
+}
+
+fn page<T>(mut entities: Vec<T>, offset: usize, limit: usize) -> SearchPage<T> {
+    let total = entities.len();
+    let entities = if offset < total {
+        entities.drain(offset..total.min(offset + limit)).collect()
+    } else {
+        Vec::new()
+    };
+    SearchPage {
+        entities,
+        offset,
+        total,
+    }
+}
+
+/// Parse the subset of Lucene that our query builder produces: clauses
+/// joined by `AND`, each a term, a quoted phrase, a group of fuzzy words or
+/// a range.
+fn parse_query(query: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut field = String::new();
+        while let Some(c) = chars.next_if(|c| *c != ':' && !c.is_whitespace()) {
+            field.push(c);
+        }
+        if field.is_empty() {
+            break;
+        }
+        if chars.next_if_eq(&':').is_none() {
+            // A bare word, such as the `AND` between clauses.
+            continue;
+        }
+
+        let (open, close) = match chars.peek() {
+            Some('"') => ('"', '"'),
+            Some('(') => ('(', ')'),
+            Some('[') => ('[', ']'),
+            _ => (' ', ' '),
+        };
+        if open != ' ' {
+            chars.next();
+        }
+        let mut value = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => value.extend(chars.next()),
+                c if c == close || (close == ' ' && c.is_whitespace()) => break,
+                c => value.push(c),
+            }
+        }
+
+        let value = match open {
+            '[' => {
+                let mut bounds = value.split(" TO ").map(|it| it.trim().parse().ok());
+                match (bounds.next().flatten(), bounds.next().flatten()) {
+                    (Some(lo), Some(hi)) => ClauseValue::Range(lo, hi),
+                    _ => continue,
+                }
+            }
+            '(' if value.contains('~') => ClauseValue::Fuzzy(tokenize(&value.replace('~', " "))),
+            _ if field == "barcode" => ClauseValue::Terms(vec![compact(&value)]),
+            _ if field == "isrc" || field == "rgid" => {
+                ClauseValue::Terms(vec![value.trim().to_lowercase()])
+            }
+            _ => ClauseValue::Terms(tokenize(&value)),
+        };
+        clauses.push(Clause {
+            field: field.to_lowercase(),
+            value,
+        });
+    }
+
+    clauses
+}
+
+/// Lowercase words, with apostrophes dropped so "What's" matches "Whats".
+fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .replace(['\'', '’'], "")
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|it| !it.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Letters and digits only, for codes like barcodes.
+fn compact(text: &str) -> String {
+    text.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+fn credit_text(credit: Option<&[ArtistCredit]>) -> String {
+    credit
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|it| [it.name.as_str(), it.artist.name.as_str()])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::{
+        codecs::AudioCodec,
+        models::{AlbumCluster, AudioFile, MusicBrainzIds, ReleaseIdentifiers, ReplayGain},
+        musicbrainz::query,
+        search::SearchStrategy,
+    };
+
+    const FIXTURE_SONGS: &str = "b84ee12a-09ef-421b-82de-0441a926375b";
+    const DELUXE: &str = "c3a1f0de-77b2-4f4e-a0c4-5d6e7f801234";
+    const OPENING_TEST: &str = "9f8e7d6c-5b4a-4392-8170-000000000001";
+
+    /// Import the fixture dump into a fresh store.
+    fn store(name: &str) -> (DumpStore, ImportSummary) {
+        let dir = env::temp_dir().join(format!("tuners-dump-{}-{}", std::process::id(), name));
+        let dump =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/musicbrainz/dump.jsonl");
+        let summary = import(&dump, &dir, |_| {}).unwrap();
+        let store = DumpStore::open(&dir).unwrap();
+        // The open store keeps its own handles on the files.
+        let _ = fs::remove_dir_all(&dir);
+        (store, summary)
+    }
+
+    fn track(title: &str, secs: u64) -> AudioFile {
+        AudioFile {
+            path: PathBuf::from(format!("/music/{}.flac", title)),
+            codec: AudioCodec::Flac,
+            title: Some(String::from(title)),
+            artist: None,
+            album_artist: None,
+            album: None,
+            track_number: None,
+            total_tracks: None,
+            disc_number: None,
+            total_discs: None,
+            genre: None,
+            year: None,
+            duration: Some(Duration::from_secs(secs)),
+            duration_source: None,
+            legacy_encoding: None,
+            replay_gain: ReplayGain::default(),
+            identifiers: ReleaseIdentifiers::default(),
+            isrc: None,
+            musicbrainz: MusicBrainzIds::default(),
+            audio_digest: None,
+        }
+    }
+
+    fn cluster(artist: &str, album: &str) -> AlbumCluster {
+        let mut opening = track("Opening Test", 201);
+        opening.isrc = Some(String::from("GB-AAA-19-00001"));
+        AlbumCluster {
+            album: String::from(album),
+            album_artist: String::from(artist),
+            tracks: vec![
+                opening,
+                track("Assertion Blues", 254),
+                track("Teardown", 187),
+            ],
+            base_path: PathBuf::from("/music"),
+            total_discs: 1,
+            encoding_suggestion: None,
+            identifiers: ReleaseIdentifiers {
+                barcode: Some(String::from("5 012345 678900")),
+                catalog_number: Some(String::from("TEST001")),
+                label: Some(String::from("Fixture Records")),
+                media: Some(String::from("CD")),
+            },
+        }
+    }
+
+    fn release_ids(
+        store: &DumpStore,
+        strategy: SearchStrategy,
+        cluster: &AlbumCluster,
+    ) -> Vec<String> {
+        let query = query::build(strategy, cluster).unwrap();
+        let page = store.search_releases(&query, 0, 25).unwrap();
+        page.entities.into_iter().map(|it| it.id).collect()
+    }
+
+    fn recording_hits(
+        store: &DumpStore,
+        strategy: SearchStrategy,
+        cluster: &AlbumCluster,
+    ) -> Vec<(String, String)> {
+        let query = query::build(strategy, cluster).unwrap();
+        let page = store.search_recordings(&query, 0, 25).unwrap();
+        page.entities
+            .into_iter()
+            .map(|it| (it.id, it.releases.unwrap()[0].id.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn unreadable_lines_are_skipped() {
+        let (_, summary) = store("summary");
+        assert_eq!(summary.releases, 3);
+        assert_eq!(summary.skipped, 1);
+    }
+
+    #[test]
+    fn every_release_strategy_finds_the_release() {
+        let (store, _) = store("releases");
+        let cluster = cluster("The Testers", "Fixture Songs");
+
+        let exact = [FIXTURE_SONGS];
+        let both = [FIXTURE_SONGS, DELUXE];
+        assert_eq!(
+            release_ids(&store, SearchStrategy::Barcode, &cluster),
+            exact
+        );
+        assert_eq!(
+            release_ids(&store, SearchStrategy::CatalogNumber, &cluster),
+            exact
+        );
+        assert_eq!(release_ids(&store, SearchStrategy::Exact, &cluster), exact);
+        assert_eq!(
+            release_ids(&store, SearchStrategy::Normalized, &cluster),
+            both
+        );
+        assert_eq!(
+            release_ids(&store, SearchStrategy::AlbumOnly, &cluster),
+            both
+        );
+        assert_eq!(
+            release_ids(&store, SearchStrategy::ArtistFuzzy, &cluster),
+            both
+        );
+    }
+
+    #[test]
+    fn every_recording_strategy_finds_the_recording() {
+        let (store, _) = store("recordings");
+        let cluster = cluster("The Testers", "Fixture Songs");
+
+        let on_both = [
+            (String::from(OPENING_TEST), String::from(FIXTURE_SONGS)),
+            (
+                String::from("9f8e7d6c-5b4a-4392-8170-100000000001"),
+                String::from(DELUXE),
+            ),
+        ];
+        assert_eq!(
+            recording_hits(&store, SearchStrategy::Isrc, &cluster),
+            on_both[..1]
+        );
+        assert_eq!(
+            recording_hits(&store, SearchStrategy::Recording, &cluster),
+            on_both
+        );
+        assert_eq!(
+            recording_hits(&store, SearchStrategy::RecordingNormalized, &cluster),
+            on_both
+        );
+    }
+
+    #[test]
+    fn recordings_outside_the_duration_window_are_left_out() {
+        let (store, _) = store("duration");
+        let mut cluster = cluster("The Testers", "Fixture Songs");
+        cluster.tracks[0].duration = Some(Duration::from_secs(300));
+
+        assert!(recording_hits(&store, SearchStrategy::Recording, &cluster).is_empty());
+    }
+
+    #[test]
+    fn fuzzy_words_match_if_any_of_them_does() {
+        let (store, _) = store("fuzzy");
+        // "Teh" is two edits from "the", more than a short word allows.
+        let cluster = cluster("Teh Testerz", "Fixture Songs");
+
+        assert_eq!(
+            release_ids(&store, SearchStrategy::ArtistFuzzy, &cluster),
+            [FIXTURE_SONGS, DELUXE]
+        );
+    }
+
+    #[test]
+    fn closer_titles_rank_first_and_pages_are_read_separately() {
+        let (store, _) = store("paging");
+        let query = "release:\"fixture songs\"";
+
+        let first = store.search_releases(query, 0, 1).unwrap();
+        let second = store.search_releases(query, 1, 1).unwrap();
+
+        assert_eq!(first.total, 2);
+        assert_eq!(first.entities[0].id, FIXTURE_SONGS);
+        assert_eq!(first.next_offset(), Some(1));
+        assert_eq!(second.entities[0].id, DELUXE);
+        assert_eq!(second.next_offset(), None);
+    }
+
+    #[test]
+    fn releases_are_looked_up_by_id_and_group() {
+        let (store, _) = store("lookup");
+
+        let release = store.lookup_release(&FIXTURE_SONGS.to_uppercase()).unwrap();
+        let group = store
+            .browse_release_group("f8a2d4c6-3b1e-4e53-9c1e-6f0d5a2b7c90")
+            .unwrap();
+
+        assert_eq!(release.title, "Fixture Songs");
+        assert_eq!(
+            group.iter().map(|it| it.id.as_str()).collect::<Vec<_>>(),
+            [FIXTURE_SONGS, DELUXE]
+        );
+        assert!(
+            store
+                .lookup_release("00000000-0000-0000-0000-000000000000")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn runs_merge_into_ascending_postings() {
+        let dir = env::temp_dir().join(format!("tuners-dump-{}-runs", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let key = |token: &str| term_key("release", token);
+        let runs = [
+            write_run(
+                &dir,
+                0,
+                &mut vec![(key("songs"), 1), (key("fixture"), 0), (key("songs"), 0)],
+            )
+            .unwrap(),
+            write_run(
+                &dir,
+                1,
+                &mut vec![(key("songs"), 2), (key("songs"), 2), (key("other"), 3)],
+            )
+            .unwrap(),
+        ];
+        let (terms, postings) = (dir.join(TERMS_FILE), dir.join(POSTINGS_FILE));
+        merge_runs(&runs, &terms, &postings).unwrap();
+        let terms = Map::new(fs::read(&terms).unwrap()).unwrap();
+        let postings = fs::read(&postings).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        let ids = |token: &str| {
+            let at = terms.get(key(token)).unwrap() as usize;
+            let count = read_u32(&postings[at..at + 4]) as usize;
+            postings[at + 4..at + 4 + count * 4]
+                .chunks_exact(4)
+                .map(read_u32)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(terms.len(), 3);
+        assert_eq!(ids("fixture"), [0]);
+        assert_eq!(ids("songs"), [0, 1, 2]);
+        assert_eq!(ids("other"), [3]);
+    }
+
+    #[test]
+    fn escaped_phrases_and_ranges_are_parsed() {
+        let cluster = cluster("Guns N' Roses", "AC/DC: \"Live\" (Part 1)");
+        let terms = |field: &str, words: &[&str]| Clause {
+            field: String::from(field),
+            value: ClauseValue::Terms(words.iter().map(|it| String::from(*it)).collect()),
+        };
+
+        let exact = query::build(SearchStrategy::Exact, &cluster).unwrap();
+        assert_eq!(
+            parse_query(&exact),
+            [
+                terms("artistname", &["guns", "n", "roses"]),
+                terms("release", &["ac", "dc", "live", "part", "1"]),
+                terms("tracks", &["3"]),
+                terms("format", &["cd"]),
+            ]
+        );
+
+        let recording = query::build(SearchStrategy::Recording, &cluster).unwrap();
+        assert_eq!(
+            parse_query(&recording),
+            [
+                terms("artistname", &["guns", "n", "roses"]),
+                terms("recording", &["opening", "test"]),
+                Clause {
+                    field: String::from("dur"),
+                    value: ClauseValue::Range(196_000, 206_000),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,401 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! A stand-in web service for tests, answering from fixtures.
+//!
+//! A fixture is a JSON file in `tests/fixtures/<service>` listing routes.
+//! Each route matches a path, and optionally text the decoded query string
+//! must contain, and gives the responses to send in turn; the last one is
+//! repeated for any further requests.  Routes are tried in order, and
+//! requests no route matches get a 404.
+//!
+//! ```json
+//! { "routes": [
+//!     { "path": "/ws/2/release", "query": "barcode:602498",
+//!       "responses": [
+//!         { "status": 503, "headers": { "Retry-After": "0" } },
+//!         { "body": { "count": 0, "offset": 0, "releases": [] } } ] } ] }
+//! ```
+//!
+//! `{server}` anywhere in a body is replaced with the root URL of the
+//! server, so that URLs handed out by one response can point back at it.
+//!
+//! Fixtures can also be recorded: a recording server passes every request
+//! on to a real server and writes what came back to a fixture file.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+use crate::musicbrainz::client::ClientConfig;
+
+/// Requests are small, so anything larger is a broken client.
+const MAX_REQUEST_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fixture {
+    pub routes: Vec<Route>,
+}
+
+impl Fixture {
+    /// Load `tests/fixtures/musicbrainz/<name>.json`.
+    pub fn load(name: &str) -> Self {
+        Self::load_for("musicbrainz", name)
+    }
+
+    /// Load `tests/fixtures/<service>/<name>.json`, for services other than
+    /// MusicBrainz.
+    pub fn load_for(service: &str, name: &str) -> Self {
+        Self::read(&fixture_path(service, name))
+    }
+
+    pub fn read(path: &Path) -> Self {
+        let text = fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("failed to read fixture {}: {}", path.display(), e));
+        serde_json::from_str(&text)
+            .unwrap_or_else(|e| panic!("invalid fixture {}: {}", path.display(), e))
+    }
+
+    fn write(&self, path: &Path) {
+        let json = serde_json::to_string_pretty(self).expect("fixtures serialize");
+        fs::write(path, json + "\n")
+            .unwrap_or_else(|e| panic!("failed to write fixture {}: {}", path.display(), e));
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Route {
+    /// Path the request must have, e.g. `/ws/2/release` or
+    /// `/ws/2/release/<mbid>`.
+    pub path: String,
+    /// Text the decoded query string must contain.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    pub responses: Vec<Response>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    #[serde(default = "ok")]
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: serde_json::Value,
+}
+
+fn ok() -> u16 {
+    200
+}
+
+impl Response {
+    fn not_found() -> Self {
+        Self {
+            status: 404,
+            headers: BTreeMap::new(),
+            body: serde_json::json!({ "error": "Not Found" }),
+        }
+    }
+}
+
+/// A server on a local port, answering from a fixture.  It stops when
+/// dropped.
+pub struct FakeServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct State {
+    fixture: Fixture,
+    /// Responses sent so far for each route.
+    served: Vec<usize>,
+    /// Path and decoded query string of every request, in order.
+    requests: Vec<String>,
+    recording: Option<Recording>,
+}
+
+#[derive(Clone)]
+struct Recording {
+    /// Where requests are passed on to, e.g. `http://musicbrainz.org`.
+    upstream: String,
+    path: PathBuf,
+}
+
+impl FakeServer {
+    /// Serve a fixture.
+    pub async fn start(fixture: Fixture) -> Self {
+        Self::spawn(State {
+            fixture,
+            ..State::default()
+        })
+        .await
+    }
+
+    /// Pass requests on to `upstream` and write the responses to a fixture
+    /// at `path`, which can then be served by [`FakeServer::start`].
+    pub async fn record(upstream: &str, path: &Path) -> Self {
+        Self::spawn(State {
+            recording: Some(Recording {
+                upstream: upstream.trim_end_matches('/').to_string(),
+                path: path.to_path_buf(),
+            }),
+            ..State::default()
+        })
+        .await
+    }
+
+    async fn spawn(state: State) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind fake server");
+        let addr = listener.local_addr().expect("fake server address");
+        let state = Arc::new(Mutex::new(state));
+        let task = tokio::spawn({
+            let state = Arc::clone(&state);
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(handle(stream, Arc::clone(&state)));
+                }
+            }
+        });
+        Self { addr, state, task }
+    }
+
+    /// `host:port` of the server, as taken by [`ClientConfig::set_server`].
+    pub fn host(&self) -> String {
+        self.addr.to_string()
+    }
+
+    /// Root URL of the server, for clients configured with one.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// A MusicBrainz client configuration for this server, without a cache.
+    pub fn config(&self) -> ClientConfig {
+        let mut config = ClientConfig::default();
+        config.set_server(&self.host());
+        config.cache = None;
+        config
+    }
+
+    /// Path and decoded query string of every request so far.
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for FakeServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle(mut stream: TcpStream, state: Arc<Mutex<State>>) {
+    let Some(target) = read_request(&mut stream).await else {
+        return;
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), decode(query)),
+        None => (target.clone(), String::new()),
+    };
+
+    let recording = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(format!("{}?{}", path, query));
+        state.recording.clone()
+    };
+    let response = match recording {
+        Some(recording) => {
+            let response = forward(&recording.upstream, &target).await;
+            let mut state = state.lock().unwrap();
+            state.fixture.routes.push(Route {
+                path,
+                query: Some(query).filter(|it| !it.is_empty()),
+                responses: vec![response.clone()],
+            });
+            state.fixture.write(&recording.path);
+            response
+        }
+        None => state.lock().unwrap().respond(&path, &query),
+    };
+
+    let mut body = serde_json::to_string(&response.body).expect("JSON serializes");
+    if let Ok(addr) = stream.local_addr() {
+        body = body.replace("{server}", &format!("http://{}", addr));
+    }
+    let reason = reqwest::StatusCode::from_u16(response.status)
+        .ok()
+        .and_then(|it| it.canonical_reason())
+        .unwrap_or("");
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason,
+        body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(body.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+impl State {
+    fn respond(&mut self, path: &str, query: &str) -> Response {
+        self.served.resize(self.fixture.routes.len(), 0);
+        let found = self.fixture.routes.iter().position(|route| {
+            route.path == path && route.query.as_deref().is_none_or(|it| query.contains(it))
+        });
+        let Some(idx) = found else {
+            return Response::not_found();
+        };
+        let responses = &self.fixture.routes[idx].responses;
+        let served = self.served[idx];
+        self.served[idx] += 1;
+        responses
+            .get(served)
+            .or(responses.last())
+            .cloned()
+            .unwrap_or_else(Response::not_found)
+    }
+}
+
+/// Read a request up to the end of its headers, returning its target.
+async fn read_request(stream: &mut TcpStream) -> Option<String> {
+    let mut request = Vec::new();
+    let mut buf = [0; 4096];
+    while !request.windows(4).any(|it| it == b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await.ok()?;
+        if read == 0 || request.len() > MAX_REQUEST_BYTES {
+            return None;
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    // e.g. "GET /ws/2/release?query=... HTTP/1.1"
+    request.lines().next()?.split(' ').nth(1).map(String::from)
+}
+
+async fn forward(upstream: &str, target: &str) -> Response {
+    let response = reqwest::Client::new()
+        .get(format!("{}{}", upstream, target))
+        .header(
+            reqwest::header::USER_AGENT,
+            crate::source::http::DEFAULT_USER_AGENT,
+        )
+        .send()
+        .await
+        .expect("upstream request");
+    let status = response.status().as_u16();
+    // Only the headers the client looks at are worth keeping.
+    let headers = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|it| it.to_str().ok())
+        .map(|it| BTreeMap::from([(String::from("Retry-After"), it.to_string())]))
+        .unwrap_or_default();
+    let body = response.json().await.unwrap_or(serde_json::Value::Null);
+    Response {
+        status,
+        headers,
+        body,
+    }
+}
+
+/// Undo percent-encoding, with `+` as a space.
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let escaped = bytes
+            .get(idx + 1..idx + 3)
+            .and_then(|it| std::str::from_utf8(it).ok())
+            .and_then(|it| u8::from_str_radix(it, 16).ok());
+        match (bytes[idx], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                idx += 3;
+                continue;
+            }
+            (b'+', _) => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        idx += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+pub fn fixture_path(service: &str, name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(service)
+        .join(format!("{}.json", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use musicbrainz_rs::entity::release::Release;
+
+    use crate::{musicbrainz::client::Client, source::http::SearchPage};
+
+    #[tokio::test]
+    async fn recorded_fixture_replays_the_same_responses() {
+        let upstream = FakeServer::start(Fixture::load("release_search")).await;
+        let path = std::env::temp_dir().join(format!("tuners-fixture-{}.json", std::process::id()));
+        let recorder = FakeServer::record(&upstream.url(), &path).await;
+        let recorded = Client::new(&recorder.config())
+            .unwrap()
+            .search_releases("barcode:5012345678900", 0, &|_| {})
+            .await
+            .unwrap();
+
+        let replay = FakeServer::start(Fixture::read(&path)).await;
+        let replayed = Client::new(&replay.config())
+            .unwrap()
+            .search_releases("barcode:5012345678900", 0, &|_| {})
+            .await
+            .unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(recorder.requests(), upstream.requests());
+        assert_eq!(replay.requests(), upstream.requests());
+        assert_eq!(replayed.total, recorded.total);
+        let ids = |page: &SearchPage<Release>| {
+            page.entities
+                .iter()
+                .map(|it| it.id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&replayed), ids(&recorded));
+    }
+
+    #[test]
+    fn query_strings_are_decoded() {
+        assert_eq!(
+            decode("query=barcode%3A602498+AND%20x&limit=25"),
+            "query=barcode:602498 AND x&limit=25"
+        );
+        assert_eq!(decode("100%"), "100%");
+    }
+}
@@ -0,0 +1,137 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Token-bucket rate limiting shared by every task talking to a server.
+//!
+//! Clones of a [`RateLimiter`] draw from the same bucket, so any number of
+//! concurrent searches and lookups together stay within the limit.  When
+//! the server asks one of them to back off, they all do.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    sync::Mutex,
+    time::{sleep, sleep_until},
+};
+
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+    /// No request may be made before this, whatever the bucket holds.  Kept
+    /// apart from the bucket so that pausing needn't wait for its lock.
+    paused_until: Arc<std::sync::Mutex<Option<Instant>>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Time it takes for one token to be added back.
+    interval: Duration,
+    capacity: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    /// Allow one request per `interval`, with up to `burst` requests in
+    /// quick succession after a quiet period.  A zero interval disables
+    /// the limit.
+    pub fn new(interval: Duration, burst: u32) -> Self {
+        let capacity = burst.max(1) as f64;
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                interval,
+                capacity,
+                tokens: capacity,
+                refilled_at: Instant::now(),
+            })),
+            paused_until: Arc::default(),
+        }
+    }
+
+    /// Hold back every request for `delay`, e.g. because the server said
+    /// to retry after that long.  A longer pause already under way is kept.
+    pub fn pause(&self, delay: Duration) {
+        let until = Instant::now() + delay;
+        let mut paused_until = self.paused_until.lock().unwrap();
+        if paused_until.is_none_or(|it| it < until) {
+            *paused_until = Some(until);
+        }
+    }
+
+    /// Wait until a request may be made.
+    ///
+    /// Waiters are served in the order they arrived, since the bucket's
+    /// lock is fair and is held while waiting for a token.
+    pub async fn acquire(&self) {
+        let mut bucket = self.bucket.lock().await;
+        // The pause may be extended while waiting for it to end.
+        loop {
+            let paused_until = *self.paused_until.lock().unwrap();
+            match paused_until {
+                Some(until) if until > Instant::now() => sleep_until(until.into()).await,
+                _ => break,
+            }
+        }
+        if bucket.interval.is_zero() {
+            return;
+        }
+
+        bucket.refill();
+        if bucket.tokens < 1.0 {
+            let wait = bucket.interval.mul_f64(1.0 - bucket.tokens);
+            sleep(wait).await;
+            bucket.refill();
+        }
+        bucket.tokens = (bucket.tokens - 1.0).max(0.0);
+    }
+}
+
+impl Bucket {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at);
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() / self.interval.as_secs_f64()).min(self.capacity);
+        self.refilled_at = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(50);
+
+    #[tokio::test]
+    async fn requests_are_spaced_by_the_interval() {
+        let limiter = RateLimiter::new(INTERVAL, 1);
+        let start = Instant::now();
+
+        limiter.acquire().await;
+        limiter.clone().acquire().await;
+        limiter.acquire().await;
+
+        assert!(start.elapsed() >= INTERVAL * 2);
+    }
+
+    #[tokio::test]
+    async fn pause_holds_back_every_clone() {
+        let limiter = RateLimiter::new(Duration::ZERO, 1);
+        let other = limiter.clone();
+        let start = Instant::now();
+
+        limiter.pause(INTERVAL * 4);
+        // A shorter pause doesn't cut the longer one short.
+        limiter.pause(INTERVAL);
+        other.acquire().await;
+        assert!(start.elapsed() >= INTERVAL * 4);
+
+        let resumed = Instant::now();
+        other.acquire().await;
+        assert!(resumed.elapsed() < INTERVAL);
+    }
+}
@@ -2,5 +2,14 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
+pub mod cache;
 pub mod client;
-pub mod search;
+pub mod coverart;
+pub mod dump;
+#[cfg(test)]
+pub mod fake;
+pub mod limiter;
+pub mod query;
+pub mod reference;
+pub mod source;
+pub mod sync;
@@ -0,0 +1,266 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Lucene queries for the MusicBrainz release and recording searches.
+//!
+//! Each [`SearchStrategy`] becomes a progressively looser query, so that a
+//! typo, a "feat." credit or an edition suffix in the tags doesn't prevent a
+//! match.
+
+use crate::{
+    models::AlbumCluster,
+    search::SearchStrategy,
+    source::normalize::{DURATION_WINDOW, clean_whitespace, normalize_artist, normalize_title},
+};
+
+/// Characters with a special meaning in Lucene queries.
+const LUCENE_SPECIAL: &str = "+-&|!(){}[]^\"~*?:\\/";
+
+/// Build the query for a search strategy, or `None` when the cluster lacks
+/// what the strategy needs.
+pub fn build(strategy: SearchStrategy, cluster: &AlbumCluster) -> Option<String> {
+    let identifiers = &cluster.identifiers;
+    let artist = clean_whitespace(&cluster.album_artist);
+    let album = clean_whitespace(&cluster.album);
+    // A single track is credited to its own artist, which may differ from
+    // the album artist.
+    let track_artist = cluster
+        .tracks
+        .first()
+        .and_then(|it| it.artist.as_deref())
+        .map(clean_whitespace)
+        .unwrap_or_else(|| artist.clone());
+
+    let query = match strategy {
+        SearchStrategy::Barcode => {
+            // Barcodes are often written with spaces or dashes between groups.
+            let barcode: String = identifiers
+                .barcode
+                .as_ref()?
+                .chars()
+                .filter(char::is_ascii_alphanumeric)
+                .collect();
+            term("barcode", &barcode)
+        }
+        SearchStrategy::CatalogNumber => {
+            let mut query = phrase("catno", identifiers.catalog_number.as_ref()?);
+            if let Some(label) = &identifiers.label {
+                query = format!("{} AND {}", query, phrase("label", label));
+            }
+            query
+        }
+        SearchStrategy::Exact => {
+            let mut query = format!(
+                "{} AND {} AND {}",
+                phrase("artistname", &artist),
+                phrase("release", &album),
+                term("tracks", &cluster.track_count().to_string())
+            );
+            if let Some(media) = &identifiers.media {
+                query = format!("{} AND {}", query, phrase("format", media));
+            }
+            query
+        }
+        SearchStrategy::Normalized => format!(
+            "{} AND {}",
+            phrase("artistname", &normalize_artist(&artist)),
+            phrase("release", &normalize_title(&album))
+        ),
+        SearchStrategy::AlbumOnly => phrase("release", &normalize_title(&album)),
+        SearchStrategy::ArtistFuzzy => fuzzy("artistname", &normalize_artist(&artist))?,
+        SearchStrategy::Isrc => {
+            let isrc: String = cluster
+                .tracks
+                .first()?
+                .isrc
+                .as_ref()?
+                .chars()
+                .filter(char::is_ascii_alphanumeric)
+                .collect();
+            term("isrc", &isrc)
+        }
+        SearchStrategy::Recording => {
+            let track = cluster.tracks.first()?;
+            let title = clean_whitespace(track.title.as_ref()?);
+            let mut query = format!(
+                "{} AND {}",
+                phrase("artistname", &track_artist),
+                phrase("recording", &title)
+            );
+            if let Some(duration) = track.duration {
+                let millis = duration.as_millis();
+                let window = DURATION_WINDOW.as_millis();
+                query = format!(
+                    "{} AND dur:[{} TO {}]",
+                    query,
+                    millis.saturating_sub(window),
+                    millis + window
+                );
+            }
+            query
+        }
+        SearchStrategy::RecordingNormalized => {
+            let title = cluster.tracks.first()?.title.as_ref()?;
+            format!(
+                "{} AND {}",
+                phrase("artistname", &normalize_artist(&track_artist)),
+                phrase("recording", &normalize_title(title))
+            )
+        }
+        SearchStrategy::Manual => return None,
+    };
+
+    Some(query)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if LUCENE_SPECIAL.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn term(field: &str, value: &str) -> String {
+    format!("{}:{}", field, escape(value))
+}
+
+fn phrase(field: &str, value: &str) -> String {
+    format!("{}:\"{}\"", field, escape(value))
+}
+
+/// Match each word approximately, to get past typos.
+fn fuzzy(field: &str, value: &str) -> Option<String> {
+    let words: Vec<String> = value
+        .split_whitespace()
+        .map(|it| format!("{}~", escape(it)))
+        .collect();
+    (!words.is_empty()).then(|| format!("{}:({})", field, words.join(" ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use crate::{
+        codecs::AudioCodec,
+        models::{AudioFile, MusicBrainzIds, ReleaseIdentifiers, ReplayGain},
+    };
+
+    fn file(number: u32) -> AudioFile {
+        AudioFile {
+            path: PathBuf::from(format!("/music/{}.flac", number)),
+            codec: AudioCodec::Flac,
+            title: Some(format!("Track {}", number)),
+            artist: None,
+            album_artist: None,
+            album: None,
+            track_number: Some(number),
+            total_tracks: None,
+            disc_number: None,
+            total_discs: None,
+            genre: None,
+            year: None,
+            duration: None,
+            duration_source: None,
+            legacy_encoding: None,
+            replay_gain: ReplayGain::default(),
+            identifiers: ReleaseIdentifiers::default(),
+            isrc: None,
+            musicbrainz: MusicBrainzIds::default(),
+            audio_digest: None,
+        }
+    }
+
+    fn cluster(artist: &str, album: &str, tracks: u32) -> AlbumCluster {
+        AlbumCluster {
+            album: String::from(album),
+            album_artist: String::from(artist),
+            tracks: (1..=tracks).map(file).collect(),
+            base_path: PathBuf::from("/music"),
+            total_discs: 1,
+            encoding_suggestion: None,
+            identifiers: ReleaseIdentifiers::default(),
+        }
+    }
+
+    #[test]
+    fn exact_query_has_the_track_count_and_format() {
+        let mut cluster = cluster("The Testers", "Fixture Songs", 12);
+        assert_eq!(
+            build(SearchStrategy::Exact, &cluster).unwrap(),
+            "artistname:\"The Testers\" AND release:\"Fixture Songs\" AND tracks:12"
+        );
+
+        cluster.identifiers.media = Some(String::from("12\" Vinyl"));
+        assert_eq!(
+            build(SearchStrategy::Exact, &cluster).unwrap(),
+            "artistname:\"The Testers\" AND release:\"Fixture Songs\" AND tracks:12 \
+             AND format:\"12\\\" Vinyl\""
+        );
+    }
+
+    #[test]
+    fn special_characters_are_escaped() {
+        let cluster = cluster("AC/DC", "Who Made Who? (Live!) [1986] & More: +1 ~ \\o/", 9);
+        assert_eq!(
+            build(SearchStrategy::Exact, &cluster).unwrap(),
+            "artistname:\"AC\\/DC\" AND release:\"Who Made Who\\? \\(Live\\!\\) \\[1986\\] \\& More\\: \
+             \\+1 \\~ \\\\o\\/\" AND tracks:9"
+        );
+    }
+
+    #[test]
+    fn looser_queries_normalize_names() {
+        let cluster = cluster(
+            "The Testers feat. Guest",
+            "Fixture Songs (Deluxe Edition)",
+            3,
+        );
+        assert_eq!(
+            build(SearchStrategy::Normalized, &cluster).unwrap(),
+            "artistname:\"The Testers\" AND release:\"Fixture Songs\""
+        );
+        assert_eq!(
+            build(SearchStrategy::AlbumOnly, &cluster).unwrap(),
+            "release:\"Fixture Songs\""
+        );
+    }
+
+    #[test]
+    fn fuzzy_query_matches_each_artist_word_approximately() {
+        let cluster = cluster("Teh  Tseters feat. Guest", "Fixture Songs", 3);
+        assert_eq!(
+            build(SearchStrategy::ArtistFuzzy, &cluster).unwrap(),
+            "artistname:(Teh~ Tseters~)"
+        );
+    }
+
+    #[test]
+    fn strategies_need_their_fields() {
+        let mut cluster = cluster("", "", 1);
+        cluster.tracks[0].title = None;
+        for strategy in [
+            SearchStrategy::Barcode,
+            SearchStrategy::CatalogNumber,
+            SearchStrategy::ArtistFuzzy,
+            SearchStrategy::Isrc,
+            SearchStrategy::Recording,
+            SearchStrategy::RecordingNormalized,
+            SearchStrategy::Manual,
+        ] {
+            assert_eq!(build(strategy, &cluster), None, "{:?}", strategy);
+        }
+
+        cluster.identifiers.barcode = Some(String::from("5 012345 678900"));
+        assert_eq!(
+            build(SearchStrategy::Barcode, &cluster).unwrap(),
+            "barcode:5012345678900"
+        );
+    }
+}
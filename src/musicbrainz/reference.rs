@@ -0,0 +1,133 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Parsing of MBIDs and MusicBrainz URLs entered by the user.
+
+use crate::musicbrainz::client::PUBLIC_HOST;
+
+/// Positions of the hyphens in the textual form of a UUID.
+const UUID_HYPHENS: [usize; 4] = [8, 13, 18, 23];
+const UUID_LEN: usize = 36;
+
+/// A release or release group identified by the user.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MbReference {
+    Release(String),
+    /// Expands to all releases in the group.
+    ReleaseGroup(String),
+}
+
+/// Parse a bare release MBID or a URL such as
+/// `https://musicbrainz.org/release/<mbid>` or
+/// `https://musicbrainz.org/release-group/<mbid>`, on musicbrainz.org or
+/// one of its mirrors like beta.musicbrainz.org.
+pub fn parse_reference(input: &str) -> Option<MbReference> {
+    let input = input.trim();
+    if is_mbid(input) {
+        return Some(MbReference::Release(input.to_lowercase()));
+    }
+
+    let url = input.split_once("://").map_or(input, |(_, rest)| rest);
+    let (host, path) = url.split_once('/')?;
+    let name = host.split(':').next().unwrap_or(host).to_lowercase();
+    if name != PUBLIC_HOST && !name.ends_with(&format!(".{}", PUBLIC_HOST)) {
+        return None;
+    }
+
+    let mut segments = path
+        .split(['/', '?', '#'])
+        .skip_while(|it| *it != "release" && *it != "release-group");
+    let kind = segments.next()?;
+    let mbid = segments.next().filter(|it| is_mbid(it))?.to_lowercase();
+
+    Some(match kind {
+        "release-group" => MbReference::ReleaseGroup(mbid),
+        _ => MbReference::Release(mbid),
+    })
+}
+
+/// Whether the text is an MBID, i.e. a UUID in its hyphenated form.
+pub fn is_mbid(text: &str) -> bool {
+    text.len() == UUID_LEN
+        && text.char_indices().all(|(idx, c)| {
+            if UUID_HYPHENS.contains(&idx) {
+                c == '-'
+            } else {
+                c.is_ascii_hexdigit()
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MBID: &str = "b84ee12a-09ef-421b-82de-0441a926375b";
+
+    fn release(mbid: &str) -> Option<MbReference> {
+        Some(MbReference::Release(mbid.to_string()))
+    }
+
+    #[test]
+    fn bare_mbids_are_releases() {
+        assert_eq!(parse_reference(MBID), release(MBID));
+        assert_eq!(
+            parse_reference(&format!(" {} ", MBID.to_uppercase())),
+            release(MBID)
+        );
+    }
+
+    #[test]
+    fn release_and_release_group_urls_are_parsed() {
+        assert_eq!(
+            parse_reference(&format!("https://musicbrainz.org/release/{}", MBID)),
+            release(MBID)
+        );
+        assert_eq!(
+            parse_reference(&format!(
+                "https://beta.musicbrainz.org/release/{}/discids?tab=1#top",
+                MBID
+            )),
+            release(MBID)
+        );
+        assert_eq!(
+            parse_reference(&format!("musicbrainz.org/release/{}#tracklist", MBID)),
+            release(MBID)
+        );
+        assert_eq!(
+            parse_reference(&format!(
+                "https://MusicBrainz.org/release-group/{}?page=2",
+                MBID.to_uppercase()
+            )),
+            Some(MbReference::ReleaseGroup(MBID.to_string()))
+        );
+    }
+
+    #[test]
+    fn other_sites_are_not_references() {
+        for url in [
+            format!("https://example.com/release/{}", MBID),
+            format!("https://notmusicbrainz.org/release/{}", MBID),
+            format!("https://musicbrainz.org.example.com/release/{}", MBID),
+            format!("https://example.com/?next=musicbrainz.org/release/{}", MBID),
+        ] {
+            assert_eq!(parse_reference(&url), None, "{}", url);
+        }
+    }
+
+    #[test]
+    fn malformed_mbids_are_rejected() {
+        for input in [
+            "b84ee12a-09ef-421b-82de-0441a926375",
+            "b84ee12a-09ef-421b-82de-0441a926375bb",
+            "b84ee12a09ef-421b-82de-0441a926375b-",
+            "g84ee12a-09ef-421b-82de-0441a926375b",
+            "https://musicbrainz.org/release/b84ee12a-09ef-421b-82de",
+            "https://musicbrainz.org/artist/b84ee12a-09ef-421b-82de-0441a926375b",
+        ] {
+            assert_eq!(parse_reference(input), None, "{}", input);
+        }
+        assert!(!is_mbid(""));
+    }
+}
@@ -0,0 +1,246 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! MusicBrainz as a [`MetadataSource`].
+
+use std::time::Duration;
+
+use color_eyre::eyre::bail;
+use musicbrainz_rs::entity::{
+    artist_credit::ArtistCredit,
+    genre::Genre,
+    recording::Recording,
+    release::{Release, Track},
+    tag::Tag,
+};
+
+use crate::{
+    credit::UNKNOWN_ARTIST_NAME,
+    models::AlbumCluster,
+    musicbrainz::{
+        client::Client,
+        query,
+        reference::{MbReference, parse_reference},
+    },
+    search::SearchStrategy,
+    source::{
+        AlbumCandidate, BoxFuture, CandidatePage, GenreTag, LabelCredit, MediumCandidate,
+        MetadataSource, OnRetry, ReleaseGroup, TrackCandidate,
+    },
+};
+
+pub const SOURCE_NAME: &str = "MusicBrainz";
+
+impl MetadataSource for Client {
+    fn name(&self) -> &'static str {
+        SOURCE_NAME
+    }
+
+    fn query(&self, strategy: SearchStrategy, cluster: &AlbumCluster) -> Option<String> {
+        query::build(strategy, cluster)
+    }
+
+    fn search<'a>(
+        &'a self,
+        strategy: SearchStrategy,
+        query: &'a str,
+        offset: usize,
+        on_retry: OnRetry<'a>,
+    ) -> BoxFuture<'a, color_eyre::Result<CandidatePage>> {
+        Box::pin(async move {
+            if strategy.is_recording() {
+                let page = self.search_recordings(query, offset, on_retry).await?;
+                let next_offset = page.next_offset();
+                Ok(CandidatePage {
+                    candidates: recording_candidates(page.entities, strategy),
+                    next_offset,
+                })
+            } else {
+                let page = self.search_releases(query, offset, on_retry).await?;
+                let next_offset = page.next_offset();
+                Ok(CandidatePage {
+                    candidates: page
+                        .entities
+                        .into_iter()
+                        .map(|release| album_candidate(release, None, strategy))
+                        .collect(),
+                    next_offset,
+                })
+            }
+        })
+    }
+
+    fn lookup<'a>(&'a self, id: &'a str) -> BoxFuture<'a, color_eyre::Result<AlbumCandidate>> {
+        Box::pin(async move {
+            let release = self.lookup_release_with_genres(id, &|_| {}).await?;
+            Ok(album_candidate(release, None, SearchStrategy::Manual))
+        })
+    }
+
+    fn lookup_reference<'a>(
+        &'a self,
+        input: &str,
+    ) -> Option<BoxFuture<'a, color_eyre::Result<Vec<AlbumCandidate>>>> {
+        let reference = parse_reference(input)?;
+        Some(Box::pin(async move {
+            let releases = match &reference {
+                MbReference::Release(mbid) => {
+                    vec![self.lookup_release_with_genres(mbid, &|_| {}).await?]
+                }
+                MbReference::ReleaseGroup(mbid) => self.browse_release_group(mbid, &|_| {}).await?,
+            };
+            if releases.is_empty() {
+                bail!("No releases in that release group");
+            }
+            Ok(releases
+                .into_iter()
+                .map(|release| album_candidate(release, None, SearchStrategy::Manual))
+                .collect())
+        }))
+    }
+}
+
+fn album_candidate(
+    mut release: Release,
+    matched_track: Option<TrackCandidate>,
+    strategy: SearchStrategy,
+) -> AlbumCandidate {
+    let mut genres = genre_tags(release.genres.take(), release.tags.take());
+    if let Some(group) = &mut release.release_group {
+        genres.extend(genre_tags(group.genres.take(), group.tags.take()));
+    }
+
+    AlbumCandidate {
+        source: SOURCE_NAME,
+        artist: format_artist_credit(release.artist_credit.as_deref())
+            .unwrap_or_else(|| UNKNOWN_ARTIST_NAME.to_string()),
+        date: release.date.map(|it| it.0).filter(|it| !it.is_empty()),
+        country: release.country,
+        barcode: release.barcode.filter(|it| !it.is_empty()),
+        labels: release
+            .label_info
+            .into_iter()
+            .flatten()
+            .map(|it| LabelCredit {
+                name: it.label.map(|it| it.name),
+                catalog_number: it.catalog_number,
+            })
+            .collect(),
+        group: release.release_group.map(|it| ReleaseGroup {
+            id: it.id,
+            title: it.title,
+            kind: it.primary_type.map(|it| format!("{:?}", it)),
+        }),
+        media: release
+            .media
+            .into_iter()
+            .flatten()
+            .map(|medium| MediumCandidate {
+                position: medium.position,
+                title: medium.title.filter(|it| !it.is_empty()),
+                format: medium.format,
+                track_count: medium.track_count,
+                tracks: medium
+                    .tracks
+                    .iter()
+                    .flatten()
+                    .map(track_candidate)
+                    .collect(),
+            })
+            .collect(),
+        genres,
+        id: release.id,
+        title: release.title,
+        matched_track,
+        strategy,
+    }
+}
+
+/// Genres are the tags MusicBrainz recognises as genres, so they are only
+/// needed when the tags weren't included.  Tags voted down are left out.
+fn genre_tags(genres: Option<Vec<Genre>>, tags: Option<Vec<Tag>>) -> Vec<GenreTag> {
+    match tags {
+        Some(tags) => tags
+            .into_iter()
+            .filter_map(|it| {
+                let count = it.count.unwrap_or(1);
+                (count > 0).then_some(GenreTag {
+                    name: it.name,
+                    count: count as u32,
+                })
+            })
+            .collect(),
+        None => genres
+            .into_iter()
+            .flatten()
+            .map(|it| GenreTag {
+                name: it.name,
+                count: it.count.unwrap_or(1).max(1),
+            })
+            .collect(),
+    }
+}
+
+fn track_candidate(track: &Track) -> TrackCandidate {
+    let recording = track.recording.as_ref();
+    TrackCandidate {
+        id: recording.map_or_else(|| track.id.clone(), |it| it.id.clone()),
+        title: track.title.clone(),
+        artist: format_artist_credit(track.artist_credit.as_deref()),
+        number: track.number.clone(),
+        length: track
+            .length
+            .or(recording.and_then(|it| it.length))
+            .map(|it| Duration::from_millis(it as u64)),
+    }
+}
+
+/// One candidate for each release a recording appears on.  Standalone
+/// recordings have no release to tag with, so they are left out.
+fn recording_candidates(
+    recordings: Vec<Recording>,
+    strategy: SearchStrategy,
+) -> Vec<AlbumCandidate> {
+    let mut candidates = Vec::new();
+    for mut recording in recordings {
+        let releases = recording.releases.take().unwrap_or_default();
+        for release in releases {
+            // Search results list the track the recording appears as.
+            let number = release
+                .media
+                .iter()
+                .flatten()
+                .flat_map(|it| it.tracks.iter().flatten())
+                .next()
+                .map(|it| it.number.clone())
+                .unwrap_or_default();
+            let track = TrackCandidate {
+                id: recording.id.clone(),
+                title: recording.title.clone(),
+                artist: format_artist_credit(recording.artist_credit.as_deref()),
+                number,
+                length: recording.length.map(|it| Duration::from_millis(it as u64)),
+            };
+            candidates.push(album_candidate(release, Some(track), strategy));
+        }
+    }
+    candidates
+}
+
+/// Join an artist credit into a single name, as MusicBrainz displays it.
+pub fn format_artist_credit(credit: Option<&[ArtistCredit]>) -> Option<String> {
+    let credit = credit.filter(|it| !it.is_empty())?;
+    Some(
+        credit
+            .iter()
+            .map(|it| {
+                format!(
+                    "{}{}",
+                    it.name,
+                    it.joinphrase.as_deref().unwrap_or_default()
+                )
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,403 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Refreshing already-tagged albums from MusicBrainz, like beets' mbsync.
+//!
+//! Clusters whose tracks carry a release MBID are looked up again, and the
+//! current release data is compared with the tags field by field.  Nothing
+//! is written here; the differences are only shown.
+
+use std::{path::PathBuf, sync::mpsc::Sender};
+
+use musicbrainz_rs::entity::release::{Media, Release, Track};
+
+use crate::{
+    models::{AlbumCluster, AudioFile},
+    musicbrainz::{client::Client, source::format_artist_credit},
+};
+
+/// A tag that would change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old: Option<String>,
+    pub new: String,
+}
+
+/// What would change for one file.
+#[derive(Debug, Clone)]
+pub struct TrackDiff {
+    pub path: PathBuf,
+    /// The track on the release, or `None` if none of the release's tracks
+    /// match the file any more.
+    pub track: Option<String>,
+    pub changes: Vec<FieldChange>,
+}
+
+/// What would change for a cluster.
+#[derive(Debug, Clone)]
+pub struct ReleaseDiff {
+    /// The release as it reads on MusicBrainz now, e.g. "Artist - Title".
+    pub release: String,
+    pub tracks: Vec<TrackDiff>,
+}
+
+impl ReleaseDiff {
+    pub fn change_count(&self) -> usize {
+        self.tracks.iter().map(|it| it.changes.len()).sum()
+    }
+
+    /// Files that no longer match a track on the release.
+    pub fn unmatched_count(&self) -> usize {
+        self.tracks.iter().filter(|it| it.track.is_none()).count()
+    }
+}
+
+pub enum SyncMessage {
+    /// The differences for the cluster at the given index.
+    Diff(usize, ReleaseDiff),
+    Error(usize, String),
+}
+
+/// Look up the release a cluster was tagged with and work out what would
+/// change.
+pub async fn sync_cluster(
+    client: &Client,
+    tx: Sender<SyncMessage>,
+    idx: usize,
+    cluster: AlbumCluster,
+) {
+    let Some(mbid) = cluster.release_mbid() else {
+        return;
+    };
+    let message = match client.lookup_release(mbid, &|_| {}).await {
+        Ok(release) => SyncMessage::Diff(idx, diff(&cluster, &release)),
+        Err(e) => SyncMessage::Error(idx, format!("Lookup failed: {}", e)),
+    };
+    let _ = tx.send(message);
+}
+
+/// Compare each track's tags with the release.
+pub fn diff(cluster: &AlbumCluster, release: &Release) -> ReleaseDiff {
+    let media = release.media.as_deref().unwrap_or_default();
+    let album_artist = format_artist_credit(release.artist_credit.as_deref());
+    let label = release
+        .label_info
+        .iter()
+        .flatten()
+        .find_map(|it| it.label.as_ref().map(|it| it.name.clone()));
+    let catalog_number = release
+        .label_info
+        .iter()
+        .flatten()
+        .find_map(|it| it.catalog_number.clone());
+
+    let tracks = cluster
+        .tracks
+        .iter()
+        .map(|file| {
+            let Some((medium, track)) = find_track(media, file) else {
+                return TrackDiff {
+                    path: file.path.clone(),
+                    track: None,
+                    changes: Vec::new(),
+                };
+            };
+
+            let mut changes = Vec::new();
+            let mut compare = |field, old: Option<String>, new: Option<String>| {
+                // Fields MusicBrainz doesn't have are left alone.
+                if let Some(new) = new
+                    && old.as_deref().map(str::trim) != Some(new.as_str())
+                {
+                    changes.push(FieldChange { field, old, new });
+                }
+            };
+            compare("Title", file.title.clone(), Some(track.title.clone()));
+            compare(
+                "Artist",
+                file.artist.clone(),
+                format_artist_credit(track.artist_credit.as_deref()).or(album_artist.clone()),
+            );
+            compare("Album", file.album.clone(), Some(release.title.clone()));
+            compare(
+                "Album artist",
+                file.album_artist.clone(),
+                album_artist.clone(),
+            );
+            compare(
+                "Track",
+                file.track_number.map(|it| it.to_string()),
+                Some(track.position.to_string()),
+            );
+            compare(
+                "Total tracks",
+                file.total_tracks.map(|it| it.to_string()),
+                Some(medium.track_count.to_string()),
+            );
+            compare(
+                "Disc",
+                file.disc_number.map(|it| it.to_string()),
+                medium.position.map(|it| it.to_string()),
+            );
+            compare(
+                "Total discs",
+                file.total_discs.map(|it| it.to_string()),
+                Some(media.len().to_string()),
+            );
+            let identifiers = &file.identifiers;
+            compare("Label", identifiers.label.clone(), label.clone());
+            compare(
+                "Catalog number",
+                identifiers.catalog_number.clone(),
+                catalog_number.clone(),
+            );
+            compare(
+                "Barcode",
+                identifiers.barcode.clone(),
+                release.barcode.clone().filter(|it| !it.is_empty()),
+            );
+            compare("Media", identifiers.media.clone(), medium.format.clone());
+
+            TrackDiff {
+                path: file.path.clone(),
+                track: Some(track.number.clone()),
+                changes,
+            }
+        })
+        .collect();
+
+    ReleaseDiff {
+        release: format!(
+            "{} - {}",
+            album_artist.as_deref().unwrap_or("?"),
+            release.title
+        ),
+        tracks,
+    }
+}
+
+/// The track a file was tagged as: by its release track ID, then by its
+/// recording ID, then by its disc and track number.
+fn find_track<'a>(media: &'a [Media], file: &AudioFile) -> Option<(&'a Media, &'a Track)> {
+    let tracks = || {
+        media
+            .iter()
+            .flat_map(|medium| medium.tracks.iter().flatten().map(move |it| (medium, it)))
+    };
+    let ids = &file.musicbrainz;
+
+    if let Some(id) = &ids.release_track_id
+        && let Some(found) = tracks().find(|(_, it)| it.id == *id)
+    {
+        return Some(found);
+    }
+    if let Some(id) = &ids.recording_id {
+        let mut found =
+            tracks().filter(|(_, it)| it.recording.as_ref().is_some_and(|it| it.id == *id));
+        // A recording on the release twice can't be told apart this way.
+        if let (Some(found), None) = (found.next(), found.next()) {
+            return Some(found);
+        }
+    }
+
+    let disc = file.disc_number.unwrap_or(1);
+    let number = file.track_number?;
+    tracks().find(|(medium, it)| medium.position.unwrap_or(1) == disc && it.position == number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::{
+        codecs::AudioCodec,
+        models::{MusicBrainzIds, ReleaseIdentifiers, ReplayGain},
+    };
+
+    fn track(id: &str, position: u32, title: &str, recording: &str) -> serde_json::Value {
+        json!({
+            "id": id,
+            "number": position.to_string(),
+            "position": position,
+            "title": title,
+            "length": 200000,
+            "recording": { "id": recording, "title": title, "length": 200000 }
+        })
+    }
+
+    /// Two discs, the second opening with a reprise of the first track's
+    /// recording.
+    fn release() -> Release {
+        serde_json::from_value(json!({
+            "id": "b84ee12a-09ef-421b-82de-0441a926375b",
+            "title": "Fixture Songs",
+            "barcode": "5012345678900",
+            "artist-credit": [{
+                "name": "The Testers",
+                "joinphrase": "",
+                "artist": {
+                    "id": "0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d",
+                    "name": "The Testers",
+                    "sort-name": "Testers, The"
+                }
+            }],
+            "label-info": [{
+                "catalog-number": "TEST001",
+                "label": { "id": "5c4b3a29-1807-4f6e-9d5c-4b3a29180716", "name": "Fixture Records" }
+            }],
+            "media": [
+                {
+                    "position": 1,
+                    "format": "CD",
+                    "track-count": 2,
+                    "tracks": [
+                        track("track-1-1", 1, "Opening Test", "recording-1"),
+                        track("track-1-2", 2, "Assertion Blues", "recording-2"),
+                    ]
+                },
+                {
+                    "position": 2,
+                    "format": "CD",
+                    "track-count": 1,
+                    "tracks": [track("track-2-1", 1, "Opening Test", "recording-1")]
+                }
+            ]
+        }))
+        .unwrap()
+    }
+
+    /// A file tagged exactly as the given track of the release.
+    fn file(disc: u32, number: u32, title: &str) -> AudioFile {
+        AudioFile {
+            path: PathBuf::from(format!("/music/{}-{}.flac", disc, number)),
+            codec: AudioCodec::Flac,
+            title: Some(String::from(title)),
+            artist: Some(String::from("The Testers")),
+            album_artist: Some(String::from("The Testers")),
+            album: Some(String::from("Fixture Songs")),
+            track_number: Some(number),
+            total_tracks: Some(if disc == 1 { 2 } else { 1 }),
+            disc_number: Some(disc),
+            total_discs: Some(2),
+            genre: None,
+            year: None,
+            duration: None,
+            duration_source: None,
+            legacy_encoding: None,
+            replay_gain: ReplayGain::default(),
+            identifiers: ReleaseIdentifiers {
+                barcode: Some(String::from("5012345678900")),
+                catalog_number: Some(String::from("TEST001")),
+                label: Some(String::from("Fixture Records")),
+                media: Some(String::from("CD")),
+            },
+            isrc: None,
+            musicbrainz: MusicBrainzIds::default(),
+            audio_digest: None,
+        }
+    }
+
+    fn cluster(tracks: Vec<AudioFile>) -> AlbumCluster {
+        AlbumCluster {
+            album: String::from("Fixture Songs"),
+            album_artist: String::from("The Testers"),
+            tracks,
+            base_path: PathBuf::from("/music"),
+            total_discs: 2,
+            encoding_suggestion: None,
+            identifiers: ReleaseIdentifiers::default(),
+        }
+    }
+
+    /// The ID of the track a file is found as.
+    fn found(file: &AudioFile) -> Option<String> {
+        let release = release();
+        find_track(release.media.as_deref().unwrap(), file).map(|(_, it)| it.id.clone())
+    }
+
+    #[test]
+    fn matching_tags_have_no_changes() {
+        let cluster = cluster(vec![
+            file(1, 1, "Opening Test"),
+            file(1, 2, "Assertion Blues"),
+            file(2, 1, "Opening Test"),
+        ]);
+
+        let diff = diff(&cluster, &release());
+
+        assert_eq!(diff.release, "The Testers - Fixture Songs");
+        assert_eq!(diff.change_count(), 0);
+        assert_eq!(diff.unmatched_count(), 0);
+    }
+
+    #[test]
+    fn changed_and_missing_tags_are_listed() {
+        let mut file = file(1, 2, "Assertion  Blues (Demo)");
+        file.total_discs = None;
+        file.identifiers.catalog_number = None;
+
+        let diff = diff(&cluster(vec![file]), &release());
+
+        let change = |field, old: Option<&str>, new: &str| FieldChange {
+            field,
+            old: old.map(String::from),
+            new: String::from(new),
+        };
+        assert_eq!(
+            diff.tracks[0].changes,
+            [
+                change("Title", Some("Assertion  Blues (Demo)"), "Assertion Blues"),
+                change("Total discs", None, "2"),
+                change("Catalog number", None, "TEST001"),
+            ]
+        );
+    }
+
+    #[test]
+    fn files_off_the_release_are_unmatched() {
+        let diff = diff(&cluster(vec![file(3, 1, "Encore")]), &release());
+
+        assert_eq!(diff.unmatched_count(), 1);
+        assert_eq!(diff.tracks[0].track, None);
+        assert!(diff.tracks[0].changes.is_empty());
+    }
+
+    #[test]
+    fn release_track_id_wins_over_position() {
+        let mut file = file(1, 1, "Opening Test");
+        file.musicbrainz.release_track_id = Some(String::from("track-1-2"));
+        file.musicbrainz.recording_id = Some(String::from("recording-1"));
+
+        assert_eq!(found(&file).as_deref(), Some("track-1-2"));
+    }
+
+    #[test]
+    fn recording_id_wins_over_position() {
+        let mut file = file(1, 1, "Opening Test");
+        file.musicbrainz.release_track_id = Some(String::from("track-gone"));
+        file.musicbrainz.recording_id = Some(String::from("recording-2"));
+
+        assert_eq!(found(&file).as_deref(), Some("track-1-2"));
+    }
+
+    #[test]
+    fn recording_on_the_release_twice_falls_back_to_position() {
+        let mut file = file(2, 1, "Opening Test");
+        file.musicbrainz.recording_id = Some(String::from("recording-1"));
+
+        assert_eq!(found(&file).as_deref(), Some("track-2-1"));
+    }
+
+    #[test]
+    fn position_defaults_to_the_first_disc() {
+        let mut file = file(1, 2, "Assertion Blues");
+        file.disc_number = None;
+        assert_eq!(found(&file).as_deref(), Some("track-1-2"));
+
+        file.track_number = None;
+        assert_eq!(found(&file), None);
+    }
+}
@@ -0,0 +1,45 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use std::path::Path;
+
+/// Render a path for display, escaping bytes that aren't valid UTF-8.
+///
+/// Unlike [`Path::display`], which replaces undecodable bytes with U+FFFD,
+/// this keeps distinct filenames distinguishable, e.g. `caf\xE9.mp3`.  A
+/// literal backslash is doubled so it can't be mistaken for an escape.
+pub fn escape_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    for chunk in path.as_os_str().as_encoded_bytes().utf8_chunks() {
+        escaped.push_str(&chunk.valid().replace('\\', "\\\\"));
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{:02X}", byte));
+        }
+    }
+    escaped
+}
+
+// Paths are only arbitrary bytes on Unix.
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    use super::*;
+
+    fn escape(bytes: &[u8]) -> String {
+        escape_path(Path::new(OsStr::from_bytes(bytes)))
+    }
+
+    #[test]
+    fn invalid_bytes_are_escaped() {
+        assert_eq!(escape(b"/music/caf\xe9.mp3"), "/music/caf\\xE9.mp3");
+        assert_eq!(escape("/music/café.mp3".as_bytes()), "/music/café.mp3");
+    }
+
+    #[test]
+    fn backslashes_are_escaped() {
+        assert_eq!(escape(b"/music/\\x41.mp3"), "/music/\\\\x41.mp3");
+        assert_ne!(escape(b"/music/\\xE9.mp3"), escape(b"/music/\xe9.mp3"));
+    }
+}
@@ -0,0 +1,347 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Hashing of the audio payload of a file, ignoring any embedded tags.
+//!
+//! Two rips of the same audio with different tags produce the same digest,
+//! which is what makes duplicate detection across clusters possible.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    ops::Range,
+    path::Path,
+};
+
+use color_eyre::eyre::{Result, bail};
+
+use crate::{codecs::AudioCodec, models::AudioDigest, scanner::ogg};
+
+const ID3V2_HEADER_LEN: u64 = 10;
+const ID3V1_TAG_LEN: u64 = 128;
+const APE_FOOTER_LEN: u64 = 32;
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Hash the audio payload of a file, excluding all metadata.
+pub fn audio_digest(path: &Path, codec: &AudioCodec) -> Result<AudioDigest> {
+    read_digest(&mut BufReader::new(File::open(path)?), codec)
+}
+
+fn read_digest<R: Read + Seek>(reader: &mut R, codec: &AudioCodec) -> Result<AudioDigest> {
+    let ranges = match codec {
+        AudioCodec::Mp3 => vec![mp3_audio_range(reader)?],
+        AudioCodec::Flac => vec![flac_audio_range(reader)?],
+        AudioCodec::Mp4 => mp4_audio_ranges(reader)?,
+        AudioCodec::Opus => ogg::audio_ranges(reader)?,
+    };
+
+    let mut hasher = blake3::Hasher::new();
+    let mut size = 0;
+    for range in ranges {
+        size += hash_range(reader, range, &mut hasher)?;
+    }
+
+    Ok(AudioDigest {
+        hash: hasher.finalize().to_hex().to_string(),
+        size,
+    })
+}
+
+fn hash_range<R: Read + Seek>(
+    reader: &mut R,
+    range: Range<u64>,
+    hasher: &mut blake3::Hasher,
+) -> io::Result<u64> {
+    reader.seek(SeekFrom::Start(range.start))?;
+    let mut remaining = range.end.saturating_sub(range.start);
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    let mut hashed = 0;
+
+    while remaining > 0 {
+        let want = remaining.min(buffer.len() as u64) as usize;
+        let read = reader.read(&mut buffer[..want])?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        remaining -= read as u64;
+        hashed += read as u64;
+    }
+
+    Ok(hashed)
+}
+
+/// Find the MPEG frames between a leading ID3v2 tag and any trailing
+/// APEv2/ID3v1 tags.
+fn mp3_audio_range<R: Read + Seek>(reader: &mut R) -> Result<Range<u64>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let start = skip_id3v2(reader, 0)?;
+    let mut end = len;
+
+    if end >= start + ID3V1_TAG_LEN {
+        let mut marker = [0; 3];
+        reader.seek(SeekFrom::Start(end - ID3V1_TAG_LEN))?;
+        reader.read_exact(&mut marker)?;
+        if &marker == b"TAG" {
+            end -= ID3V1_TAG_LEN;
+        }
+    }
+
+    if end >= start + APE_FOOTER_LEN {
+        let mut footer = [0; APE_FOOTER_LEN as usize];
+        reader.seek(SeekFrom::Start(end - APE_FOOTER_LEN))?;
+        reader.read_exact(&mut footer)?;
+        if &footer[..8] == b"APETAGEX" {
+            // The tag size includes the footer but not the optional header.
+            let tag_size = u32::from_le_bytes([footer[12], footer[13], footer[14], footer[15]]);
+            let flags = u32::from_le_bytes([footer[20], footer[21], footer[22], footer[23]]);
+            let has_header = flags & (1 << 31) != 0;
+            let total = tag_size as u64 + if has_header { APE_FOOTER_LEN } else { 0 };
+            end = end.saturating_sub(total).max(start);
+        }
+    }
+
+    Ok(start..end)
+}
+
+/// Return the offset just past an ID3v2 tag starting at `offset`, or
+/// `offset` itself when there is no tag.
+pub(super) fn skip_id3v2<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<u64> {
+    let mut header = [0; ID3V2_HEADER_LEN as usize];
+    reader.seek(SeekFrom::Start(offset))?;
+    if reader.read_exact(&mut header).is_err() || &header[..3] != b"ID3" {
+        return Ok(offset);
+    }
+
+    let size = synchsafe(&header[6..10]);
+    let has_footer = header[5] & 0x10 != 0;
+    let footer_len = if has_footer { ID3V2_HEADER_LEN } else { 0 };
+
+    Ok(offset + ID3V2_HEADER_LEN + size + footer_len)
+}
+
+fn synchsafe(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |acc, &byte| (acc << 7) | (byte & 0x7f) as u64)
+}
+
+/// Find the audio frames following the FLAC metadata blocks.
+fn flac_audio_range<R: Read + Seek>(reader: &mut R) -> Result<Range<u64>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    // Some taggers prepend an ID3v2 tag to FLAC files.
+    let mut offset = skip_id3v2(reader, 0)?;
+
+    let mut marker = [0; 4];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut marker)?;
+    if &marker != b"fLaC" {
+        bail!("Missing FLAC stream marker");
+    }
+    offset += 4;
+
+    loop {
+        let mut header = [0; 4];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut header)?;
+        let is_last = header[0] & 0x80 != 0;
+        let block_len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+        offset += 4 + block_len;
+        if is_last {
+            break;
+        }
+    }
+
+    Ok(offset.min(len)..len)
+}
+
+/// Find the `mdat` atoms holding the media data of an MP4 file.
+fn mp4_audio_ranges<R: Read + Seek>(reader: &mut R) -> Result<Vec<Range<u64>>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+
+    while offset + 8 <= len {
+        let mut header = [0; 8];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut header)?;
+
+        let mut header_len = 8;
+        let atom_len = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => len - offset,
+            1 => {
+                let mut large = [0; 8];
+                reader.read_exact(&mut large)?;
+                header_len = 16;
+                u64::from_be_bytes(large)
+            }
+            n => n as u64,
+        };
+        if atom_len < header_len {
+            bail!("Malformed MP4 atom at offset {}", offset);
+        }
+
+        if &header[4..8] == b"mdat" {
+            ranges.push(offset + header_len..(offset + atom_len).min(len));
+        }
+        offset += atom_len;
+    }
+
+    if ranges.is_empty() {
+        bail!("No media data found in MP4 file");
+    }
+
+    Ok(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Stands in for encoded audio; the digest never looks inside it.
+    const AUDIO: &[u8] = b"\xff\xfb\x90\x64 pretend these are frames of audio";
+
+    fn digest(codec: AudioCodec, bytes: Vec<u8>) -> AudioDigest {
+        read_digest(&mut Cursor::new(bytes), &codec).unwrap()
+    }
+
+    fn id3v2(text: &str) -> Vec<u8> {
+        let body = text.as_bytes();
+        let len = body.len();
+        let mut tag = b"ID3\x04\x00\x00".to_vec();
+        tag.extend([(len >> 21) as u8 & 0x7f, (len >> 14) as u8 & 0x7f]);
+        tag.extend([(len >> 7) as u8 & 0x7f, len as u8 & 0x7f]);
+        tag.extend_from_slice(body);
+        tag
+    }
+
+    fn id3v1(title: &str) -> Vec<u8> {
+        let mut tag = b"TAG".to_vec();
+        tag.extend_from_slice(title.as_bytes());
+        tag.resize(ID3V1_TAG_LEN as usize, 0);
+        tag
+    }
+
+    fn ape(text: &str) -> Vec<u8> {
+        let mut tag = text.as_bytes().to_vec();
+        let size = (tag.len() as u64 + APE_FOOTER_LEN) as u32;
+        tag.extend_from_slice(b"APETAGEX");
+        tag.extend(2000u32.to_le_bytes());
+        tag.extend(size.to_le_bytes());
+        tag.extend(1u32.to_le_bytes());
+        tag.extend(0u32.to_le_bytes());
+        tag.extend([0; 8]);
+        tag
+    }
+
+    fn flac_block(kind: u8, is_last: bool, body: &[u8]) -> Vec<u8> {
+        let len = (body.len() as u32).to_be_bytes();
+        let mut block = vec![
+            kind | if is_last { 0x80 } else { 0 },
+            len[1],
+            len[2],
+            len[3],
+        ];
+        block.extend_from_slice(body);
+        block
+    }
+
+    fn flac(comment: &str) -> Vec<u8> {
+        let mut bytes = b"fLaC".to_vec();
+        bytes.extend(flac_block(0, false, &[0; 34]));
+        bytes.extend(flac_block(4, true, comment.as_bytes()));
+        bytes.extend_from_slice(AUDIO);
+        bytes
+    }
+
+    fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut atom = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        atom.extend_from_slice(kind);
+        atom.extend_from_slice(body);
+        atom
+    }
+
+    fn mp4(title: &str) -> Vec<u8> {
+        let mut bytes = atom(b"ftyp", b"M4A \0\0\0\0");
+        bytes.extend(atom(b"moov", &atom(b"udta", title.as_bytes())));
+        bytes.extend(atom(b"mdat", AUDIO));
+        bytes
+    }
+
+    #[test]
+    fn mp3_tags_are_not_hashed() {
+        let bare = digest(AudioCodec::Mp3, AUDIO.to_vec());
+        let tagged = digest(
+            AudioCodec::Mp3,
+            [
+                id3v2("TIT2 Some title"),
+                AUDIO.to_vec(),
+                ape("Artist"),
+                id3v1("Some title"),
+            ]
+            .concat(),
+        );
+        let retagged = digest(
+            AudioCodec::Mp3,
+            [
+                id3v2("TIT2 A much longer title than before"),
+                AUDIO.to_vec(),
+            ]
+            .concat(),
+        );
+
+        assert_eq!(bare.size, AUDIO.len() as u64);
+        assert_eq!(tagged.hash, bare.hash);
+        assert_eq!(retagged.hash, bare.hash);
+    }
+
+    #[test]
+    fn mp3_audio_changes_are_hashed() {
+        let bare = digest(AudioCodec::Mp3, AUDIO.to_vec());
+        let mut edited = AUDIO.to_vec();
+        edited[10] ^= 1;
+
+        assert_ne!(digest(AudioCodec::Mp3, edited).hash, bare.hash);
+    }
+
+    #[test]
+    fn flac_metadata_blocks_are_not_hashed() {
+        let tagged = digest(AudioCodec::Flac, flac("TITLE=Some title"));
+        let retagged = digest(
+            AudioCodec::Flac,
+            flac("TITLE=Another title\nARTIST=Someone"),
+        );
+        let id3_prefixed = digest(
+            AudioCodec::Flac,
+            [id3v2("TIT2 Stray"), flac("TITLE=Some title")].concat(),
+        );
+
+        assert_eq!(tagged.size, AUDIO.len() as u64);
+        assert_eq!(retagged.hash, tagged.hash);
+        assert_eq!(id3_prefixed.hash, tagged.hash);
+    }
+
+    #[test]
+    fn flac_without_marker_is_rejected() {
+        assert!(read_digest(&mut Cursor::new(AUDIO.to_vec()), &AudioCodec::Flac).is_err());
+    }
+
+    #[test]
+    fn mp4_metadata_atoms_are_not_hashed() {
+        let tagged = digest(AudioCodec::Mp4, mp4("Some title"));
+        let retagged = digest(AudioCodec::Mp4, mp4("A much longer title than before"));
+
+        assert_eq!(tagged.size, AUDIO.len() as u64);
+        assert_eq!(retagged.hash, tagged.hash);
+    }
+
+    #[test]
+    fn mp4_without_media_data_is_rejected() {
+        let bytes = atom(b"ftyp", b"M4A \0\0\0\0");
+        assert!(read_digest(&mut Cursor::new(bytes), &AudioCodec::Mp4).is_err());
+    }
+}
@@ -2,11 +2,27 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
-use crate::{codecs::AudioCodec, models::AudioFile};
+use crate::{
+    codecs::AudioCodec,
+    models::{AudioFile, DurationSource, MusicBrainzIds, ReleaseIdentifiers, ReplayGain},
+    scanner::{digest, mp3, ogg},
+};
 
+use chardetng::EncodingDetector;
 use color_eyre::Result;
+use encoding_rs::{Encoding, WINDOWS_1252};
 use id3::TagLike;
-use std::{path::Path, time::Duration};
+use std::{fs::File, io::BufReader, path::Path, time::Duration};
+
+/// Description of the comment frame or freeform atom holding Apple's Sound
+/// Check data.
+const ITUNNORM: &str = "iTunNORM";
+
+/// Owner of the unique file identifier frame holding the recording MBID.
+const MUSICBRAINZ_UFID_OWNER: &str = "http://musicbrainz.org";
+
+/// ID3 text frames whose contents are used for clustering and searching.
+const ID3_TEXT_FRAMES: &[&str] = &["TIT2", "TPE1", "TPE2", "TALB", "TCON"];
 
 /// Extract metadata from an audio file.
 pub fn extract(path: &Path) -> Result<AudioFile> {
@@ -16,20 +32,51 @@ pub fn extract(path: &Path) -> Result<AudioFile> {
         .map(|s| s.to_lowercase())
         .unwrap_or_default();
 
-    let res = match ext.as_str() {
+    let mut res = match ext.as_str() {
         "mp3" => extract_mp3(path)?,
         "m4a" => extract_mp4(path)?,
         "flac" => extract_flac(path)?,
+        "opus" => extract_opus(path)?,
         // FIXME: provide some kind of logging for these, or prompt?
         _ => todo!(),
     };
+    res.audio_digest = digest::audio_digest(path, &res.codec).ok();
 
     Ok(res)
 }
 
 fn extract_mp3(path: &Path) -> Result<AudioFile> {
-    let tag = id3::Tag::read_from_path(path)?;
-    let duration = mp3_duration::from_path(path).ok();
+    // Fall back to ID3v1 when a file has no ID3v2 tag.
+    let tag = id3::v1v2::read_from_path(path)?;
+    let (duration, duration_source) = mp3::duration(path).ok().unzip();
+    let fields: Vec<(&str, &str)> = tag
+        .extended_texts()
+        .map(|it| (it.description.as_str(), it.value.as_str()))
+        .chain(
+            tag.comments()
+                .filter(|it| it.description == ITUNNORM)
+                .map(|it| (ITUNNORM, it.text.as_str())),
+        )
+        .collect();
+    let mut identifiers = parse_identifiers(&fields);
+    // ID3 has dedicated frames for the label and media type.
+    if let Some(publisher) = tag.get("TPUB").and_then(|it| it.content().text()) {
+        identifiers.label = Some(publisher.to_string());
+    }
+    if let Some(media) = tag.get("TMED").and_then(|it| it.content().text()) {
+        identifiers.media = Some(media.to_string());
+    }
+    let mut musicbrainz = parse_musicbrainz_ids(&fields);
+    // Picard writes the recording ID in a unique file identifier frame.
+    if let Some(id) = tag
+        .frames()
+        .filter_map(|it| it.content().unique_file_identifier())
+        .find(|it| it.owner_identifier == MUSICBRAINZ_UFID_OWNER)
+        .and_then(|it| String::from_utf8(it.identifier.clone()).ok())
+    {
+        musicbrainz.recording_id = Some(id.trim().to_lowercase());
+    }
+
     Ok(AudioFile {
         path: path.to_path_buf(),
         codec: AudioCodec::Mp3,
@@ -42,12 +89,79 @@ fn extract_mp3(path: &Path) -> Result<AudioFile> {
         disc_number: tag.disc(),
         total_discs: tag.total_discs(),
         genre: tag.genre().map(String::from),
+        // ID3v2.4 replaced the year frame with a recording timestamp.
+        year: tag
+            .year()
+            .or_else(|| tag.date_recorded().map(|it| it.year))
+            .and_then(|it| u32::try_from(it).ok()),
         duration,
+        duration_source,
+        legacy_encoding: detect_legacy_encoding(&tag),
+        replay_gain: parse_replay_gain(&fields),
+        identifiers,
+        isrc: tag
+            .get("TSRC")
+            .and_then(|it| it.content().text())
+            .map(|it| it.trim().to_string()),
+        musicbrainz,
+        audio_digest: None,
     })
 }
 
+/// Guess the real encoding of text frames that claim to be Latin-1.
+///
+/// ID3v1 and many ID3v2.3 taggers wrote strings in whatever the local code
+/// page was (CP1251, Shift-JIS, GBK, ...) while declaring them as Latin-1.
+/// Returns `None` when the text is plausibly Western European after all.
+fn detect_legacy_encoding(tag: &id3::Tag) -> Option<&'static Encoding> {
+    let mut bytes = Vec::new();
+    for frame in tag.frames() {
+        let is_legacy = matches!(frame.encoding(), None | Some(id3::Encoding::Latin1));
+        if !is_legacy || !ID3_TEXT_FRAMES.contains(&frame.id()) {
+            continue;
+        }
+        if let Some(raw) = frame.content().text().and_then(latin1_bytes) {
+            bytes.extend(raw);
+            bytes.push(b' ');
+        }
+    }
+
+    if bytes.is_ascii() {
+        return None;
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(&bytes, true);
+    let encoding = detector.guess(None, false);
+    (encoding != WINDOWS_1252).then_some(encoding)
+}
+
+/// Recover the bytes of a string that was decoded as Latin-1.
+fn latin1_bytes(text: &str) -> Option<Vec<u8>> {
+    text.chars().map(|c| u8::try_from(c).ok()).collect()
+}
+
+/// Re-decode a string that was wrongly decoded as Latin-1.
+///
+/// Returns `None` if the text could not have come from a Latin-1 decoding or
+/// isn't valid in the given encoding.
+pub fn repair_text(text: &str, encoding: &'static Encoding) -> Option<String> {
+    let bytes = latin1_bytes(text)?;
+    encoding
+        .decode_without_bom_handling_and_without_replacement(&bytes)
+        .map(|it| it.into_owned())
+}
+
 fn extract_mp4(path: &Path) -> Result<AudioFile> {
     let tag = mp4ameta::Tag::read_from_path(path)?;
+    let fields: Vec<(&str, &str)> = tag
+        .data()
+        .filter_map(|(ident, data)| match ident {
+            mp4ameta::DataIdent::Freeform { name, .. } => Some((name.as_ref(), data.string()?)),
+            _ => None,
+        })
+        .collect();
+
     Ok(AudioFile {
         path: path.to_path_buf(),
         codec: AudioCodec::Mp4,
@@ -60,7 +174,15 @@ fn extract_mp4(path: &Path) -> Result<AudioFile> {
         disc_number: tag.disc_number().map(|n| n as u32),
         total_discs: tag.total_discs().map(|n| n as u32),
         genre: tag.genre().map(String::from),
+        year: tag.year().and_then(parse_year),
         duration: Some(tag.duration()),
+        duration_source: Some(DurationSource::StreamInfo),
+        legacy_encoding: None,
+        replay_gain: parse_replay_gain(&fields),
+        identifiers: parse_identifiers(&fields),
+        isrc: parse_isrc(&fields),
+        musicbrainz: parse_musicbrainz_ids(&fields),
+        audio_digest: None,
     })
 }
 
@@ -88,9 +210,23 @@ fn extract_flac(path: &Path) -> Result<AudioFile> {
         .and_then(|t| t.iter().next())
         .map(String::from);
 
+    let genre = vorbis
+        .and_then(|v| v.genre())
+        .and_then(|g| g.iter().next())
+        .map(String::from);
+
     let track_number = vorbis.and_then(|v| v.track());
     let total_tracks = vorbis.and_then(|v| v.total_tracks());
 
+    let fields: Vec<(&str, &str)> = vorbis
+        .into_iter()
+        .flat_map(|v| {
+            v.comments
+                .iter()
+                .flat_map(|(key, values)| values.iter().map(move |it| (key.as_str(), it.as_str())))
+        })
+        .collect();
+
     let duration = tag
         .get_streaminfo()
         .map(|v| Duration::from_secs(v.total_samples / v.sample_rate as u64));
@@ -105,9 +241,257 @@ fn extract_flac(path: &Path) -> Result<AudioFile> {
         track_number,
         total_tracks,
         duration,
-        // TODO
-        disc_number: None,
-        total_discs: None,
-        genre: None,
+        duration_source: duration.map(|_| DurationSource::StreamInfo),
+        legacy_encoding: None,
+        replay_gain: parse_replay_gain(&fields),
+        identifiers: parse_identifiers(&fields),
+        isrc: parse_isrc(&fields),
+        musicbrainz: parse_musicbrainz_ids(&fields),
+        disc_number: parse_number(&fields, "DISCNUMBER"),
+        total_discs: parse_number(&fields, "DISCTOTAL")
+            .or_else(|| parse_number(&fields, "TOTALDISCS")),
+        genre,
+        year: fields
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("DATE"))
+            .and_then(|(_, value)| parse_year(value)),
+        audio_digest: None,
+    })
+}
+
+fn extract_opus(path: &Path) -> Result<AudioFile> {
+    let info = ogg::read_opus(&mut BufReader::new(File::open(path)?))?;
+    let fields: Vec<(&str, &str)> = info
+        .comments
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    let comment = |key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.to_string())
+    };
+    let number = |key: &str| parse_number(&fields, key);
+
+    Ok(AudioFile {
+        path: path.to_path_buf(),
+        codec: AudioCodec::Opus,
+        title: comment("TITLE"),
+        artist: comment("ARTIST"),
+        album_artist: comment("ALBUMARTIST"),
+        album: comment("ALBUM"),
+        track_number: number("TRACKNUMBER"),
+        total_tracks: number("TRACKTOTAL").or_else(|| number("TOTALTRACKS")),
+        disc_number: number("DISCNUMBER"),
+        total_discs: number("DISCTOTAL").or_else(|| number("TOTALDISCS")),
+        genre: comment("GENRE"),
+        year: comment("DATE").as_deref().and_then(parse_year),
+        duration: info.duration,
+        duration_source: info.duration.map(|_| DurationSource::StreamInfo),
+        legacy_encoding: None,
+        replay_gain: parse_replay_gain(&fields),
+        identifiers: parse_identifiers(&fields),
+        isrc: parse_isrc(&fields),
+        musicbrainz: parse_musicbrainz_ids(&fields),
+        audio_digest: None,
     })
 }
+
+/// The year at the start of a date such as "2019" or "2019-05-17".
+fn parse_year(date: &str) -> Option<u32> {
+    date.trim()
+        .get(..4)
+        .filter(|it| it.bytes().all(|b| b.is_ascii_digit()))?
+        .parse()
+        .ok()
+}
+
+/// Collect ReplayGain, R128 and Sound Check values from tag key/value pairs.
+///
+/// Keys are matched case-insensitively since taggers disagree on case, and
+/// values that fail to parse are ignored.
+fn parse_replay_gain(fields: &[(&str, &str)]) -> ReplayGain {
+    let mut gain = ReplayGain::default();
+
+    for &(key, value) in fields {
+        let slot = match key.to_ascii_uppercase().as_str() {
+            "REPLAYGAIN_TRACK_GAIN" => (&mut gain.track_gain, parse_gain(value)),
+            "REPLAYGAIN_TRACK_PEAK" => (&mut gain.track_peak, value.trim().parse().ok()),
+            "REPLAYGAIN_ALBUM_GAIN" => (&mut gain.album_gain, parse_gain(value)),
+            "REPLAYGAIN_ALBUM_PEAK" => (&mut gain.album_peak, value.trim().parse().ok()),
+            "R128_TRACK_GAIN" => (&mut gain.r128_track_gain, parse_r128(value)),
+            "R128_ALBUM_GAIN" => (&mut gain.r128_album_gain, parse_r128(value)),
+            "ITUNNORM" => (&mut gain.sound_check, parse_itunnorm(value)),
+            _ => continue,
+        };
+        if let (field @ None, Some(value)) = slot {
+            *field = Some(value);
+        }
+    }
+
+    gain
+}
+
+/// Collect the barcode, catalog number, label and media from tag key/value
+/// pairs, using the names Picard writes.
+fn parse_identifiers(fields: &[(&str, &str)]) -> ReleaseIdentifiers {
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, value)| key.eq_ignore_ascii_case(name) && !value.trim().is_empty())
+            .map(|(_, value)| value.trim().to_string())
+    };
+
+    ReleaseIdentifiers {
+        barcode: field("BARCODE"),
+        catalog_number: field("CATALOGNUMBER"),
+        label: field("LABEL"),
+        media: field("MEDIA"),
+    }
+}
+
+/// Read a number from Vorbis comments, which allow both "3" and "3/12".
+fn parse_number(fields: &[(&str, &str)], key: &str) -> Option<u32> {
+    fields
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .and_then(|(_, value)| value.split('/').next()?.trim().parse().ok())
+}
+
+/// Read the ISRC, which Picard writes under the same name in Vorbis
+/// comments and MP4 freeform atoms.
+fn parse_isrc(fields: &[(&str, &str)]) -> Option<String> {
+    fields
+        .iter()
+        .find(|(key, value)| key.eq_ignore_ascii_case("ISRC") && !value.trim().is_empty())
+        .map(|(_, value)| value.trim().to_string())
+}
+
+/// Read the MusicBrainz IDs, which Picard writes as `MUSICBRAINZ_ALBUMID`
+/// and so on in Vorbis comments, and as "MusicBrainz Album Id" and so on in
+/// ID3 TXXX frames and MP4 freeform atoms.
+fn parse_musicbrainz_ids(fields: &[(&str, &str)]) -> MusicBrainzIds {
+    let field = |names: [&str; 2]| {
+        fields
+            .iter()
+            .find(|(key, value)| {
+                names.iter().any(|it| key.eq_ignore_ascii_case(it)) && !value.trim().is_empty()
+            })
+            .map(|(_, value)| value.trim().to_lowercase())
+    };
+
+    MusicBrainzIds {
+        release_id: field(["MUSICBRAINZ_ALBUMID", "MusicBrainz Album Id"]),
+        release_track_id: field(["MUSICBRAINZ_RELEASETRACKID", "MusicBrainz Release Track Id"]),
+        recording_id: field(["MUSICBRAINZ_TRACKID", "MusicBrainz Track Id"]),
+    }
+}
+
+/// Parse a gain such as "-6.50 dB".
+fn parse_gain(value: &str) -> Option<f32> {
+    value
+        .trim()
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// R128 gains are Q7.8 fixed-point integers, in dB.
+fn parse_r128(value: &str) -> Option<f32> {
+    value.trim().parse::<i16>().ok().map(|it| it as f32 / 256.0)
+}
+
+/// Convert the left/right 1/1000 W adjustments at the start of an `iTunNORM`
+/// value into a gain in dB.
+fn parse_itunnorm(value: &str) -> Option<f32> {
+    let mut values = value
+        .split_whitespace()
+        .map(|it| u32::from_str_radix(it, 16).ok());
+    let (left, right) = (values.next()??, values.next()??);
+    let loudest = left.max(right);
+    (loudest > 0).then(|| -10.0 * (loudest as f32 / 1000.0).log10())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Option<f32>, b: f32) -> bool {
+        a.is_some_and(|a| (a - b).abs() < 0.001)
+    }
+
+    #[test]
+    fn replay_gain_tags_are_read_in_any_case() {
+        let gain = parse_replay_gain(&[
+            ("replaygain_track_gain", "-6.50 dB"),
+            ("REPLAYGAIN_TRACK_PEAK", "0.988"),
+            ("ReplayGain_Album_Gain", " -7.25 dB "),
+            ("REPLAYGAIN_ALBUM_PEAK", "1.000000"),
+            ("TITLE", "Opening Test"),
+        ]);
+
+        assert_eq!(gain.track_gain, Some(-6.5));
+        assert_eq!(gain.track_peak, Some(0.988));
+        assert_eq!(gain.album_gain, Some(-7.25));
+        assert_eq!(gain.album_peak, Some(1.0));
+        assert_eq!(gain.r128_track_gain, None);
+    }
+
+    #[test]
+    fn first_parsable_value_wins() {
+        let gain = parse_replay_gain(&[
+            ("REPLAYGAIN_TRACK_GAIN", "loud"),
+            ("REPLAYGAIN_TRACK_GAIN", "+1.5 dB"),
+            ("REPLAYGAIN_TRACK_GAIN", "-3 dB"),
+        ]);
+        assert_eq!(gain.track_gain, Some(1.5));
+    }
+
+    #[test]
+    fn r128_gains_are_q7_8_fixed_point() {
+        assert_eq!(parse_r128("-512"), Some(-2.0));
+        assert_eq!(parse_r128(" 384 "), Some(1.5));
+        assert_eq!(parse_r128("0"), Some(0.0));
+        assert_eq!(parse_r128("-32768"), Some(-128.0));
+        // Out of range for an i16, or not an integer at all.
+        assert_eq!(parse_r128("40000"), None);
+        assert_eq!(parse_r128("-2.0"), None);
+
+        let gain = parse_replay_gain(&[("R128_TRACK_GAIN", "-1280"), ("R128_ALBUM_GAIN", "256")]);
+        assert_eq!(gain.r128_track_gain, Some(-5.0));
+        assert_eq!(gain.r128_album_gain, Some(1.0));
+    }
+
+    #[test]
+    fn itunnorm_uses_the_louder_channel() {
+        let value = " 000003E8 000007D0 00002710 00002710 00000000 00000000 00007FFF 00007FFF \
+                     00000000 00000000";
+        // 2000/1000 W is a 3 dB reduction.
+        assert!(close(parse_itunnorm(value), -3.0103));
+        assert!(close(parse_itunnorm("000003E8 000003E8"), 0.0));
+        assert!(close(parse_itunnorm("00000064 0000000a"), 10.0));
+    }
+
+    #[test]
+    fn malformed_itunnorm_is_ignored() {
+        assert_eq!(parse_itunnorm(""), None);
+        assert_eq!(parse_itunnorm("000003E8"), None);
+        assert_eq!(parse_itunnorm("000003E8 nothex"), None);
+        assert_eq!(parse_itunnorm("00000000 00000000"), None);
+    }
+
+    #[test]
+    fn vorbis_numbers_may_include_the_total() {
+        let fields = [
+            ("discnumber", "2/3"),
+            ("DISCTOTAL", " 3 "),
+            ("TRACKNUMBER", "A1"),
+        ];
+        assert_eq!(parse_number(&fields, "DISCNUMBER"), Some(2));
+        assert_eq!(parse_number(&fields, "DISCTOTAL"), Some(3));
+        assert_eq!(parse_number(&fields, "TRACKNUMBER"), None);
+        assert_eq!(parse_number(&fields, "TOTALDISCS"), None);
+    }
+}
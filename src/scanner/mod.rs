@@ -3,50 +3,84 @@
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
     sync::mpsc::{Sender, SyncSender},
 };
 
 use color_eyre::eyre::Result;
 use color_eyre::eyre::WrapErr;
+use encoding_rs::Encoding;
 use rayon::prelude::*;
 
 use crate::credit::UNKNOWN_ALBUM_NAME;
 use crate::credit::UNKNOWN_ARTIST_NAME;
-use crate::models::{AlbumCluster, AudioFile};
+use crate::models::{AlbumCluster, AudioFile, EncodingSuggestion, ReleaseIdentifiers};
+use crate::paths::escape_path;
 
+mod digest;
 mod metadata;
+mod mp3;
+mod ogg;
 
-const SUPPORTED_AUDIO_EXTENSIONS: &[&str] = &["mp3", "m4a", "flac"];
+const SUPPORTED_AUDIO_EXTENSIONS: &[&str] = &["mp3", "m4a", "flac", "opus"];
 
 const DEFAULT_TOTAL_DISCS: u8 = 1;
 
 pub struct ScanProgress {
-    pub current_dir: String,
+    pub current_dir: PathBuf,
     pub clusters_found: usize,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// Descend into symlinked directories and read symlinked files.
+    pub follow_symlinks: bool,
+}
+
+/// Identifies a directory independently of the path used to reach it.
+#[cfg(unix)]
+type DirId = (u64, u64);
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+#[derive(Default)]
+struct ScanState {
+    clusters_found: usize,
+    files_found: Vec<AudioFile>,
+    /// Directories already scanned, to break symlink loops.
+    visited: HashSet<DirId>,
+}
+
 /// Scan a directory recursively for audio files and extract their
 /// metadata.
+///
+/// Returns every audio file found once the scan is complete.
 pub fn scan_directory(
     path: &Path,
+    options: &ScanOptions,
     cluster_tx: SyncSender<AlbumCluster>,
     progress_tx: Option<Sender<ScanProgress>>,
-) -> Result<()> {
-    let mut clusters_found = 0;
-    scan_directory_recursive(path, &cluster_tx, &progress_tx, &mut clusters_found)?;
-    Ok(())
+) -> Result<Vec<AudioFile>> {
+    let mut state = ScanState::default();
+    scan_directory_recursive(path, options, &cluster_tx, &progress_tx, &mut state)?;
+    Ok(state.files_found)
 }
 
-pub fn scan_directory_recursive(
+fn scan_directory_recursive(
     path: &Path,
+    options: &ScanOptions,
     cluster_tx: &SyncSender<AlbumCluster>,
     progress_tx: &Option<Sender<ScanProgress>>,
-    clusters_found: &mut usize,
+    state: &mut ScanState,
 ) -> Result<()> {
-    let entries =
-        std::fs::read_dir(path).context(format!("Failed to read directory: {}", path.display()))?;
+    let id = dir_id(path).context(format!("Failed to read directory: {}", escape_path(path)))?;
+    if !state.visited.insert(id) {
+        return Ok(());
+    }
+
+    let entries = std::fs::read_dir(path)
+        .context(format!("Failed to read directory: {}", escape_path(path)))?;
     let mut files = Vec::new();
     let mut subdirs = Vec::new();
 
@@ -55,19 +89,32 @@ pub fn scan_directory_recursive(
         let entry = entry?;
         let path = entry.path();
 
-        if path.is_dir() {
+        // Unlike `Path::is_dir`, the entry's file type doesn't follow symlinks.
+        let mut file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            if !options.follow_symlinks {
+                continue;
+            }
+            match std::fs::metadata(&path) {
+                Ok(target) => file_type = target.file_type(),
+                // Dangling link.
+                Err(_) => continue,
+            }
+        }
+
+        if file_type.is_dir() {
             if is_hidden(&path) {
                 continue;
             }
             subdirs.push(path);
-        } else if path.is_file() && is_supported_audio_file(&path) {
+        } else if file_type.is_file() && is_supported_audio_file(&path) {
             files.push(path);
         }
     }
 
     // Process subdirectories first (depth-first).
     for subdir in subdirs {
-        scan_directory_recursive(&subdir, cluster_tx, progress_tx, clusters_found)?;
+        scan_directory_recursive(&subdir, options, cluster_tx, progress_tx, state)?;
     }
 
     // Process files in the current directory.
@@ -81,14 +128,15 @@ pub fn scan_directory_recursive(
             let clusters = cluster_files(audio_files);
 
             for cluster in clusters {
+                state.files_found.extend(cluster.tracks.iter().cloned());
                 cluster_tx
                     .send(cluster)
                     .context("Failed to send cluster to queue")?;
-                *clusters_found += 1;
+                state.clusters_found += 1;
                 if let Some(tx) = progress_tx {
                     let _ = tx.send(ScanProgress {
-                        current_dir: path.display().to_string(),
-                        clusters_found: *clusters_found,
+                        current_dir: path.to_path_buf(),
+                        clusters_found: state.clusters_found,
                     });
                 }
             }
@@ -98,10 +146,22 @@ pub fn scan_directory_recursive(
     Ok(())
 }
 
+#[cfg(unix)]
+fn dir_id(path: &Path) -> std::io::Result<DirId> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(path)?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(path: &Path) -> std::io::Result<DirId> {
+    std::fs::canonicalize(path)
+}
+
 fn is_hidden(path: &Path) -> bool {
     path.file_name()
-        .and_then(|name| name.to_str())
-        .map(|name| name.starts_with('.'))
+        .map(|name| name.as_encoded_bytes().starts_with(b"."))
         .unwrap_or(false)
 }
 
@@ -124,17 +184,100 @@ pub fn cluster_files(files: Vec<AudioFile>) -> Vec<AlbumCluster> {
         .into_iter()
         .map(|(key, mut tracks)| {
             tracks.sort_by_key(|it| (it.disc_number.unwrap_or(1), it.track_number.unwrap_or(0)));
+            let encoding_suggestion = suggest_encoding(&key, &tracks);
+            let identifiers = common_identifiers(&tracks);
             AlbumCluster {
                 album_artist: key.album_artist,
                 album: key.album,
                 tracks,
                 base_path: key.base_path,
                 total_discs: key.total_discs,
+                encoding_suggestion,
+                identifiers,
             }
         })
         .collect()
 }
 
+/// Take each identifier from the value most tracks agree on.
+fn common_identifiers(tracks: &[AudioFile]) -> ReleaseIdentifiers {
+    let most_common = |field: fn(&ReleaseIdentifiers) -> &Option<String>| {
+        let mut votes: HashMap<&str, usize> = HashMap::new();
+        for value in tracks
+            .iter()
+            .filter_map(|it| field(&it.identifiers).as_deref())
+        {
+            *votes.entry(value).or_default() += 1;
+        }
+        votes
+            .into_iter()
+            .max_by_key(|(value, count)| (*count, *value))
+            .map(|(value, _)| value.to_string())
+    };
+
+    ReleaseIdentifiers {
+        barcode: most_common(|it| &it.barcode),
+        catalog_number: most_common(|it| &it.catalog_number),
+        label: most_common(|it| &it.label),
+        media: most_common(|it| &it.media),
+    }
+}
+
+/// Propose the legacy encoding detected for most of the cluster's tracks.
+fn suggest_encoding(key: &ClusterKey, tracks: &[AudioFile]) -> Option<EncodingSuggestion> {
+    let mut votes: HashMap<&'static Encoding, usize> = HashMap::new();
+    for encoding in tracks.iter().filter_map(|it| it.legacy_encoding) {
+        *votes.entry(encoding).or_default() += 1;
+    }
+    let (encoding, _) = votes
+        .into_iter()
+        .max_by_key(|(encoding, count)| (*count, encoding.name()))?;
+
+    let repair = |text: &str| metadata::repair_text(text, encoding).unwrap_or(text.to_string());
+    Some(EncodingSuggestion {
+        encoding,
+        album_artist: repair(&key.album_artist),
+        album: repair(&key.album),
+    })
+}
+
+/// Re-decode a cluster's tags using its suggested encoding.
+///
+/// The repaired tracks are clustered again, since the corrected tags may
+/// no longer agree with each other the way the garbled ones did.
+pub fn accept_encoding_suggestion(cluster: AlbumCluster) -> Vec<AlbumCluster> {
+    let Some(suggestion) = &cluster.encoding_suggestion else {
+        return vec![cluster];
+    };
+    let encoding = suggestion.encoding;
+
+    let repair = |field: &mut Option<String>| {
+        if let Some(text) = field
+            && let Some(repaired) = metadata::repair_text(text, encoding)
+        {
+            *text = repaired;
+        }
+    };
+
+    let tracks = cluster
+        .tracks
+        .into_iter()
+        .map(|mut track| {
+            if track.legacy_encoding.is_some() {
+                repair(&mut track.title);
+                repair(&mut track.artist);
+                repair(&mut track.album_artist);
+                repair(&mut track.album);
+                repair(&mut track.genre);
+                track.legacy_encoding = None;
+            }
+            track
+        })
+        .collect();
+
+    cluster_files(tracks)
+}
+
 #[derive(Debug, Hash, Eq, PartialEq)]
 struct ClusterKey {
     base_path: PathBuf,
@@ -0,0 +1,442 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! MP3 duration from the Xing/Info, VBRI and LAME headers.
+//!
+//! Encoders write a summary of the stream into the first MPEG frame, so the
+//! duration can be read without decoding every frame in the file.  A full
+//! frame scan is only used when no such header is present.
+
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+    time::Duration,
+};
+
+use color_eyre::eyre::{Result, bail};
+
+use crate::{models::DurationSource, scanner::digest};
+
+/// How far past the ID3v2 tag to look for the first frame before giving up.
+const MAX_SYNC_SEARCH: usize = 64 * 1024;
+
+/// Offset of the VBRI header from the start of the frame.
+const VBRI_OFFSET: usize = 36;
+
+/// Offset of the encoder delay/padding field from the start of the LAME tag.
+const LAME_DELAY_OFFSET: usize = 21;
+
+const BITRATES_V1_L1: [u32; 15] = [
+    0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+];
+const BITRATES_V1_L2: [u32; 15] = [
+    0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+];
+const BITRATES_V1_L3: [u32; 15] = [
+    0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+];
+const BITRATES_V2_L1: [u32; 15] = [
+    0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+];
+const BITRATES_V2_L23: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Version {
+    Mpeg1,
+    Mpeg2,
+    Mpeg25,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Layer {
+    L1,
+    L2,
+    L3,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FrameHeader {
+    version: Version,
+    layer: Layer,
+    bitrate: u32,
+    sample_rate: u32,
+    padding: bool,
+    is_mono: bool,
+}
+
+impl FrameHeader {
+    fn parse(bytes: [u8; 4]) -> Option<Self> {
+        if bytes[0] != 0xff || bytes[1] & 0xe0 != 0xe0 {
+            return None;
+        }
+
+        let version = match (bytes[1] >> 3) & 0b11 {
+            0b00 => Version::Mpeg25,
+            0b10 => Version::Mpeg2,
+            0b11 => Version::Mpeg1,
+            _ => return None,
+        };
+        let layer = match (bytes[1] >> 1) & 0b11 {
+            0b01 => Layer::L3,
+            0b10 => Layer::L2,
+            0b11 => Layer::L1,
+            _ => return None,
+        };
+
+        let bitrate_idx = (bytes[2] >> 4) as usize;
+        if bitrate_idx == 0 || bitrate_idx == 15 {
+            return None;
+        }
+        let bitrate = match (version, layer) {
+            (Version::Mpeg1, Layer::L1) => BITRATES_V1_L1[bitrate_idx],
+            (Version::Mpeg1, Layer::L2) => BITRATES_V1_L2[bitrate_idx],
+            (Version::Mpeg1, Layer::L3) => BITRATES_V1_L3[bitrate_idx],
+            (_, Layer::L1) => BITRATES_V2_L1[bitrate_idx],
+            (_, _) => BITRATES_V2_L23[bitrate_idx],
+        };
+
+        let base_rate = match (bytes[2] >> 2) & 0b11 {
+            0 => 44100,
+            1 => 48000,
+            2 => 32000,
+            _ => return None,
+        };
+        let sample_rate = match version {
+            Version::Mpeg1 => base_rate,
+            Version::Mpeg2 => base_rate / 2,
+            Version::Mpeg25 => base_rate / 4,
+        };
+
+        Some(Self {
+            version,
+            layer,
+            bitrate,
+            sample_rate,
+            padding: (bytes[2] >> 1) & 1 == 1,
+            is_mono: bytes[3] >> 6 == 0b11,
+        })
+    }
+
+    fn samples_per_frame(&self) -> u64 {
+        match (self.layer, self.version) {
+            (Layer::L1, _) => 384,
+            (Layer::L2, _) | (Layer::L3, Version::Mpeg1) => 1152,
+            (Layer::L3, _) => 576,
+        }
+    }
+
+    fn frame_len(&self) -> usize {
+        let padding = self.padding as u32;
+        let len = match self.layer {
+            Layer::L1 => (12 * self.bitrate * 1000 / self.sample_rate + padding) * 4,
+            Layer::L3 if self.version != Version::Mpeg1 => {
+                72 * self.bitrate * 1000 / self.sample_rate + padding
+            }
+            _ => 144 * self.bitrate * 1000 / self.sample_rate + padding,
+        };
+        len as usize
+    }
+
+    /// Whether this could be the frame following `previous` in the same
+    /// stream.
+    fn continues(&self, previous: &FrameHeader) -> bool {
+        self.version == previous.version
+            && self.layer == previous.layer
+            && self.sample_rate == previous.sample_rate
+    }
+
+    /// Offset of the Xing/Info header from the start of the frame.
+    fn xing_offset(&self) -> usize {
+        4 + match (self.version, self.is_mono) {
+            (Version::Mpeg1, false) => 32,
+            (Version::Mpeg1, true) | (_, false) => 17,
+            (_, true) => 9,
+        }
+    }
+}
+
+/// Calculate the duration of an MP3 file, preferring encoder headers over a
+/// full frame scan.
+pub fn duration(path: &Path) -> Result<(Duration, DurationSource)> {
+    match header_duration(path) {
+        Ok(Some(found)) => Ok(found),
+        Ok(None) | Err(_) => {
+            let duration = mp3_duration::from_path(path)?;
+            Ok((duration, DurationSource::FrameScan))
+        }
+    }
+}
+
+fn header_duration(path: &Path) -> Result<Option<(Duration, DurationSource)>> {
+    read_header_duration(&mut BufReader::new(File::open(path)?))
+}
+
+fn read_header_duration<R: Read + Seek>(
+    reader: &mut R,
+) -> Result<Option<(Duration, DurationSource)>> {
+    let start = digest::skip_id3v2(reader, 0)?;
+
+    let (offset, header) = find_first_frame(reader, start)?;
+    let mut frame = vec![0; header.frame_len()];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut frame)?;
+
+    let spf = header.samples_per_frame();
+    let to_duration =
+        |samples: u64| Duration::from_secs_f64(samples as f64 / header.sample_rate as f64);
+
+    if let Some(xing) = parse_xing(&frame, &header) {
+        let total = xing.frames * spf;
+        return Ok(Some(match xing.gapless {
+            Some((delay, padding)) => (
+                to_duration(total.saturating_sub(delay + padding)),
+                DurationSource::Lame,
+            ),
+            None => (to_duration(total), DurationSource::Xing),
+        }));
+    }
+
+    if let Some(frames) = parse_vbri(&frame) {
+        return Ok(Some((to_duration(frames * spf), DurationSource::Vbri)));
+    }
+
+    Ok(None)
+}
+
+fn find_first_frame<R: Read + Seek>(reader: &mut R, start: u64) -> Result<(u64, FrameHeader)> {
+    let mut buffer = Vec::with_capacity(MAX_SYNC_SEARCH);
+    reader.seek(SeekFrom::Start(start))?;
+    reader
+        .take(MAX_SYNC_SEARCH as u64)
+        .read_to_end(&mut buffer)?;
+
+    let header_at = |idx: usize| {
+        let bytes = buffer.get(idx..idx + 4)?;
+        FrameHeader::parse([bytes[0], bytes[1], bytes[2], bytes[3]])
+    };
+    let is_end = |idx: usize| idx == buffer.len() && buffer.len() < MAX_SYNC_SEARCH;
+
+    for idx in 0..buffer.len() {
+        let Some(header) = header_at(idx) else {
+            continue;
+        };
+        // Sync patterns turn up in padding and junk too, so only trust one
+        // followed by a matching frame, or by the end of a one-frame file.
+        let next = idx + header.frame_len();
+        let confirmed = header_at(next).is_some_and(|it| it.continues(&header)) || is_end(next);
+        if confirmed {
+            return Ok((start + idx as u64, header));
+        }
+    }
+
+    bail!("No MPEG frame found")
+}
+
+struct XingHeader {
+    frames: u64,
+    /// Encoder delay and padding in samples, from the LAME tag.
+    gapless: Option<(u64, u64)>,
+}
+
+fn parse_xing(frame: &[u8], header: &FrameHeader) -> Option<XingHeader> {
+    let mut pos = header.xing_offset();
+    let tag = frame.get(pos..pos + 4)?;
+    if tag != b"Xing" && tag != b"Info" {
+        return None;
+    }
+    let flags = read_u32(frame, pos + 4)?;
+    pos += 8;
+
+    // Without a frame count the header says nothing about the duration.
+    if flags & 0x1 == 0 {
+        return None;
+    }
+    let frames = read_u32(frame, pos)? as u64;
+    pos += 4;
+    if flags & 0x2 != 0 {
+        pos += 4;
+    }
+    if flags & 0x4 != 0 {
+        pos += 100;
+    }
+    if flags & 0x8 != 0 {
+        pos += 4;
+    }
+
+    let gapless = frame
+        .get(pos..pos + 4)
+        .filter(|encoder| *encoder == b"LAME" || *encoder == b"Lavf" || *encoder == b"Lavc")
+        .and_then(|_| frame.get(pos + LAME_DELAY_OFFSET..pos + LAME_DELAY_OFFSET + 3))
+        .map(|bytes| {
+            let delay = ((bytes[0] as u64) << 4) | (bytes[1] as u64 >> 4);
+            let padding = ((bytes[1] as u64 & 0x0f) << 8) | bytes[2] as u64;
+            (delay, padding)
+        });
+
+    Some(XingHeader { frames, gapless })
+}
+
+fn parse_vbri(frame: &[u8]) -> Option<u64> {
+    if frame.get(VBRI_OFFSET..VBRI_OFFSET + 4)? != b"VBRI" {
+        return None;
+    }
+    // Tag, version, delay and quality precede the byte and frame counts.
+    read_u32(frame, VBRI_OFFSET + 14).map(u64::from)
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Option<u32> {
+    let bytes = bytes.get(pos..pos + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// MPEG-1 layer III, 128 kbps, 44.1 kHz, joint stereo, no padding.
+    const HEADER: [u8; 4] = [0xff, 0xfb, 0x90, 0x64];
+    /// 144 * 128000 / 44100
+    const FRAME_LEN: usize = 417;
+    const SAMPLES_PER_FRAME: u64 = 1152;
+    const XING_OFFSET: usize = 36;
+
+    fn frame() -> Vec<u8> {
+        let mut frame = HEADER.to_vec();
+        frame.resize(FRAME_LEN, 0);
+        frame
+    }
+
+    fn frames(count: usize) -> Vec<u8> {
+        frame().repeat(count)
+    }
+
+    /// A first frame with a Xing header counting `frames` frames, with all
+    /// optional fields, and a LAME tag when `gapless` is given.
+    fn xing_frame(frames: u32, gapless: Option<(u16, u16)>) -> Vec<u8> {
+        let mut frame = frame();
+        let mut pos = XING_OFFSET;
+        let mut write = |bytes: &[u8]| {
+            frame[pos..pos + bytes.len()].copy_from_slice(bytes);
+            pos += bytes.len();
+        };
+        write(b"Xing");
+        write(&0xfu32.to_be_bytes());
+        write(&frames.to_be_bytes());
+        write(&1000u32.to_be_bytes());
+        write(&[0; 100]);
+        write(&50u32.to_be_bytes());
+        if let Some((delay, padding)) = gapless {
+            write(b"LAME3.100");
+            write(&[0; LAME_DELAY_OFFSET - 9]);
+            write(&[
+                (delay >> 4) as u8,
+                ((delay & 0x0f) << 4) as u8 | (padding >> 8) as u8,
+                padding as u8,
+            ]);
+        }
+        frame
+    }
+
+    fn header() -> FrameHeader {
+        FrameHeader::parse(HEADER).unwrap()
+    }
+
+    fn seconds(samples: u64) -> Duration {
+        Duration::from_secs_f64(samples as f64 / 44100.0)
+    }
+
+    #[test]
+    fn frame_header_is_parsed() {
+        let header = header();
+        assert_eq!(header.sample_rate, 44100);
+        assert_eq!(header.bitrate, 128);
+        assert_eq!(header.frame_len(), FRAME_LEN);
+        assert_eq!(header.xing_offset(), XING_OFFSET);
+    }
+
+    #[test]
+    fn xing_header_gives_frame_count() {
+        let xing = parse_xing(&xing_frame(1000, None), &header()).unwrap();
+        assert_eq!(xing.frames, 1000);
+        assert_eq!(xing.gapless, None);
+    }
+
+    #[test]
+    fn lame_tag_gives_delay_and_padding() {
+        let xing = parse_xing(&xing_frame(1000, Some((576, 1234))), &header()).unwrap();
+        assert_eq!(xing.frames, 1000);
+        assert_eq!(xing.gapless, Some((576, 1234)));
+    }
+
+    #[test]
+    fn xing_header_without_frame_count_is_ignored() {
+        let mut without_count = xing_frame(1000, None);
+        without_count[XING_OFFSET + 4..XING_OFFSET + 8].copy_from_slice(&0x2u32.to_be_bytes());
+        assert!(parse_xing(&without_count, &header()).is_none());
+        assert!(parse_xing(&frame(), &header()).is_none());
+    }
+
+    #[test]
+    fn vbri_header_gives_frame_count() {
+        let mut vbri = frame();
+        vbri[VBRI_OFFSET..VBRI_OFFSET + 4].copy_from_slice(b"VBRI");
+        vbri[VBRI_OFFSET + 14..VBRI_OFFSET + 18].copy_from_slice(&750u32.to_be_bytes());
+        assert_eq!(parse_vbri(&vbri), Some(750));
+        assert_eq!(parse_vbri(&frames(1)), None);
+    }
+
+    #[test]
+    fn header_duration_subtracts_gapless_samples() {
+        let bytes = [xing_frame(100, Some((576, 1000))), frames(99)].concat();
+        let found = read_header_duration(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(
+            found,
+            Some((
+                seconds(100 * SAMPLES_PER_FRAME - 1576),
+                DurationSource::Lame
+            ))
+        );
+    }
+
+    #[test]
+    fn false_sync_before_the_first_frame_is_skipped() {
+        // A lone header in junk, followed by nothing that looks like a frame
+        // where its successor would be.
+        let mut junk = HEADER.to_vec();
+        junk.resize(100, 0x55);
+        let bytes = [junk, xing_frame(100, None), frames(99)].concat();
+
+        let (offset, _) = find_first_frame(&mut Cursor::new(&bytes), 0).unwrap();
+        assert_eq!(offset, 100);
+        let found = read_header_duration(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(
+            found,
+            Some((seconds(100 * SAMPLES_PER_FRAME), DurationSource::Xing))
+        );
+    }
+
+    #[test]
+    fn lone_frame_at_end_of_file_is_accepted() {
+        let (offset, _) = find_first_frame(&mut Cursor::new(frames(1)), 0).unwrap();
+        assert_eq!(offset, 0);
+    }
+
+    #[test]
+    fn files_without_headers_are_scanned() {
+        let path = std::env::temp_dir().join(format!("tuners-mp3-{}.mp3", std::process::id()));
+        std::fs::write(&path, frames(100)).unwrap();
+
+        let header = header_duration(&path);
+        let scanned = duration(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert!(matches!(header, Ok(None)));
+        let (duration, source) = scanned.unwrap();
+        assert_eq!(source, DurationSource::FrameScan);
+        assert!(duration.abs_diff(seconds(100 * SAMPLES_PER_FRAME)) < Duration::from_millis(30));
+    }
+}
@@ -0,0 +1,292 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Minimal reader for Opus streams in an Ogg container.
+//!
+//! Only the two header packets (`OpusHead` and `OpusTags`) are decoded;
+//! everything after them is audio.
+
+use std::{
+    io::{Read, Seek, SeekFrom},
+    ops::Range,
+    time::Duration,
+};
+
+use color_eyre::eyre::{Result, bail};
+
+const PAGE_HEADER_LEN: usize = 27;
+
+/// How far from the end of the file to look for the last page.
+const LAST_PAGE_SEARCH: u64 = 64 * 1024;
+
+/// Opus always uses a 48 kHz granule clock, whatever the input rate was.
+const OPUS_GRANULE_RATE: f64 = 48_000.0;
+
+/// Number of header packets preceding the audio in an Opus stream.
+const OPUS_HEADER_PACKETS: usize = 2;
+
+pub struct OpusInfo {
+    /// Vorbis-style comments as (key, value) pairs, in file order.
+    pub comments: Vec<(String, String)>,
+    pub duration: Option<Duration>,
+}
+
+struct Page {
+    granule_position: i64,
+    /// Lacing values for the segments of the page body.
+    segments: Vec<u8>,
+    body: Range<u64>,
+}
+
+impl Page {
+    fn read<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<Self> {
+        let mut header = [0; PAGE_HEADER_LEN];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut header)?;
+        if &header[..4] != b"OggS" {
+            bail!("Missing Ogg page marker at offset {}", offset);
+        }
+
+        let granule_position = i64::from_le_bytes(header[6..14].try_into()?);
+        let mut segments = vec![0; header[26] as usize];
+        reader.read_exact(&mut segments)?;
+
+        let body_start = offset + PAGE_HEADER_LEN as u64 + segments.len() as u64;
+        let body_len: u64 = segments.iter().map(|&it| it as u64).sum();
+
+        Ok(Self {
+            granule_position,
+            segments,
+            body: body_start..body_start + body_len,
+        })
+    }
+}
+
+/// Read the Opus header packets and compute the stream duration.
+pub fn read_opus<R: Read + Seek>(reader: &mut R) -> Result<OpusInfo> {
+    let (packets, _) = header_packets(reader)?;
+    let [head, tags] = &packets[..] else {
+        bail!("Incomplete Opus headers");
+    };
+
+    if !head.starts_with(b"OpusHead") || head.len() < 12 {
+        bail!("Not an Opus stream");
+    }
+    let pre_skip = u16::from_le_bytes([head[10], head[11]]) as i64;
+
+    let duration = last_granule_position(reader)?.map(|granule| {
+        Duration::from_secs_f64((granule - pre_skip).max(0) as f64 / OPUS_GRANULE_RATE)
+    });
+
+    Ok(OpusInfo {
+        comments: parse_comments(tags)?,
+        duration,
+    })
+}
+
+/// Byte ranges of the audio data, excluding page headers and header
+/// packets, so that retagging a file doesn't change its digest.
+pub fn audio_ranges<R: Read + Seek>(reader: &mut R) -> Result<Vec<Range<u64>>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let (_, mut offset) = header_packets(reader)?;
+    let mut ranges = Vec::new();
+
+    while offset + (PAGE_HEADER_LEN as u64) <= len {
+        let page = Page::read(reader, offset)?;
+        offset = page.body.end;
+        ranges.push(page.body);
+    }
+
+    Ok(ranges)
+}
+
+/// Read the header packets, returning them along with the offset of the
+/// first audio page.
+fn header_packets<R: Read + Seek>(reader: &mut R) -> Result<(Vec<Vec<u8>>, u64)> {
+    let mut packets = Vec::new();
+    let mut current = Vec::new();
+    let mut offset = 0;
+
+    while packets.len() < OPUS_HEADER_PACKETS {
+        let page = Page::read(reader, offset)?;
+        reader.seek(SeekFrom::Start(page.body.start))?;
+        for &lacing in &page.segments {
+            let mut segment = vec![0; lacing as usize];
+            reader.read_exact(&mut segment)?;
+            current.extend(segment);
+            // A segment shorter than 255 bytes ends the packet.
+            if lacing < 255 {
+                packets.push(std::mem::take(&mut current));
+            }
+        }
+        offset = page.body.end;
+    }
+
+    Ok((packets, offset))
+}
+
+fn last_granule_position<R: Read + Seek>(reader: &mut R) -> Result<Option<i64>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let start = len.saturating_sub(LAST_PAGE_SEARCH);
+    let mut tail = Vec::new();
+    reader.seek(SeekFrom::Start(start))?;
+    reader.take(LAST_PAGE_SEARCH).read_to_end(&mut tail)?;
+
+    let Some(pos) = tail.windows(4).rposition(|it| it == b"OggS") else {
+        return Ok(None);
+    };
+    let page = Page::read(reader, start + pos as u64)?;
+    Ok((page.granule_position >= 0).then_some(page.granule_position))
+}
+
+fn parse_comments(packet: &[u8]) -> Result<Vec<(String, String)>> {
+    let Some(mut rest) = packet.strip_prefix(b"OpusTags") else {
+        bail!("Missing OpusTags header");
+    };
+
+    let vendor_len = read_len(&mut rest)?;
+    rest = rest.get(vendor_len..).unwrap_or_default();
+    let count = read_len(&mut rest)?;
+
+    let mut comments = Vec::new();
+    for _ in 0..count {
+        let len = read_len(&mut rest)?;
+        let Some(comment) = rest.get(..len) else {
+            bail!("Truncated Opus comment");
+        };
+        rest = &rest[len..];
+        let comment = String::from_utf8_lossy(comment);
+        if let Some((key, value)) = comment.split_once('=') {
+            comments.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    Ok(comments)
+}
+
+fn read_len(bytes: &mut &[u8]) -> Result<usize> {
+    let Some((len, rest)) = bytes.split_first_chunk::<4>() else {
+        bail!("Truncated Opus comment header");
+    };
+    *bytes = rest;
+    Ok(u32::from_le_bytes(*len) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const PRE_SKIP: u16 = 312;
+
+    fn page(granule_position: i64, lacing: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS\0\0".to_vec();
+        page.extend(granule_position.to_le_bytes());
+        // Serial number, sequence number and CRC, none of which are checked.
+        page.extend([0; 12]);
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(lacing);
+        page.extend_from_slice(body);
+        page
+    }
+
+    fn opus_head() -> Vec<u8> {
+        let mut head = b"OpusHead\x01\x02".to_vec();
+        head.extend(PRE_SKIP.to_le_bytes());
+        head.extend(48_000u32.to_le_bytes());
+        head.extend([0; 3]);
+        head
+    }
+
+    fn opus_tags(comments: &[&str]) -> Vec<u8> {
+        let mut tags = b"OpusTags".to_vec();
+        let vendor = b"tuners tests";
+        tags.extend((vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor);
+        tags.extend((comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            tags.extend((comment.len() as u32).to_le_bytes());
+            tags.extend_from_slice(comment.as_bytes());
+        }
+        tags
+    }
+
+    /// A stream whose tags packet is longer than a segment and is split
+    /// across two pages, followed by two audio pages.
+    fn stream(comments: &[&str]) -> Vec<u8> {
+        let head = opus_head();
+        let tags = opus_tags(comments);
+        assert!(tags.len() > 255 && tags.len() < 510);
+        [
+            page(0, &[head.len() as u8], &head),
+            page(0, &[255], &tags[..255]),
+            page(0, &[(tags.len() - 255) as u8], &tags[255..]),
+            page(48_000 + PRE_SKIP as i64, &[5], b"audio"),
+            page(96_000 + PRE_SKIP as i64, &[6], b"audio2"),
+        ]
+        .concat()
+    }
+
+    fn long_comments() -> Vec<String> {
+        vec![
+            String::from("TITLE=Opening Test"),
+            format!("COMMENT={}", "x".repeat(300)),
+            String::from("R128_TRACK_GAIN=-512"),
+        ]
+    }
+
+    #[test]
+    fn packets_are_joined_across_pages() {
+        let comments = long_comments();
+        let comments: Vec<&str> = comments.iter().map(String::as_str).collect();
+        let info = read_opus(&mut Cursor::new(stream(&comments))).unwrap();
+
+        let keys: Vec<&str> = info.comments.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["TITLE", "COMMENT", "R128_TRACK_GAIN"]);
+        assert_eq!(info.comments[0].1, "Opening Test");
+        assert_eq!(info.comments[1].1.len(), 300);
+    }
+
+    #[test]
+    fn duration_comes_from_the_last_page_less_pre_skip() {
+        let info = read_opus(&mut Cursor::new(stream(&["TITLE=x".repeat(40).as_str()]))).unwrap();
+        assert_eq!(info.duration, Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn packet_filling_whole_segments_ends_with_an_empty_one() {
+        let head = opus_head();
+        let mut tags = opus_tags(&["TITLE=x"]);
+        tags.resize(255, 0);
+        let bytes = [
+            page(0, &[head.len() as u8], &head),
+            page(0, &[255, 0], &tags),
+        ]
+        .concat();
+
+        let (packets, offset) = header_packets(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(packets, [head, tags]);
+        assert_eq!(offset, bytes.len() as u64);
+    }
+
+    #[test]
+    fn audio_ranges_skip_headers() {
+        let bytes = stream(&["TITLE=x".repeat(40).as_str()]);
+        let ranges = audio_ranges(&mut Cursor::new(&bytes)).unwrap();
+
+        let audio: Vec<&[u8]> = ranges
+            .into_iter()
+            .map(|it| &bytes[it.start as usize..it.end as usize])
+            .collect();
+        assert_eq!(audio, [&b"audio"[..], &b"audio2"[..]]);
+    }
+
+    #[test]
+    fn other_streams_are_rejected() {
+        let bytes = [page(0, &[8], b"OpusHeax"), page(0, &[8], b"OpusTags")].concat();
+        assert!(read_opus(&mut Cursor::new(bytes)).is_err());
+        assert!(read_opus(&mut Cursor::new(b"RIFF".repeat(10))).is_err());
+    }
+}
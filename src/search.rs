@@ -0,0 +1,602 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Searching a metadata source for the releases a cluster might be.
+
+use std::sync::{Arc, mpsc::Sender};
+
+use color_eyre::eyre::Result;
+use tokio::task::JoinSet;
+
+use crate::{
+    models::AlbumCluster,
+    source::{
+        AlbumCandidate, CandidatePage, MetadataSource, OnRetry,
+        error::RetryAttempt,
+        normalize::{DURATION_WINDOW, normalize_title},
+    },
+};
+
+/// Title similarity above which a candidate with the right number of
+/// tracks ends the search.
+const GOOD_TITLE_SIMILARITY: f64 = 0.9;
+
+/// How a candidate release was found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchStrategy {
+    Barcode,
+    /// Catalog number, and label when the tracks have one.
+    CatalogNumber,
+    /// Album artist and title as tagged, with the track count and format.
+    Exact,
+    /// Album artist and title without punctuation, guest artists or
+    /// edition suffixes.
+    Normalized,
+    /// Normalized title alone, for when the artist is tagged differently.
+    AlbumOnly,
+    /// Approximate artist name alone, for typos.
+    ArtistFuzzy,
+    /// ISRC of a single track.
+    Isrc,
+    /// Artist and title of a single track, with its duration.
+    Recording,
+    /// Artist and title of a single track without punctuation, guest
+    /// artists or edition suffixes, and without the duration.
+    RecordingNormalized,
+    /// Entered by the user as an ID or URL.
+    Manual,
+}
+
+impl SearchStrategy {
+    /// The strategies tried for a cluster, from most to least precise.
+    const CHAIN: [SearchStrategy; 6] = [
+        SearchStrategy::Barcode,
+        SearchStrategy::CatalogNumber,
+        SearchStrategy::Exact,
+        SearchStrategy::Normalized,
+        SearchStrategy::AlbumOnly,
+        SearchStrategy::ArtistFuzzy,
+    ];
+
+    /// The strategies tried for a single track, which search recordings
+    /// instead of releases.
+    const SINGLETON_CHAIN: [SearchStrategy; 3] = [
+        SearchStrategy::Isrc,
+        SearchStrategy::Recording,
+        SearchStrategy::RecordingNormalized,
+    ];
+
+    /// Whether any result of the strategy identifies the release.
+    fn is_identifier(&self) -> bool {
+        matches!(
+            self,
+            SearchStrategy::Barcode | SearchStrategy::CatalogNumber | SearchStrategy::Isrc
+        )
+    }
+
+    /// Whether the strategy searches recordings rather than releases.
+    pub fn is_recording(&self) -> bool {
+        matches!(
+            self,
+            SearchStrategy::Isrc | SearchStrategy::Recording | SearchStrategy::RecordingNormalized
+        )
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SearchStrategy::Barcode => "barcode",
+            SearchStrategy::CatalogNumber => "cat#",
+            SearchStrategy::Exact => "exact",
+            SearchStrategy::Normalized => "normalized",
+            SearchStrategy::AlbumOnly => "album only",
+            SearchStrategy::ArtistFuzzy => "fuzzy artist",
+            SearchStrategy::Isrc => "ISRC",
+            SearchStrategy::Recording => "recording",
+            SearchStrategy::RecordingNormalized => "normalized recording",
+            SearchStrategy::Manual => "manual",
+        }
+    }
+}
+
+pub enum SearchMessage {
+    Searching(AlbumCluster, String),
+    /// A transient failure is being retried; the string is a status line.
+    Retrying(AlbumCluster, String),
+    /// Candidates for a cluster, with where to continue each query that has
+    /// more results.
+    Results(AlbumCluster, Vec<AlbumCandidate>, Vec<SearchCursor>),
+    NoResults(AlbumCluster),
+    Error(AlbumCluster, String),
+    /// Releases found from a reference entered by the user, to be added to
+    /// the cluster's candidates.
+    Candidates(AlbumCluster, Vec<AlbumCandidate>),
+    /// The full release for a search result, with its tracklist.
+    ReleaseDetails(Box<AlbumCandidate>),
+    /// Looking up the release with the given ID failed.
+    ReleaseDetailsError(String, String),
+    /// The next page of a query, to be appended to the cluster's candidates,
+    /// and where to continue if there are still more.
+    MoreResults(AlbumCluster, Vec<AlbumCandidate>, Option<SearchCursor>),
+}
+
+/// Where to continue a search query that has more results than were
+/// fetched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchCursor {
+    /// Name of the source the query was sent to.
+    pub source: &'static str,
+    pub strategy: SearchStrategy,
+    pub query: String,
+    /// Position of the next result to fetch.
+    pub offset: usize,
+}
+
+/// Search every source for releases matching a cluster.
+///
+/// The sources are searched at the same time, since each has its own rate
+/// limit, and their candidates are listed in the order the sources are
+/// given.
+pub async fn search_for_cluster(
+    sources: &[Arc<dyn MetadataSource>],
+    tx: Sender<SearchMessage>,
+    cluster: AlbumCluster,
+) -> Result<Vec<AlbumCandidate>> {
+    let mut tasks = JoinSet::new();
+    for (idx, source) in sources.iter().enumerate() {
+        let source = Arc::clone(source);
+        let tx = tx.clone();
+        let cluster = cluster.clone();
+        tasks.spawn(async move { (idx, search_source(source.as_ref(), &tx, &cluster).await) });
+    }
+    let mut found = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        // A search can only fail to join by panicking, which is reported
+        // on its own.
+        if let Ok(it) = result {
+            found.push(it);
+        }
+    }
+    found.sort_by_key(|(idx, _)| *idx);
+
+    let mut candidates = Vec::new();
+    let mut cursors = Vec::new();
+    let mut error = None;
+    for (_, found) in found {
+        candidates.extend(found.candidates);
+        cursors.extend(found.cursors);
+        error = error.or(found.error);
+    }
+
+    match error {
+        Some(e) if candidates.is_empty() => {
+            let msg = format!("Search failed: {}", e);
+            let _ = tx.send(SearchMessage::Error(cluster, msg));
+            Err(e)
+        }
+        _ => {
+            if candidates.is_empty() {
+                let _ = tx.send(SearchMessage::NoResults(cluster));
+            } else {
+                let message = SearchMessage::Results(cluster, candidates.clone(), cursors);
+                let _ = tx.send(message);
+            }
+            Ok(candidates)
+        }
+    }
+}
+
+/// What one source turned up for a cluster.
+struct SourceResults {
+    candidates: Vec<AlbumCandidate>,
+    cursors: Vec<SearchCursor>,
+    /// The last failure, if any query failed.
+    error: Option<color_eyre::Report>,
+}
+
+/// Search one source for releases matching a cluster.
+///
+/// Barcodes and catalog numbers identify a release far more reliably than
+/// its title, so those are tried first, followed by increasingly loose text
+/// queries.  Results are merged, and the chain stops as soon as a good
+/// candidate turns up.
+///
+/// A cluster with a single track is searched as a recording instead, by
+/// ISRC and then by artist, title and duration, and each release the
+/// recording appears on becomes a candidate.
+async fn search_source(
+    source: &dyn MetadataSource,
+    tx: &Sender<SearchMessage>,
+    cluster: &AlbumCluster,
+) -> SourceResults {
+    let mut candidates: Vec<AlbumCandidate> = Vec::new();
+    let mut queries: Vec<String> = Vec::new();
+    let mut cursors: Vec<SearchCursor> = Vec::new();
+    let mut error = None;
+
+    let chain: &[SearchStrategy] = if cluster.is_singleton() {
+        &SearchStrategy::SINGLETON_CHAIN
+    } else {
+        &SearchStrategy::CHAIN
+    };
+
+    for &strategy in chain {
+        if candidates.iter().any(|it| is_good_candidate(cluster, it)) {
+            break;
+        }
+        let Some(query) = source.query(strategy, cluster) else {
+            continue;
+        };
+        // Normalizing may not have changed anything.
+        if queries.contains(&query) {
+            continue;
+        }
+
+        let status = format!(
+            "Searching {} for {} ({})...",
+            source.name(),
+            describe(cluster),
+            strategy.label()
+        );
+        let _ = tx.send(SearchMessage::Searching(cluster.clone(), status.clone()));
+
+        let on_retry = |retry: &RetryAttempt| {
+            let status = retry.describe(&status);
+            let _ = tx.send(SearchMessage::Retrying(cluster.clone(), status));
+        };
+
+        let cursor = SearchCursor {
+            source: source.name(),
+            strategy,
+            query: query.clone(),
+            offset: 0,
+        };
+        match search_page(source, &cursor, &on_retry).await {
+            Ok((found, next)) => {
+                for candidate in found {
+                    if !candidates.iter().any(|it| it.is_same(&candidate)) {
+                        candidates.push(candidate);
+                    }
+                }
+                cursors.extend(next);
+            }
+            // Keep going, since another strategy may still find something.
+            Err(e) => error = Some(e),
+        }
+        queries.push(query);
+    }
+
+    SourceResults {
+        candidates,
+        cursors,
+        error,
+    }
+}
+
+/// Fetch one page of results for a query, and where the next page starts.
+async fn search_page(
+    source: &dyn MetadataSource,
+    cursor: &SearchCursor,
+    on_retry: OnRetry<'_>,
+) -> Result<(Vec<AlbumCandidate>, Option<SearchCursor>)> {
+    let CandidatePage {
+        candidates,
+        next_offset,
+    } = source
+        .search(cursor.strategy, &cursor.query, cursor.offset, on_retry)
+        .await?;
+    let next = next_offset.map(|offset| SearchCursor {
+        offset,
+        ..cursor.clone()
+    });
+    Ok((candidates, next))
+}
+
+/// Fetch the next page of a query that was cut short.
+pub async fn load_more(
+    source: &dyn MetadataSource,
+    tx: Sender<SearchMessage>,
+    cluster: AlbumCluster,
+    cursor: SearchCursor,
+) {
+    let status = format!(
+        "Loading more {} matches ({})...",
+        source.name(),
+        cursor.strategy.label()
+    );
+    let on_retry = |retry: &RetryAttempt| {
+        let status = retry.describe(&status);
+        let _ = tx.send(SearchMessage::Retrying(cluster.clone(), status));
+    };
+    let message = match search_page(source, &cursor, &on_retry).await {
+        Ok((candidates, next)) => SearchMessage::MoreResults(cluster, candidates, next),
+        Err(e) => SearchMessage::Error(cluster, format!("Loading more failed: {}", e)),
+    };
+    let _ = tx.send(message);
+}
+
+/// Artist and album for a cluster, or artist and title for a single track.
+fn describe(cluster: &AlbumCluster) -> String {
+    match cluster.tracks.first() {
+        Some(track) if cluster.is_singleton() => format!(
+            "{} - {}",
+            cluster.album_artist,
+            track.title.as_deref().unwrap_or("Unknown")
+        ),
+        _ => format!("{} - {}", cluster.album_artist, cluster.album),
+    }
+}
+
+/// Whether a candidate is convincing enough to stop searching: found by
+/// barcode, catalog number or ISRC, or with a near-identical title and the
+/// same number of tracks, or for a single track the same duration.
+fn is_good_candidate(cluster: &AlbumCluster, candidate: &AlbumCandidate) -> bool {
+    if candidate.strategy.is_identifier() {
+        return true;
+    }
+
+    if let (Some(matched), Some(track)) = (&candidate.matched_track, cluster.tracks.first()) {
+        let similarity = strsim::normalized_levenshtein(
+            &normalize_title(&matched.title).to_lowercase(),
+            &normalize_title(track.title.as_deref().unwrap_or_default()).to_lowercase(),
+        );
+        let same_length = match (matched.length, track.duration) {
+            (Some(length), Some(duration)) => length.abs_diff(duration) <= DURATION_WINDOW,
+            _ => false,
+        };
+        return same_length && similarity >= GOOD_TITLE_SIMILARITY;
+    }
+
+    let similarity = strsim::normalized_levenshtein(
+        &normalize_title(&candidate.title).to_lowercase(),
+        &normalize_title(&cluster.album).to_lowercase(),
+    );
+    candidate.track_count() as usize == cluster.track_count() && similarity >= GOOD_TITLE_SIMILARITY
+}
+
+/// Look up the full release for a search result.
+pub async fn lookup_release_details(
+    source: &dyn MetadataSource,
+    tx: Sender<SearchMessage>,
+    id: String,
+) {
+    let message = match source.lookup(&id).await {
+        Ok(candidate) => SearchMessage::ReleaseDetails(Box::new(candidate)),
+        Err(e) => SearchMessage::ReleaseDetailsError(id, format!("Lookup failed: {}", e)),
+    };
+    let _ = tx.send(message);
+}
+
+/// Fetch the releases for an ID or URL entered for a cluster, from the
+/// first source that recognizes it.
+pub async fn lookup_reference(
+    sources: &[Arc<dyn MetadataSource>],
+    tx: Sender<SearchMessage>,
+    cluster: AlbumCluster,
+    input: String,
+) {
+    let Some(lookup) = sources
+        .iter()
+        .find_map(|source| source.lookup_reference(&input))
+    else {
+        let names: Vec<&str> = sources.iter().map(|it| it.name()).collect();
+        let msg = format!("Not a {} release ID or URL", names.join(" or "));
+        let _ = tx.send(SearchMessage::Error(cluster, msg));
+        return;
+    };
+    let message = match lookup.await {
+        Ok(candidates) => SearchMessage::Candidates(cluster, candidates),
+        Err(e) => SearchMessage::Error(cluster, format!("Lookup failed: {}", e)),
+    };
+    let _ = tx.send(message);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::mpsc::{self, Receiver},
+    };
+
+    use super::*;
+    use crate::{
+        codecs::AudioCodec,
+        models::{AudioFile, ReleaseIdentifiers},
+        musicbrainz::{
+            client::{Client, ClientConfig},
+            fake::{FakeServer, Fixture},
+        },
+    };
+
+    const RELEASE_ID: &str = "b84ee12a-09ef-421b-82de-0441a926375b";
+
+    fn identifiers() -> ReleaseIdentifiers {
+        ReleaseIdentifiers {
+            barcode: Some(String::from("5012345678900")),
+            ..ReleaseIdentifiers::default()
+        }
+    }
+
+    fn track(number: u32, title: &str) -> AudioFile {
+        AudioFile {
+            path: PathBuf::from(format!("/music/Fixture Songs/{:02} {}.flac", number, title)),
+            codec: AudioCodec::Flac,
+            title: Some(title.to_string()),
+            artist: Some(String::from("The Testers")),
+            album_artist: Some(String::from("The Testers")),
+            album: Some(String::from("Fixture Songs")),
+            track_number: Some(number),
+            total_tracks: Some(3),
+            disc_number: None,
+            total_discs: None,
+            genre: None,
+            year: Some(2019),
+            duration: None,
+            duration_source: None,
+            legacy_encoding: None,
+            replay_gain: Default::default(),
+            identifiers: identifiers(),
+            isrc: None,
+            musicbrainz: Default::default(),
+            audio_digest: None,
+        }
+    }
+
+    fn cluster() -> AlbumCluster {
+        AlbumCluster {
+            album: String::from("Fixture Songs"),
+            album_artist: String::from("The Testers"),
+            tracks: vec![
+                track(1, "Opening Test"),
+                track(2, "Assertion Blues"),
+                track(3, "Teardown"),
+            ],
+            base_path: PathBuf::from("/music/Fixture Songs"),
+            total_discs: 1,
+            encoding_suggestion: None,
+            identifiers: identifiers(),
+        }
+    }
+
+    fn sources(config: &ClientConfig) -> Vec<Arc<dyn MetadataSource>> {
+        vec![Arc::new(Client::new(config).unwrap())]
+    }
+
+    /// The messages the app received, in order, as text.
+    fn received(rx: Receiver<SearchMessage>) -> Vec<String> {
+        rx.try_iter()
+            .map(|message| match message {
+                SearchMessage::Searching(_, status) => status,
+                SearchMessage::Retrying(_, status) => status,
+                SearchMessage::Results(_, candidates, cursors) => {
+                    let found: Vec<String> = candidates
+                        .iter()
+                        .map(|it| format!("{} ({})", it.id, it.strategy.label()))
+                        .collect();
+                    format!(
+                        "Results: {}; {} to continue",
+                        found.join(", "),
+                        cursors.len()
+                    )
+                }
+                SearchMessage::NoResults(_) => String::from("No results"),
+                SearchMessage::Error(_, msg) => format!("Error: {}", msg),
+                SearchMessage::ReleaseDetails(release) => format!(
+                    "Details: {} with {} tracks",
+                    release.title,
+                    release.track_count()
+                ),
+                SearchMessage::ReleaseDetailsError(id, msg) => {
+                    format!("Details error: {}: {}", id, msg)
+                }
+                SearchMessage::Candidates(..) | SearchMessage::MoreResults(..) => {
+                    panic!("unexpected message")
+                }
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn barcode_match_ends_the_search() {
+        let server = FakeServer::start(Fixture::load("release_search")).await;
+        let (tx, rx) = mpsc::channel();
+
+        let candidates = search_for_cluster(&sources(&server.config()), tx, cluster())
+            .await
+            .unwrap();
+
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(
+            received(rx),
+            [
+                String::from("Searching MusicBrainz for The Testers - Fixture Songs (barcode)..."),
+                format!("Results: {} (barcode); 1 to continue", RELEASE_ID),
+            ]
+        );
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn every_strategy_is_tried_before_giving_up() {
+        let server = FakeServer::start(Fixture::load("empty")).await;
+        let (tx, rx) = mpsc::channel();
+
+        let candidates = search_for_cluster(&sources(&server.config()), tx, cluster())
+            .await
+            .unwrap();
+
+        assert!(candidates.is_empty());
+        assert_eq!(
+            received(rx),
+            [
+                "Searching MusicBrainz for The Testers - Fixture Songs (barcode)...",
+                "Searching MusicBrainz for The Testers - Fixture Songs (exact)...",
+                "Searching MusicBrainz for The Testers - Fixture Songs (normalized)...",
+                "Searching MusicBrainz for The Testers - Fixture Songs (album only)...",
+                "Searching MusicBrainz for The Testers - Fixture Songs (fuzzy artist)...",
+                "No results",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limited_search_is_retried() {
+        let server = FakeServer::start(Fixture::load("rate_limited")).await;
+        let (tx, rx) = mpsc::channel();
+
+        search_for_cluster(&sources(&server.config()), tx, cluster())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            received(rx),
+            [
+                String::from("Searching MusicBrainz for The Testers - Fixture Songs (barcode)..."),
+                String::from(
+                    "Searching MusicBrainz for The Testers - Fixture Songs (barcode)... \
+                     rate limited, retrying (2/5) in 0.0s"
+                ),
+                format!("Results: {} (barcode); 0 to continue", RELEASE_ID),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unavailable_server_is_reported() {
+        let server = FakeServer::start(Fixture::load("unavailable")).await;
+        let mut config = server.config();
+        config.max_attempts = 2;
+        let (tx, rx) = mpsc::channel();
+
+        let result = search_for_cluster(&sources(&config), tx, cluster()).await;
+
+        assert!(result.is_err());
+        let received = received(rx);
+        // Each strategy is searched and retried once before moving on.
+        assert_eq!(received.len(), 5 * 2 + 1);
+        assert_eq!(
+            received.last().unwrap(),
+            "Error: Search failed: MusicBrainz rate limited: HTTP 503 Service Unavailable"
+        );
+        assert_eq!(server.requests().len(), 5 * 2);
+    }
+
+    #[tokio::test]
+    async fn release_details_are_looked_up() {
+        let server = FakeServer::start(Fixture::load("release_lookup")).await;
+        let source = Client::new(&server.config()).unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        lookup_release_details(&source, tx.clone(), RELEASE_ID.to_string()).await;
+        lookup_release_details(&source, tx, String::from("missing")).await;
+
+        assert_eq!(
+            received(rx),
+            [
+                "Details: Fixture Songs with 3 tracks",
+                "Details error: missing: Lookup failed: \
+                 MusicBrainz request rejected: HTTP 404 Not Found",
+            ]
+        );
+    }
+}
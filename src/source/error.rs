@@ -0,0 +1,151 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Failures talking to a metadata provider, described the same way for
+//! every provider so that retries and the interface can treat them alike.
+
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+/// Broad categories of failure when talking to a provider.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    /// The server asked us to slow down (HTTP 429, or 503 with
+    /// `Retry-After`).
+    RateLimited,
+    /// The server failed to handle the request (HTTP 5xx).
+    Server,
+    /// The request never got a complete response.
+    Network,
+    /// The response wasn't what we expected.
+    Parse,
+    /// The server rejected the request itself (HTTP 4xx).  Retrying won't
+    /// help.
+    Rejected,
+}
+
+impl ErrorKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ErrorKind::RateLimited => "rate limited",
+            ErrorKind::Server => "server error",
+            ErrorKind::Network => "network error",
+            ErrorKind::Parse => "unexpected response",
+            ErrorKind::Rejected => "request rejected",
+        }
+    }
+
+    /// Whether the whole server wants a break, rather than just the one
+    /// request having failed.
+    pub fn is_server_wide(&self) -> bool {
+        matches!(self, ErrorKind::RateLimited | ErrorKind::Server)
+    }
+
+    /// Whether the same request might succeed if sent again later.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ErrorKind::RateLimited | ErrorKind::Server | ErrorKind::Network
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    /// Name of the service that failed, e.g. "MusicBrainz".
+    pub service: &'static str,
+    pub kind: ErrorKind,
+    pub message: String,
+    /// How long the server asked us to wait, from the `Retry-After` header.
+    pub retry_after: Option<Duration>,
+}
+
+impl ApiError {
+    pub fn new(service: &'static str, kind: ErrorKind, message: impl fmt::Display) -> Self {
+        Self {
+            service,
+            kind,
+            message: message.to_string(),
+            retry_after: None,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {}",
+            self.service,
+            self.kind.label(),
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// A failed attempt that is about to be retried.
+#[derive(Debug, Clone)]
+pub struct RetryAttempt {
+    /// The attempt about to be made, starting at 2.
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay: Duration,
+    pub error: ApiError,
+}
+
+impl RetryAttempt {
+    /// Describe the retry of whatever `status` says is going on.  The delay
+    /// is rounded up to a tenth of a second, so a short wait doesn't read as
+    /// none.
+    pub fn describe(&self, status: &str) -> String {
+        let tenths = self.delay.as_millis().div_ceil(100);
+        format!(
+            "{} {}, retrying ({}/{}) in {}.{}s",
+            status,
+            self.error.kind.label(),
+            self.attempt,
+            self.max_attempts,
+            tenths / 10,
+            tenths % 10
+        )
+    }
+}
+
+/// Read a `Retry-After` header, which is either a number of seconds or an
+/// HTTP date.  A date in the past means no wait at all.
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let at = SystemTime::UNIX_EPOCH + Duration::from_secs(date.timestamp().max(0) as u64);
+    Some(at.duration_since(now).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_is_read_as_seconds_or_a_date() {
+        // Wed, 21 Oct 2015 07:28:00 GMT
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_445_412_480);
+        let parse = |value| parse_retry_after(value, now);
+
+        assert_eq!(parse("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse(" 0 "), Some(Duration::ZERO));
+        assert_eq!(
+            parse("Wed, 21 Oct 2015 07:28:30 GMT"),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(parse("Wed, 21 Oct 2015 07:00:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse("soon"), None);
+        assert_eq!(parse("-5"), None);
+    }
+}
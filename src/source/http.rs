@@ -0,0 +1,264 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Talking to a provider's web API: retrying with backoff, telling failures
+//! apart by status, and the pieces of a request every client shares.
+
+use std::{
+    future::Future,
+    time::{Duration, SystemTime},
+};
+
+use reqwest::{Response, header::RETRY_AFTER};
+use tokio::time::sleep;
+
+use crate::{
+    musicbrainz::limiter::RateLimiter,
+    source::{
+        OnRetry,
+        error::{ApiError, ErrorKind, RetryAttempt, parse_retry_after},
+    },
+};
+
+/// Number of attempts made for a request before giving up.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry, doubled for every further attempt.
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// Sent with every request so the server operators can get in touch.
+pub const DEFAULT_USER_AGENT: &str = concat!(
+    "tuners/",
+    env!("CARGO_PKG_VERSION"),
+    " ( chmont@protonmail.com )"
+);
+
+/// One page of search results.
+#[derive(Debug, Clone)]
+pub struct SearchPage<T> {
+    pub entities: Vec<T>,
+    /// Position of the first result on the page among all results.
+    pub offset: usize,
+    /// Number of results across all pages.
+    pub total: usize,
+}
+
+impl<T> SearchPage<T> {
+    /// Offset of the following page, if there is one.
+    pub fn next_offset(&self) -> Option<usize> {
+        let next = self.offset + self.entities.len();
+        (!self.entities.is_empty() && next < self.total).then_some(next)
+    }
+}
+
+/// How a client paces and retries its requests.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Shared by every client whose requests count towards the same limit.
+    pub limiter: RateLimiter,
+    /// Shortest wait before a retry, whatever the server says, so that
+    /// retries never come faster than the rate limit.
+    pub min_delay: Duration,
+    pub max_attempts: u32,
+}
+
+impl RetryPolicy {
+    /// Make a request, retrying transient failures with exponential backoff.
+    ///
+    /// Every attempt waits its turn with the rate limiter.  `on_retry` is
+    /// called before waiting for each retry.
+    pub async fn run<T, F>(
+        &self,
+        on_retry: OnRetry<'_>,
+        mut request: impl FnMut() -> F,
+    ) -> Result<T, ApiError>
+    where
+        F: Future<Output = Result<T, ApiError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            self.limiter.acquire().await;
+            let error = match request().await {
+                Ok(result) => return Ok(result),
+                Err(error) => error,
+            };
+
+            if !error.kind.is_transient() || attempt >= self.max_attempts {
+                return Err(error);
+            }
+
+            attempt += 1;
+            let delay = error
+                .retry_after
+                .unwrap_or_else(|| backoff(attempt - 1))
+                .max(self.min_delay);
+            let is_server_wide = error.kind.is_server_wide();
+            on_retry(&RetryAttempt {
+                attempt,
+                max_attempts: self.max_attempts,
+                delay,
+                error,
+            });
+            // Other tasks sharing the limiter hold off too, rather than
+            // keep asking a server that just told us to back off.
+            if is_server_wide {
+                self.limiter.pause(delay);
+            } else {
+                sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// The error for a response that isn't a success, or `None` for one that
+/// is.
+pub fn status_error(service: &'static str, response: &Response) -> Option<ApiError> {
+    let status = response.status();
+    if status.is_success() {
+        return None;
+    }
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|it| it.to_str().ok())
+        .and_then(|it| parse_retry_after(it, SystemTime::now()));
+    let kind = match status.as_u16() {
+        429 => ErrorKind::RateLimited,
+        // MusicBrainz asks clients over its limit to slow down this way.
+        503 if retry_after.is_some() => ErrorKind::RateLimited,
+        500.. => ErrorKind::Server,
+        _ => ErrorKind::Rejected,
+    };
+    Some(ApiError {
+        retry_after,
+        ..ApiError::new(service, kind, format!("HTTP {}", status))
+    })
+}
+
+/// Read the body of a successful response as JSON.
+pub async fn read_json(
+    service: &'static str,
+    response: Response,
+) -> Result<serde_json::Value, ApiError> {
+    response.json().await.map_err(|e| {
+        let kind = if e.is_decode() {
+            ErrorKind::Parse
+        } else {
+            ErrorKind::Network
+        };
+        ApiError::new(service, kind, e)
+    })
+}
+
+/// Percent-encode a query parameter value, so that characters like `&` in
+/// a title don't end the parameter early.
+pub fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Exponential backoff with jitter, so that clients that failed together
+/// don't all retry at the same moment.
+pub fn backoff(retry: u32) -> Duration {
+    let delay = BACKOFF_BASE
+        .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+        .min(BACKOFF_MAX);
+    delay.mul_f64(0.5 + fastrand::f64() * 0.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    fn policy(min_delay: Duration) -> RetryPolicy {
+        RetryPolicy {
+            limiter: RateLimiter::new(Duration::ZERO, 1),
+            min_delay,
+            max_attempts: 3,
+        }
+    }
+
+    fn failure(kind: ErrorKind) -> ApiError {
+        ApiError {
+            retry_after: Some(Duration::ZERO),
+            ..ApiError::new("Test", kind, "failed")
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_wait_at_least_the_rate_limit() {
+        let attempts = Mutex::new(0);
+        let retries = Mutex::new(Vec::new());
+        let result = policy(Duration::from_millis(20))
+            .run(
+                &|retry| retries.lock().unwrap().push((retry.attempt, retry.delay)),
+                || {
+                    let attempt = {
+                        let mut attempts = attempts.lock().unwrap();
+                        *attempts += 1;
+                        *attempts
+                    };
+                    async move {
+                        match attempt {
+                            1 => Err(failure(ErrorKind::Network)),
+                            _ => Ok(attempt),
+                        }
+                    }
+                },
+            )
+            .await;
+
+        assert_eq!(result.unwrap(), 2);
+        // The server said not to wait, but the floor still applies.
+        assert_eq!(*retries.lock().unwrap(), [(2, Duration::from_millis(20))]);
+    }
+
+    #[tokio::test]
+    async fn lasting_failures_are_not_retried() {
+        let attempts = Mutex::new(0);
+        let result: Result<(), ApiError> = policy(Duration::ZERO)
+            .run(&|_| {}, || {
+                *attempts.lock().unwrap() += 1;
+                async { Err(failure(ErrorKind::Rejected)) }
+            })
+            .await;
+
+        assert_eq!(result.unwrap_err().kind, ErrorKind::Rejected);
+        assert_eq!(*attempts.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn transient_failures_give_up_after_the_last_attempt() {
+        let attempts = Mutex::new(0);
+        let result: Result<(), ApiError> = policy(Duration::ZERO)
+            .run(&|_| {}, || {
+                *attempts.lock().unwrap() += 1;
+                async { Err(failure(ErrorKind::Server)) }
+            })
+            .await;
+
+        assert_eq!(result.unwrap_err().kind, ErrorKind::Server);
+        assert_eq!(*attempts.lock().unwrap(), 3);
+    }
+
+    #[test]
+    fn query_values_are_percent_encoded() {
+        assert_eq!(
+            percent_encode("Simon & Garfunkel"),
+            "Simon%20%26%20Garfunkel"
+        );
+        assert_eq!(percent_encode("a-b_c.d~"), "a-b_c.d~");
+    }
+}
@@ -0,0 +1,160 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Provider-neutral view of the releases a cluster might be tagged with.
+//!
+//! Searching, matching and the interface only deal with [`AlbumCandidate`]s,
+//! so a metadata provider only has to implement [`MetadataSource`] to be
+//! usable.
+
+pub mod error;
+pub mod http;
+pub mod normalize;
+
+use std::{future::Future, pin::Pin, time::Duration};
+
+use color_eyre::eyre::Result;
+
+use crate::{models::AlbumCluster, search::SearchStrategy, source::error::RetryAttempt};
+
+/// A boxed future, so that sources can be used as trait objects.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Called before waiting to retry a failed request.
+pub type OnRetry<'a> = &'a (dyn Fn(&RetryAttempt) + Sync);
+
+/// A provider of release metadata.
+pub trait MetadataSource: Send + Sync {
+    /// Name of the provider, as shown in the interface.
+    fn name(&self) -> &'static str;
+
+    /// Build the query for a search strategy, or `None` when the source
+    /// doesn't support the strategy or the cluster lacks what it needs.
+    fn query(&self, strategy: SearchStrategy, cluster: &AlbumCluster) -> Option<String>;
+
+    /// Run a query built by [`MetadataSource::query`], starting at the given
+    /// result.
+    fn search<'a>(
+        &'a self,
+        strategy: SearchStrategy,
+        query: &'a str,
+        offset: usize,
+        on_retry: OnRetry<'a>,
+    ) -> BoxFuture<'a, Result<CandidatePage>>;
+
+    /// Look up a release with its full tracklist.
+    fn lookup<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<AlbumCandidate>>;
+
+    /// Look up the releases for an ID or URL entered by the user, or `None`
+    /// if the input isn't a reference to this source.
+    fn lookup_reference<'a>(
+        &'a self,
+        input: &str,
+    ) -> Option<BoxFuture<'a, Result<Vec<AlbumCandidate>>>>;
+}
+
+/// Candidates from one page of a query.
+#[derive(Debug, Clone)]
+pub struct CandidatePage {
+    pub candidates: Vec<AlbumCandidate>,
+    /// Where the next page starts, if there is one.
+    pub next_offset: Option<usize>,
+}
+
+/// A release that might match a cluster.
+#[derive(Debug, Clone)]
+pub struct AlbumCandidate {
+    /// Name of the source the release came from.
+    pub source: &'static str,
+    /// The source's ID for the release.
+    pub id: String,
+    pub title: String,
+    /// The artist credit, joined into one name.
+    pub artist: String,
+    pub date: Option<String>,
+    pub country: Option<String>,
+    pub barcode: Option<String>,
+    pub labels: Vec<LabelCredit>,
+    /// The work this release is an edition of.
+    pub group: Option<ReleaseGroup>,
+    /// Media in order.  Search results may leave out the tracks.
+    pub media: Vec<MediumCandidate>,
+    /// Genres and tags of the release and the work it belongs to.  Tags
+    /// can be anything, so they need sorting out with a
+    /// [`GenreTree`](crate::genres::GenreTree).
+    pub genres: Vec<GenreTag>,
+    /// The track matched for a single file, when searching recordings.
+    pub matched_track: Option<TrackCandidate>,
+    pub strategy: SearchStrategy,
+}
+
+impl AlbumCandidate {
+    pub fn track_count(&self) -> u32 {
+        self.media.iter().map(|it| it.track_count).sum()
+    }
+
+    /// The year of release, from the start of the date.
+    pub fn year(&self) -> Option<u32> {
+        self.date.as_deref()?.get(..4)?.parse().ok()
+    }
+
+    /// Whether the tracks of the release are known, rather than only how
+    /// many there are.
+    pub fn has_tracklist(&self) -> bool {
+        self.media.iter().any(|it| !it.tracks.is_empty())
+    }
+
+    /// Whether two candidates are the same release, matched through the
+    /// same track if any.
+    pub fn is_same(&self, other: &AlbumCandidate) -> bool {
+        self.source == other.source
+            && self.id == other.id
+            && self.matched_track.as_ref().map(|it| &it.id)
+                == other.matched_track.as_ref().map(|it| &it.id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LabelCredit {
+    pub name: Option<String>,
+    pub catalog_number: Option<String>,
+}
+
+/// A genre or tag, with how many people applied it.
+#[derive(Debug, Clone)]
+pub struct GenreTag {
+    pub name: String,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReleaseGroup {
+    pub id: String,
+    pub title: String,
+    /// Album, single, EP and so on.
+    pub kind: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MediumCandidate {
+    pub position: Option<u32>,
+    pub title: Option<String>,
+    /// CD, vinyl, digital media and so on.
+    pub format: Option<String>,
+    pub track_count: u32,
+    pub tracks: Vec<TrackCandidate>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackCandidate {
+    /// The source's ID for the recording, or for the track when the source
+    /// has no separate recordings.
+    pub id: String,
+    pub title: String,
+    /// The track's own artist credit, when it has one.
+    pub artist: Option<String>,
+    /// Position as printed on the medium, e.g. "A1".
+    pub number: String,
+    pub length: Option<Duration>,
+}
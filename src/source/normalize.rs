@@ -0,0 +1,149 @@
+// SPDX-FileCopyrightText: (C) 2025 chris montgomery <chmont@protonmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Normalization of tagged names, shared by every source's queries and by
+//! matching, so that a "feat." credit, an edition suffix or stray
+//! punctuation compares the same everywhere.
+
+use std::time::Duration;
+
+/// How far a recording's length may be from the track's duration.
+pub const DURATION_WINDOW: Duration = Duration::from_secs(5);
+
+/// Words in a bracketed or dashed suffix that mark it as describing the
+/// edition rather than being part of the title.
+const EDITION_WORDS: &[&str] = &[
+    "anniversary",
+    "bonus",
+    "deluxe",
+    "edition",
+    "expanded",
+    "limited",
+    "reissue",
+    "remaster",
+    "remastered",
+    "special",
+    "version",
+];
+
+/// Separators that introduce guest artists in an artist credit.
+const FEATURING: &[&str] = &[" feat. ", " feat ", " ft. ", " featuring "];
+
+/// Strip edition suffixes and punctuation from a release title, e.g.
+/// "Abbey Road (Super Deluxe Edition)" becomes "Abbey Road".
+pub fn normalize_title(title: &str) -> String {
+    let mut title = title.trim().to_string();
+    loop {
+        let stripped = strip_edition_suffix(&title);
+        if stripped == title {
+            break;
+        }
+        title = stripped;
+    }
+    strip_punctuation(&title)
+}
+
+/// Drop guest artists and punctuation from an artist credit.
+pub fn normalize_artist(artist: &str) -> String {
+    let end = artist
+        .char_indices()
+        .map(|(idx, _)| idx)
+        .find(|&idx| {
+            FEATURING
+                .iter()
+                .any(|it| starts_with_ignore_case(&artist[idx..], it))
+        })
+        .unwrap_or(artist.len());
+    strip_punctuation(&artist[..end])
+}
+
+/// Whether `text` starts with the lowercase `prefix`, ignoring the case of
+/// `text`.  Offsets stay those of `text`, even where lowercasing would
+/// change its length.
+fn starts_with_ignore_case(text: &str, prefix: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+    prefix.chars().all(|c| text.next() == Some(c))
+}
+
+fn strip_edition_suffix(title: &str) -> String {
+    let title = title.trim_end();
+    let suffix_start = match title.chars().last() {
+        Some(')') => title.rfind('('),
+        Some(']') => title.rfind('['),
+        _ => title.rfind(" - ").map(|it| it + 1),
+    };
+
+    match suffix_start {
+        Some(start) if start > 0 && is_edition_suffix(&title[start..]) => title[..start]
+            .trim_end()
+            .trim_end_matches('-')
+            .trim_end()
+            .to_string(),
+        _ => title.to_string(),
+    }
+}
+
+fn is_edition_suffix(suffix: &str) -> bool {
+    suffix
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| EDITION_WORDS.contains(&word.to_lowercase().as_str()))
+}
+
+fn strip_punctuation(text: &str) -> String {
+    let text: String = text
+        .chars()
+        // Apostrophes join words rather than separating them.
+        .filter(|c| !matches!(c, '\'' | '’'))
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    clean_whitespace(&text)
+}
+
+/// Collapse runs of whitespace so that trivially different tags share a
+/// cache entry.
+pub fn clean_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guest_artists_are_dropped() {
+        assert_eq!(normalize_artist("Jay-Z feat. Beyoncé"), "Jay Z");
+        assert_eq!(normalize_artist("Daft Punk FT. Pharrell"), "Daft Punk");
+        assert_eq!(normalize_artist("Artist Featuring Guest"), "Artist");
+        assert_eq!(normalize_artist("Featherweight"), "Featherweight");
+        assert_eq!(normalize_artist("Nobody Else"), "Nobody Else");
+    }
+
+    #[test]
+    fn guest_artists_are_dropped_after_non_ascii_credits() {
+        // "İ" lowercases to two characters, three bytes instead of two.
+        assert_eq!(
+            normalize_artist("İBRAHİM TATLISES Feat. Guest"),
+            "İBRAHİM TATLISES"
+        );
+        assert_eq!(normalize_artist("Ǆemo FEAT Ñandú"), "Ǆemo");
+        assert_eq!(normalize_artist("Björk feat. Thom Yorke"), "Björk");
+        assert_eq!(normalize_artist("Sigur Rós"), "Sigur Rós");
+    }
+
+    #[test]
+    fn edition_suffixes_are_stripped() {
+        assert_eq!(
+            normalize_title("Abbey Road (Super Deluxe Edition)"),
+            "Abbey Road"
+        );
+        assert_eq!(
+            normalize_title("OK Computer - Remastered [Bonus Tracks]"),
+            "OK Computer"
+        );
+        assert_eq!(
+            normalize_title("(What's the Story) Morning Glory?"),
+            "Whats the Story Morning Glory"
+        );
+    }
+}
@@ -2,10 +2,23 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
-use crate::{app::AppState, codecs::codec_name, credit::UNKNOWN_ARTIST_NAME, models::AlbumCluster};
-use musicbrainz_rs::entity::release::Release;
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    app::{
+        AppState, ArtDownload, ArtListing, ArtPicker, CandidateRow, FrontCover, ReleaseLookup,
+        SyncEntry, SyncStatus, candidate_rows,
+    },
+    codecs::codec_name,
+    duplicates::DuplicateReport,
+    matching::{MatchScore, TrackMapping, TrackPosition},
+    models::{AlbumCluster, DurationSource},
+    musicbrainz::coverart::CachedImage,
+    paths::escape_path,
+    source::{AlbumCandidate, LabelCredit, MediumCandidate},
+};
 use ratatui::{prelude::*, widgets::*};
-use ratatui_macros::vertical;
+use ratatui_macros::{horizontal, vertical};
 
 const SECONDS_PER_MINUTE: u32 = 60;
 const SECONDS_PER_HOUR: u32 = 3600;
@@ -23,12 +36,47 @@ pub fn render(frame: &mut Frame, state: &AppState) {
         AppState::AutoTagging {
             cluster,
             results,
+            scores,
             selected_idx,
-        } => render_autotagging(frame, cluster, results, *selected_idx),
+            grouped,
+            expanded,
+            cursors,
+            details,
+            prompt,
+            status,
+            art,
+            front_cover,
+            ..
+        } => render_autotagging(
+            frame,
+            cluster,
+            results,
+            scores,
+            &candidate_rows(results, scores, *grouped, expanded),
+            !cursors.is_empty(),
+            *selected_idx,
+            details,
+            prompt.as_deref(),
+            status.as_deref(),
+            art.as_ref(),
+            front_cover.as_ref(),
+        ),
         AppState::ClusterList {
             clusters,
             selected_idx,
         } => render_clusters(frame, clusters, *selected_idx),
+        AppState::Duplicates {
+            clusters,
+            report,
+            selected_idx,
+            ..
+        } => render_duplicates(frame, clusters, report, *selected_idx),
+        AppState::Syncing {
+            clusters,
+            entries,
+            selected_idx,
+            ..
+        } => render_sync(frame, clusters, entries, *selected_idx),
         AppState::Error { message } => render_error(frame, message),
     }
 }
@@ -42,7 +90,7 @@ fn render_scanning(
 ) {
     let [header_area, main_area, footer_area] = vertical![==3, >=5, ==3].areas(frame.area());
 
-    let header = Paragraph::new(format!("Scanning: {}", path.display())).block(
+    let header = Paragraph::new(format!("Scanning: {}", escape_path(path))).block(
         Block::default()
             .borders(Borders::ALL)
             .title("Directory Scanner"),
@@ -77,53 +125,95 @@ fn render_scanning(
     frame.render_widget(footer, footer_area);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_autotagging(
     frame: &mut Frame,
     cluster: &AlbumCluster,
-    results: &[Release],
+    results: &[AlbumCandidate],
+    scores: &[MatchScore],
+    rows: &[CandidateRow],
+    has_more: bool,
     selected_idx: usize,
+    details: &HashMap<String, ReleaseLookup>,
+    prompt: Option<&str>,
+    status: Option<&str>,
+    art: Option<&ArtPicker>,
+    front_cover: Option<&FrontCover>,
 ) {
     let [header_area, main_area, footer_area] = vertical![==5, >=10, ==3].areas(frame.area());
 
-    let cluster_info = format!(
-        "Album Artist: {}\nAlbum: {}\nTracks: {}\nPath: {}",
-        cluster.album_artist,
-        cluster.album,
-        cluster.tracks.len(),
-        cluster.base_path.display()
-    );
+    let cluster_info = match cluster.tracks.first() {
+        Some(track) if cluster.is_singleton() => format!(
+            "Artist: {}\nTrack: {}\nAlbum: {}\nPath: {}",
+            cluster.album_artist,
+            track.title.as_deref().unwrap_or_default(),
+            cluster.album,
+            escape_path(&track.path)
+        ),
+        _ => format!(
+            "Album Artist: {}\nAlbum: {}\nTracks: {}\nPath: {}",
+            cluster.album_artist,
+            cluster.album,
+            cluster.tracks.len(),
+            escape_path(&cluster.base_path)
+        ),
+    };
+    let title = match &cluster.encoding_suggestion {
+        Some(suggestion) => format!(
+            "Current Cluster (as {}: {} - {} | e : Fix encoding and search again)",
+            suggestion.encoding.name(),
+            suggestion.album_artist,
+            suggestion.album
+        ),
+        None => String::from("Current Cluster"),
+    };
     let header = Paragraph::new(cluster_info)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .wrap(Wrap { trim: true });
+    frame.render_widget(header, header_area);
+
+    if results.is_empty() {
+        let no_results = Paragraph::new(
+            "No matches found\n\nPress [u] to enter a release MBID or URL, \
+             [M] for manual search or [s] to skip",
+        )
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .title("Current Cluster"),
+                .title("Search Results"),
         )
         .wrap(Wrap { trim: true });
-    frame.render_widget(header, header_area);
-
-    if results.is_empty() {
-        let no_results =
-            Paragraph::new("No matches found\n\nPress [m] for manual search or [s] to skip")
-                .block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .title("Search Results"),
-                )
-                .wrap(Wrap { trim: true });
         frame.render_widget(no_results, main_area);
     } else {
-        let items: Vec<ListItem> = results
+        let mut number = 0;
+        let items: Vec<ListItem> = rows
             .iter()
-            .enumerate()
-            .map(render_search_result)
+            .map(|row| match row {
+                CandidateRow::Group {
+                    editions, expanded, ..
+                } => {
+                    number += 1;
+                    render_release_group(number, results, scores, editions, *expanded)
+                }
+                CandidateRow::Release { idx, nested: false } => {
+                    number += 1;
+                    render_search_result((number - 1, &results[*idx]), scores.get(*idx))
+                }
+                CandidateRow::Release { idx, nested: true } => {
+                    render_edition(&results[*idx], scores.get(*idx))
+                }
+            })
             .collect();
 
         let list = List::new(items)
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .title(format!("Found {} matches", results.len())),
-            )
+            .block(Block::default().borders(Borders::ALL).title(format!(
+                "Found {} matches in {} release groups{}",
+                results.len(),
+                rows.iter()
+                    .filter(|it| !matches!(it, CandidateRow::Release { nested: true, .. }))
+                    .count(),
+                if has_more { " (n : Load more)" } else { "" }
+            )))
             .highlight_style(
                 Style::default()
                     .bg(Color::DarkGray)
@@ -131,43 +221,483 @@ fn render_autotagging(
             )
             .highlight_symbol(HIGHLIGHT_SYMBOL);
 
+        let [results_area, details_area] = horizontal![==1/2, ==1/2].areas(main_area);
         let mut state = ListState::default();
         state.select(Some(selected_idx));
-        frame.render_stateful_widget(list, main_area, &mut state);
+        frame.render_stateful_widget(list, results_area, &mut state);
+
+        if let Some(art) = art {
+            render_art_picker(frame, details_area, art, front_cover);
+        } else if let Some(idx) = rows.get(selected_idx).map(CandidateRow::candidate) {
+            let candidate = &results[idx];
+            let front_cover = front_cover.filter(|it| it.release_id == candidate.id);
+            render_release_details(
+                frame,
+                details_area,
+                cluster,
+                details.get(&candidate.id),
+                scores.get(idx),
+                front_cover,
+            );
+        }
     }
 
-    let help =
-        Paragraph::new("j/k or ↑/↓ : Navigate | [a]pply | [s]kip | [m]anual search | q : Quit")
-            .block(Block::default().borders(Borders::ALL).title("Actions"));
-    frame.render_widget(help, footer_area);
+    let footer = match (prompt, status) {
+        _ if let Some(art) = art => Paragraph::new(art.status.as_deref().unwrap_or(
+            "j/k or ↑/↓ : Navigate | <RET> : Use as front cover | <ESC> : Back to release",
+        ))
+        .block(Block::default().borders(Borders::ALL).title("Cover Art")),
+        (Some(text), _) => Paragraph::new(format!("{}█", text)).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Release ID or URL (<RET> : Look up | <ESC> : Cancel)"),
+        ),
+        (None, Some(status)) => {
+            Paragraph::new(status).block(Block::default().borders(Borders::ALL).title("Actions"))
+        }
+        (None, None) => Paragraph::new(
+            "j/k or ↑/↓ : Navigate | l/h : Expand/collapse group | g : Group editions | A : Apply | \
+             s : Skip | M : Manual search | u : ID/URL | c : Cover art | q : Quit",
+        )
+        .block(Block::default().borders(Borders::ALL).title("Actions")),
+    };
+    frame.render_widget(footer, footer_area);
+}
+
+fn render_release_details(
+    frame: &mut Frame,
+    area: Rect,
+    cluster: &AlbumCluster,
+    lookup: Option<&ReleaseLookup>,
+    score: Option<&MatchScore>,
+    front_cover: Option<&FrontCover>,
+) {
+    let block = Block::default().borders(Borders::ALL).title("Release");
+    let (release, genres) = match lookup {
+        Some(ReleaseLookup::Loaded { release, genres }) => (release, genres),
+        Some(ReleaseLookup::Failed(msg)) => {
+            let error = Paragraph::new(msg.as_str())
+                .style(Style::default().fg(Color::Red))
+                .block(block)
+                .wrap(Wrap { trim: true });
+            frame.render_widget(error, area);
+            return;
+        }
+        Some(ReleaseLookup::Pending) | None => {
+            frame.render_widget(Paragraph::new("Loading tracklist...").block(block), area);
+            return;
+        }
+    };
+
+    let mut lines = vec![
+        Line::styled(
+            format!("{} - {}", release.artist, release.title),
+            Style::default().add_modifier(Modifier::BOLD),
+        ),
+        Line::raw(format!(
+            "Released: {} ({})",
+            release_date(release),
+            release.country.as_deref().unwrap_or("??")
+        )),
+    ];
+
+    if let Some(score) = score {
+        let fields: Vec<String> = score
+            .fields
+            .iter()
+            .map(|(field, similarity)| format!("{} {:.0}%", field.name(), similarity * 100.0))
+            .collect();
+        lines.push(Line::raw(format!(
+            "Match: {} ({})",
+            score,
+            fields.join(", ")
+        )));
+    }
+
+    if let Some(group) = &release.group {
+        let kind = group
+            .kind
+            .as_ref()
+            .map_or(String::new(), |it| format!(" ({})", it));
+        lines.push(Line::raw(format!("Release group: {}{}", group.title, kind)));
+    }
+
+    let labels = format_labels(&release.labels);
+    if !labels.is_empty() {
+        lines.push(Line::raw(format!("Label: {}", labels)));
+    }
+
+    lines.push(Line::raw(format!(
+        "Format: {}",
+        format_media(&release.media)
+    )));
+    if !genres.is_empty() {
+        let genre = genres.join(", ");
+        // Show what it replaces, since the files' own genres are often junk.
+        let current = cluster_genre(cluster).filter(|it| *it != genre);
+        lines.push(Line::raw(format!(
+            "Genre: {}{}",
+            genre,
+            current.map_or(String::new(), |it| format!(" (tagged: {})", it))
+        )));
+    }
+    if let Some(cover) = front_cover {
+        lines.push(Line::raw(format!(
+            "Front cover: {} ({})",
+            describe_image(&cover.image),
+            escape_path(&cover.image.path)
+        )));
+    }
+    lines.push(Line::raw(format!("Source: {}", release.source)));
+
+    // Preview which file each track would be written to.
+    let unscored = TrackMapping::default();
+    let mapping = score.map_or(&unscored, |it| &it.mapping);
+    let is_partial = !mapping.missing_tracks.is_empty() || !mapping.unmatched_files.is_empty();
+    if release.has_tracklist() && !cluster.is_singleton() && is_partial {
+        lines.push(Line::styled(
+            format!(
+                "Tracks: {} without a file, {} files not on this release",
+                mapping.missing_tracks.len(),
+                mapping.unmatched_files.len()
+            ),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    for (medium_idx, medium) in release.media.iter().enumerate() {
+        lines.push(Line::raw(""));
+        if release.media.len() > 1 {
+            lines.push(Line::styled(
+                format!(
+                    "Disc {}{}",
+                    medium.position.unwrap_or_default(),
+                    medium
+                        .title
+                        .as_ref()
+                        .map_or(String::new(), |it| format!(": {}", it))
+                ),
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+        }
+        for (track_idx, track) in medium.tracks.iter().enumerate() {
+            let position = TrackPosition {
+                medium: medium_idx,
+                track: track_idx,
+            };
+            let mut spans = vec![Span::raw(format!(
+                "{:>3}. {}{} ({})",
+                track.number,
+                track.title,
+                track_artist_suffix(track.artist.as_deref(), &release.artist),
+                track.length.map_or(String::from("???"), |it| {
+                    seconds_to_timecode(it.as_secs() as u32)
+                }),
+            ))];
+            match mapping.file_for(position).map(|idx| &cluster.tracks[idx]) {
+                Some(file) => {
+                    // Show the title being replaced, if it changes.
+                    let was = file
+                        .title
+                        .as_ref()
+                        .filter(|it| **it != track.title)
+                        .map_or(String::new(), |it| format!(" (was: {})", it));
+                    spans.push(Span::styled(
+                        format!(" ← {}{}", file_name(&file.path), was),
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                }
+                // A single file only ever fills one track.
+                None if !cluster.is_singleton() => {
+                    spans.push(Span::styled(" (no file)", Style::default().fg(Color::Red)));
+                }
+                None => {}
+            }
+            lines.push(Line::from(spans));
+        }
+    }
+
+    if !mapping.unmatched_files.is_empty() && release.has_tracklist() {
+        lines.push(Line::raw(""));
+        lines.push(Line::styled(
+            format!("Not on this release ({}):", mapping.unmatched_files.len()),
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ));
+        for idx in &mapping.unmatched_files {
+            lines.push(Line::raw(format!(
+                "  {}",
+                file_name(&cluster.tracks[*idx].path)
+            )));
+        }
+    }
+
+    frame.render_widget(
+        Paragraph::new(lines)
+            .block(block)
+            .wrap(Wrap { trim: false }),
+        area,
+    );
+}
+
+/// The Cover Art Archive images of a release, with their sizes once
+/// downloaded.
+fn render_art_picker(
+    frame: &mut Frame,
+    area: Rect,
+    art: &ArtPicker,
+    front_cover: Option<&FrontCover>,
+) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("Cover Art: {}", art.release));
+    let entries = match &art.listing {
+        ArtListing::Pending => {
+            frame.render_widget(Paragraph::new("Listing images...").block(block), area);
+            return;
+        }
+        ArtListing::Failed(msg) => {
+            let error = Paragraph::new(msg.as_str())
+                .style(Style::default().fg(Color::Red))
+                .block(block)
+                .wrap(Wrap { trim: true });
+            frame.render_widget(error, area);
+            return;
+        }
+        ArtListing::Loaded(entries) if entries.is_empty() => {
+            frame.render_widget(
+                Paragraph::new("No front, back or booklet images").block(block),
+                area,
+            );
+            return;
+        }
+        ArtListing::Loaded(entries) => entries,
+    };
+
+    let items: Vec<ListItem> = entries
+        .iter()
+        .map(|entry| {
+            let picked = front_cover.is_some_and(|it| {
+                it.release_id == art.release_id && it.image_id == entry.image.id()
+            });
+            let (text, style) = match &entry.download {
+                ArtDownload::NotStarted => (String::new(), Style::default()),
+                ArtDownload::Pending => (String::from("downloading..."), Style::default()),
+                ArtDownload::Done(image) => (describe_image(image), Style::default()),
+                ArtDownload::Failed(msg) => (msg.clone(), Style::default().fg(Color::Red)),
+            };
+            let comment = if entry.image.comment.is_empty() {
+                String::new()
+            } else {
+                format!(" \"{}\"", entry.image.comment)
+            };
+            ListItem::new(Line::from(vec![
+                Span::raw(format!(
+                    "{} {:<8}",
+                    if picked { "✓" } else { " " },
+                    entry.image.kind()
+                )),
+                Span::styled(text, style),
+                Span::raw(comment),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(HIGHLIGHT_SYMBOL);
+    let mut state = ListState::default();
+    state.select(Some(art.selected_idx));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+/// Dimensions and file size, e.g. "1200×1200, 412 KB".
+fn describe_image(image: &CachedImage) -> String {
+    let size = if image.size >= 1024 * 1024 {
+        format!("{:.1} MB", image.size as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{} KB", image.size.div_ceil(1024))
+    };
+    match image.dimensions {
+        Some((width, height)) => format!("{}×{}, {}", width, height, size),
+        None => size,
+    }
+}
+
+/// The genre the cluster's files are tagged with, when they agree.
+fn cluster_genre(cluster: &AlbumCluster) -> Option<&str> {
+    let mut genres = cluster.tracks.iter().map(|it| it.genre.as_deref());
+    let first = genres.next()??;
+    genres.all(|it| it == Some(first)).then_some(first)
+}
+
+/// Labels with their catalog numbers, e.g. "Apple PCS 7088".
+fn format_labels(labels: &[LabelCredit]) -> String {
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|it| {
+            let label = it.name.as_deref().unwrap_or("[no label]");
+            match &it.catalog_number {
+                Some(catno) => format!("{} {}", label, catno),
+                None => label.to_string(),
+            }
+        })
+        .collect();
+    labels.join(", ")
+}
+
+/// Medium formats with repeats counted, e.g. "2×CD + DVD".
+fn format_media(media: &[MediumCandidate]) -> String {
+    let mut formats: Vec<(String, usize)> = Vec::new();
+    for medium in media {
+        let format = medium.format.clone().unwrap_or_else(|| String::from("?"));
+        match formats.iter_mut().find(|(it, _)| *it == format) {
+            Some((_, count)) => *count += 1,
+            None => formats.push((format, 1)),
+        }
+    }
+    let formats: Vec<String> = formats
+        .into_iter()
+        .map(|(format, count)| match count {
+            1 => format,
+            n => format!("{}×{}", n, format),
+        })
+        .collect();
+    formats.join(" + ")
+}
+
+/// The artist of a track following its title, when it differs from the
+/// album artist.  Only worth showing on compilations and guest appearances.
+fn track_artist_suffix(artist: Option<&str>, album_artist: &str) -> String {
+    artist
+        .filter(|it| *it != album_artist)
+        .map_or(String::new(), |it| format!(" — {}", it))
+}
+
+/// The last part of a path, for listing files whose directory is known.
+fn file_name(path: &Path) -> String {
+    escape_path(path.file_name().map_or(path, Path::new))
 }
 
-fn render_search_result(result: (usize, &Release)) -> ListItem<'_> {
-    let (idx, release) = result;
-    let artist = release
-        .artist_credit
-        .as_ref()
-        .and_then(|ac| ac.first())
-        .and_then(|a| Some(a.name.clone()))
-        .unwrap_or_else(|| UNKNOWN_ARTIST_NAME.to_string());
-    let date = release
-        .date
-        .as_ref()
-        .map(|it| it.0.as_str())
+/// A match score padded to line up in lists, e.g. "[ 87%]".
+fn format_score(score: Option<&MatchScore>) -> String {
+    score.map_or(String::from("[ --%]"), |it| {
+        format!("[{:>3}%]", it.percent())
+    })
+}
+
+fn release_date(release: &AlbumCandidate) -> &str {
+    release.date.as_deref().unwrap_or("????")
+}
+
+/// One line for a release group with several candidate editions.
+fn render_release_group<'a>(
+    number: usize,
+    results: &[AlbumCandidate],
+    scores: &[MatchScore],
+    editions: &[usize],
+    expanded: bool,
+) -> ListItem<'a> {
+    let release = &results[editions[0]];
+    let (title, kind) = match &release.group {
+        Some(group) => (
+            group.title.as_str(),
+            group
+                .kind
+                .as_ref()
+                .map_or(String::new(), |it| format!(" [{}]", it)),
+        ),
+        None => (release.title.as_str(), String::new()),
+    };
+    // The earliest edition dates the group.
+    let date = editions
+        .iter()
+        .filter_map(|it| results[*it].date.as_deref())
+        .min()
         .unwrap_or("????");
-    let track_count = release
-        .media
-        .as_ref()
-        .map(|media| media.iter().map(|it| it.track_count).sum::<u32>())
-        .unwrap_or(0);
+
+    ListItem::new(format!(
+        "{}. {} {} - {} ({}){} [{} editions] [via {} on {}] {}",
+        number,
+        format_score(scores.get(editions[0])),
+        release.artist,
+        title,
+        date,
+        kind,
+        editions.len(),
+        release.strategy.label(),
+        release.source,
+        if expanded { "▾" } else { "▸" }
+    ))
+}
+
+/// One line for an edition listed under its release group, with what tells
+/// the editions apart.
+fn render_edition<'a>(candidate: &'a AlbumCandidate, score: Option<&MatchScore>) -> ListItem<'a> {
+    let mut parts = vec![
+        candidate
+            .country
+            .clone()
+            .unwrap_or_else(|| String::from("??")),
+        release_date(candidate).to_string(),
+        format_media(&candidate.media),
+    ];
+    let labels = format_labels(&candidate.labels);
+    if !labels.is_empty() {
+        parts.push(labels);
+    }
+    if let Some(barcode) = &candidate.barcode {
+        parts.push(barcode.clone());
+    }
+
+    ListItem::new(format!(
+        "   └ {} {} [via {} on {}]",
+        format_score(score),
+        parts.join(" | "),
+        candidate.strategy.label(),
+        candidate.source
+    ))
+}
+
+fn render_search_result<'a>(
+    result: (usize, &'a AlbumCandidate),
+    score: Option<&MatchScore>,
+) -> ListItem<'a> {
+    let (idx, candidate) = result;
+    // A single track is matched to a recording, shown with the release it
+    // appears on.
+    let title = match &candidate.matched_track {
+        Some(track) => format!(
+            "{} ({}) on {}",
+            track.title,
+            track.length.map_or(String::from("???"), |it| {
+                seconds_to_timecode(it.as_secs() as u32)
+            }),
+            candidate.title
+        ),
+        None => candidate.title.clone(),
+    };
+    // Some sources only give track counts with the full release.
+    let track_count = match candidate.track_count() {
+        0 => String::from("?"),
+        n => n.to_string(),
+    };
     let text = format!(
-        "{}. {} - {} ({}) [Tracks: {}] [Country: {}]",
+        "{}. {} {} - {} ({}) [Tracks: {}] [Country: {}] [via {} on {}]",
         idx + 1,
-        artist,
-        release.title,
-        date,
+        format_score(score),
+        candidate.artist,
+        title,
+        release_date(candidate),
         track_count,
-        release.country.clone().unwrap_or(String::from("??"))
+        candidate.country.as_deref().unwrap_or("??"),
+        candidate.strategy.label(),
+        candidate.source
     );
 
     ListItem::new(text)
@@ -192,7 +722,7 @@ fn render_clusters(
         .iter()
         .map(|it| {
             ListItem::new(format!(
-                "{} - {} [{}] ({} tracks) [{}]",
+                "{} - {} [{}] ({} tracks) [RG: {}] [{}]{}",
                 it.album_artist,
                 it.album,
                 match it.codec() {
@@ -200,7 +730,11 @@ fn render_clusters(
                     None => String::from("Mutt"),
                 },
                 it.track_count(),
-                it.base_path.display()
+                it.replay_gain_status().label(),
+                escape_path(&it.base_path),
+                it.encoding_suggestion
+                    .as_ref()
+                    .map_or(String::new(), |it| format!(" [{}?]", it.encoding.name()))
             ))
         })
         .collect();
@@ -218,12 +752,22 @@ fn render_clusters(
     frame.render_stateful_widget(list, results_area, &mut state);
 
     if let Some(cluster) = clusters.get(selected_idx) {
-        let tracklist: Vec<Line> = cluster
-            .tracks
-            .iter()
-            .map(|it| {
+        let suggestion = cluster.encoding_suggestion.as_ref().map(|it| {
+            Line::styled(
+                format!(
+                    "Tags look like {}: {} - {} (e : Accept)",
+                    it.encoding.name(),
+                    it.album_artist,
+                    it.album
+                ),
+                Style::default().fg(Color::Yellow),
+            )
+        });
+        let tracklist: Vec<Line> = suggestion
+            .into_iter()
+            .chain(cluster.tracks.iter().map(|it| {
                 Line::raw(format!(
-                    "{}{}{} ({})\n",
+                    "{}{}{} ({}{})\n",
                     it.disc_number.map_or(String::new(), |n| {
                         if it.total_discs.unwrap_or(1) <= 1 {
                             return String::new();
@@ -237,19 +781,244 @@ fn render_clusters(
                         .map_or(String::from("???"), |n| seconds_to_timecode(
                             n.as_secs() as u32
                         )),
+                    // Container durations are exact; anything else is worth flagging.
+                    it.duration_source
+                        .filter(|source| *source != DurationSource::StreamInfo)
+                        .map_or(String::new(), |source| format!(" via {}", source.label())),
                 ))
-            })
+            }))
             .collect();
         frame.render_widget(Paragraph::new(tracklist), tracklist_area);
     } else {
         frame.render_widget(Clear, tracklist_area);
     }
 
-    let help = Paragraph::new("j/k : Navigate | <RET> : Lookup (TODO) | q : Quit")
+    let help = Paragraph::new(
+        "j/k : Navigate | <RET> : Lookup | S : Lookup tracks as singles | d : Duplicates | \
+         m : MusicBrainz sync | q : Quit",
+    )
+    .block(Block::default().borders(Borders::ALL));
+    frame.render_widget(help, footer_area);
+}
+
+fn render_duplicates(
+    frame: &mut Frame,
+    clusters: &[AlbumCluster],
+    report: &DuplicateReport,
+    selected_idx: usize,
+) {
+    let [header_area, main_area, footer_area] = vertical![==3, >=5, ==3].areas(frame.area());
+
+    let header = Paragraph::new(format!(
+        "{} groups of identical audio, {} near-duplicate albums",
+        report.identical.len(),
+        report.near.len()
+    ))
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Duplicates Report"),
+    );
+    frame.render_widget(header, header_area);
+
+    if report.is_empty() {
+        let empty =
+            Paragraph::new("No duplicates found").block(Block::default().borders(Borders::ALL));
+        frame.render_widget(empty, main_area);
+    } else {
+        let cluster_label = |idx: usize| {
+            clusters.get(idx).map_or(String::new(), |it| {
+                format!("{} - {}", it.album_artist, it.album)
+            })
+        };
+
+        let identical = report.identical.iter().map(|group| {
+            let mut lines = vec![Line::raw(format!(
+                "≡ Identical audio ({} files) {}",
+                group.files.len(),
+                &group.hash[..12]
+            ))];
+            lines.extend(group.files.iter().map(|(idx, path)| {
+                Line::raw(format!(
+                    "    {} : {}",
+                    cluster_label(*idx),
+                    escape_path(path)
+                ))
+            }));
+            ListItem::new(lines)
+        });
+
+        let near = report.near.iter().map(|dup| {
+            let (a, b) = dup.clusters;
+            let mut lines = vec![Line::raw(format!(
+                "≈ Near duplicate ({})",
+                dup.differences.join(", ")
+            ))];
+            for idx in [a, b] {
+                lines.push(Line::raw(format!(
+                    "    {} : {}",
+                    cluster_label(idx),
+                    clusters
+                        .get(idx)
+                        .map_or(String::new(), |it| escape_path(&it.base_path))
+                )));
+            }
+            ListItem::new(lines)
+        });
+
+        let list = List::new(identical.chain(near).collect::<Vec<_>>())
+            .block(Block::default().borders(Borders::ALL))
+            .highlight_style(
+                Style::default()
+                    .bg(Color::DarkGray)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol(HIGHLIGHT_SYMBOL);
+        let mut state = ListState::default();
+        state.select(Some(selected_idx));
+        frame.render_stateful_widget(list, main_area, &mut state);
+    }
+
+    let help = Paragraph::new("j/k : Navigate | Esc/h : Back to clusters | q : Quit")
+        .block(Block::default().borders(Borders::ALL));
+    frame.render_widget(help, footer_area);
+}
+
+fn render_sync(
+    frame: &mut Frame,
+    clusters: &[AlbumCluster],
+    entries: &[SyncEntry],
+    selected_idx: usize,
+) {
+    let [header_area, main_area, footer_area] = vertical![==3, >=5, ==3].areas(frame.area());
+
+    let pending = entries
+        .iter()
+        .filter(|it| matches!(it.status, SyncStatus::Pending))
+        .count();
+    let header = Paragraph::new(format!(
+        "{} clusters tagged from MusicBrainz{}",
+        entries.len(),
+        match pending {
+            0 => String::new(),
+            n => format!(", {} still being looked up", n),
+        }
+    ))
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("MusicBrainz Sync"),
+    );
+    frame.render_widget(header, header_area);
+
+    if entries.is_empty() {
+        let empty = Paragraph::new("No clusters carry a release MBID")
+            .block(Block::default().borders(Borders::ALL));
+        frame.render_widget(empty, main_area);
+    } else {
+        let items: Vec<ListItem> = entries
+            .iter()
+            .map(|entry| {
+                let cluster = &clusters[entry.cluster];
+                let status = match &entry.status {
+                    SyncStatus::Pending => String::from("looking up..."),
+                    SyncStatus::Failed(_) => String::from("⚠ failed"),
+                    SyncStatus::Done(diff) => match (diff.change_count(), diff.unmatched_count()) {
+                        (0, 0) => String::from("up to date"),
+                        (changes, 0) => format!("{} changes", changes),
+                        (changes, unmatched) => {
+                            format!("{} changes, {} unmatched files", changes, unmatched)
+                        }
+                    },
+                };
+                ListItem::new(format!(
+                    "{} - {} [{}]",
+                    cluster.album_artist, cluster.album, status
+                ))
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title("Clusters"))
+            .highlight_style(
+                Style::default()
+                    .bg(Color::DarkGray)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol(HIGHLIGHT_SYMBOL);
+
+        let [list_area, diff_area] = horizontal![==1/2, ==1/2].areas(main_area);
+        let mut state = ListState::default();
+        state.select(Some(selected_idx));
+        frame.render_stateful_widget(list, list_area, &mut state);
+
+        if let Some(entry) = entries.get(selected_idx) {
+            render_sync_diff(frame, diff_area, &entry.status);
+        }
+    }
+
+    let help = Paragraph::new("j/k : Navigate | Esc/h : Back to clusters | q : Quit")
         .block(Block::default().borders(Borders::ALL));
     frame.render_widget(help, footer_area);
 }
 
+/// What would change for each track of a cluster, field by field.
+fn render_sync_diff(frame: &mut Frame, area: Rect, status: &SyncStatus) {
+    let block = Block::default().borders(Borders::ALL).title("Changes");
+    let diff = match status {
+        SyncStatus::Done(diff) => diff,
+        SyncStatus::Failed(msg) => {
+            let error = Paragraph::new(msg.as_str())
+                .style(Style::default().fg(Color::Red))
+                .block(block)
+                .wrap(Wrap { trim: true });
+            frame.render_widget(error, area);
+            return;
+        }
+        SyncStatus::Pending => {
+            frame.render_widget(Paragraph::new("Looking up release...").block(block), area);
+            return;
+        }
+    };
+
+    let mut lines = vec![Line::styled(
+        diff.release.clone(),
+        Style::default().add_modifier(Modifier::BOLD),
+    )];
+    for track in &diff.tracks {
+        let name = file_name(&track.path);
+        match &track.track {
+            None => {
+                lines.push(Line::styled(
+                    format!("{} : no matching track", name),
+                    Style::default().fg(Color::Yellow),
+                ));
+                continue;
+            }
+            Some(_) if track.changes.is_empty() => continue,
+            Some(number) => lines.push(Line::raw(format!("{:>3}. {}", number, name))),
+        }
+        for change in &track.changes {
+            lines.push(Line::raw(format!(
+                "       {}: {} → {}",
+                change.field,
+                change.old.as_deref().unwrap_or("(none)"),
+                change.new
+            )));
+        }
+    }
+    if diff.change_count() == 0 && diff.unmatched_count() == 0 {
+        lines.push(Line::raw("Tags match the release"));
+    }
+
+    frame.render_widget(
+        Paragraph::new(lines)
+            .block(block)
+            .wrap(Wrap { trim: false }),
+        area,
+    );
+}
+
 fn render_error(frame: &mut Frame, message: &str) {
     let [header_area, main_area, footer_area] = vertical![==3, >=5, ==3].areas(frame.area());
 